@@ -0,0 +1,98 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::collapse::bpftrace::{Folder, Options};
+use inferno::collapse::{Collapse, DemangleOptions, DEFAULT_NTHREADS};
+use once_cell::sync::Lazy;
+
+static NTHREADS: Lazy<String> = Lazy::new(|| DEFAULT_NTHREADS.to_string());
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-collapse-bpftrace",
+    about,
+    after_help = "\
+[1] This processes the stack counts printed by bpftrace, as run with:
+        bpftrace -e 'profile:hz:99 { @[kstack, ustack, comm] = count(); }'
+    or by the BCC tools without their folded output option, such as:
+        profile -d 30
+        offcputime -d 30
+    "
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Annotate kernel functions with a `_[k]`
+    #[clap(long = "kernel")]
+    kernel: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Number of threads to use.
+    #[clap(
+        short = 'n',
+        long = "nthreads",
+        default_value = &**NTHREADS,
+        value_name = "UINT"
+    )]
+    nthreads: usize,
+
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    #[clap(value_name = "PATH")]
+    /// bpftrace or BCC output file, or STDIN if not specified
+    infile: Option<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Option<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.annotate_kernel = self.kernel;
+        options.nthreads = self.nthreads;
+        options.demangle = self.demangle;
+        (self.infile, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infile, options) = opt.into_parts();
+    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+}
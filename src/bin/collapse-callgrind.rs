@@ -0,0 +1,91 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::collapse::callgrind::{Event, Folder, Options};
+use inferno::collapse::{Collapse, DemangleOptions};
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-collapse-callgrind",
+    about,
+    after_help = "\
+[1] This processes the callgrind.out.* files written by valgrind --tool=callgrind.
+    callgrind does not record whole stacks, so they are rebuilt from the call
+    graph by splitting the cost of each function among its callers in
+    proportion to the cost of their calls. For example:
+
+    $ valgrind --tool=callgrind --callgrind-out-file=callgrind.out ./app
+    $ inferno-collapse-callgrind --event Ir callgrind.out > out.folded
+    "
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Event column to weight stacks by (e.g. Ir, Dr or cycles)
+    /// [default: the first event in the profile]
+    #[clap(long = "event", value_name = "EVENT")]
+    event: Option<String>,
+
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// callgrind output file, or STDIN if not specified
+    #[clap(value_name = "PATH")]
+    infile: Option<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Option<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.event = match self.event {
+            Some(event) => Event::Named(event),
+            None => Event::First,
+        };
+        options.demangle = self.demangle;
+        (self.infile, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infile, options) = opt.into_parts();
+    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+}
@@ -0,0 +1,94 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::collapse::chrome::{Folder, Options, Weight};
+use inferno::collapse::{Collapse, DemangleOptions};
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-collapse-chrome",
+    about,
+    after_help = "\
+[1] This processes CPU profiles from Chrome DevTools and Node.js, either as
+    .cpuprofile JSON or as trace-event JSON. For example:
+
+    $ node --cpu-prof --cpu-prof-name=out.cpuprofile app.js
+    $ inferno-collapse-chrome out.cpuprofile > out.folded
+    "
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Weight stacks by the time spent in them (in microseconds) rather than by sample count
+    #[clap(long = "time")]
+    time: bool,
+
+    /// Include the script URL and line number in frame names (e.g. render (app.js:12))
+    #[clap(long = "source")]
+    source: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Chrome profile or trace file, or STDIN if not specified
+    #[clap(value_name = "PATH")]
+    infile: Option<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Option<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.weight = if self.time {
+            Weight::Time
+        } else {
+            Weight::Samples
+        };
+        options.include_source = self.source;
+        options.demangle = self.demangle;
+        (self.infile, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infile, options) = opt.into_parts();
+    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+}
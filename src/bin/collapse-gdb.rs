@@ -0,0 +1,101 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::collapse::gdb::{Folder, Options};
+use inferno::collapse::{Collapse, DemangleOptions};
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-collapse-gdb",
+    about,
+    after_help = "\
+[1] This processes backtraces printed by gdb or eu-stack. Several dumps can be
+    concatenated to count identical stacks across them, for example:
+
+    $ for i in $(seq 60); do
+        gdb -p $pid -batch -ex \"thread apply all bt\" 2>/dev/null; sleep 1
+      done > stacks.txt
+    $ inferno-collapse-gdb stacks.txt > out.folded
+
+    or, with elfutils:
+
+    $ for i in $(seq 60); do eu-stack -p $pid; sleep 1; done > stacks.txt
+    "
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Keep function arguments (e.g. worker_loop (arg=0x0))
+    #[clap(long = "args")]
+    args: bool,
+
+    /// Keep source locations (e.g. worker_loop at worker.c:42)
+    #[clap(long = "source")]
+    source: bool,
+
+    /// Add a root frame for each thread (e.g. Thread 12347 (worker))
+    #[clap(long = "threads")]
+    threads: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// gdb or eu-stack output file, or STDIN if not specified
+    #[clap(value_name = "PATH")]
+    infile: Option<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Option<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.include_args = self.args;
+        options.include_source = self.source;
+        options.include_thread = self.threads;
+        options.demangle = self.demangle;
+        (self.infile, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infile, options) = opt.into_parts();
+    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+}
@@ -0,0 +1,96 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::collapse::gecko::{Folder, Options};
+use inferno::collapse::{Collapse, DemangleOptions};
+use regex::Regex;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-collapse-gecko",
+    about,
+    after_help = "\
+[1] This processes profiles in the processed format of the Firefox Profiler, as
+    written by samply, for example:
+
+    $ samply record --save-only -o profile.json ./target/release/myapp
+    $ inferno-collapse-gecko --thread '^myapp$' profile.json > out.folded
+    "
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Include the process name as the root frame of each stack
+    #[clap(long = "include-process")]
+    include_process: bool,
+
+    /// Include the thread name as the root frame of each stack (below the process name)
+    #[clap(long = "include-thread")]
+    include_thread: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Only include threads whose name matches this regular expression
+    #[clap(long = "thread", value_name = "REGEX")]
+    thread: Option<Regex>,
+
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Firefox Profiler JSON file, or STDIN if not specified
+    #[clap(value_name = "PATH")]
+    infile: Option<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Option<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.thread_name = self.thread;
+        options.include_process = self.include_process;
+        options.include_thread = self.include_thread;
+        options.demangle = self.demangle;
+        (self.infile, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infile, options) = opt.into_parts();
+    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+}
@@ -4,7 +4,7 @@ use std::path::PathBuf;
 use clap::{ArgAction, ArgGroup, Parser};
 use env_logger::Env;
 use inferno::collapse::ghcprof::{Folder, Options, Source};
-use inferno::collapse::Collapse;
+use inferno::collapse::{Collapse, DemangleOptions};
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -42,6 +42,20 @@ struct Opt {
     #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
     verbose: u8,
 
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -60,6 +74,7 @@ impl Opt {
         } else {
             Source::PercentTime
         };
+        options.demangle = self.demangle;
         (self.infile, options)
     }
 }
@@ -4,7 +4,7 @@ use std::path::PathBuf;
 use clap::{ArgAction, Parser};
 use env_logger::Env;
 use inferno::collapse::guess::{Folder, Options};
-use inferno::collapse::{Collapse, DEFAULT_NTHREADS};
+use inferno::collapse::{Collapse, DemangleOptions, DEFAULT_NTHREADS};
 use once_cell::sync::Lazy;
 
 static NTHREADS: Lazy<String> = Lazy::new(|| DEFAULT_NTHREADS.to_string());
@@ -41,6 +41,17 @@ struct Opt {
     )]
     nthreads: usize,
 
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -53,6 +64,7 @@ impl Opt {
     fn into_parts(self) -> (Option<PathBuf>, Options) {
         let mut options = Options::default();
         options.nthreads = self.nthreads;
+        options.demangle = self.demangle;
         (self.infile, options)
     }
 }
@@ -0,0 +1,97 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::collapse::jstack::{Folder, Options, ThreadState};
+use inferno::collapse::{Collapse, DemangleOptions};
+use regex::Regex;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-collapse-jstack",
+    about,
+    after_help = "\
+[1] This processes Java thread dumps, as printed by jstack or jcmd Thread.print.
+    Several dumps can be concatenated to count identical stacks across them,
+    for example:
+
+    $ for i in $(seq 60); do jstack $pid; sleep 1; done > dumps.txt
+    $ inferno-collapse-jstack --state runnable dumps.txt > out.folded
+    "
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Include the thread state as a leaf frame (e.g. [WAITING])
+    #[clap(long = "include-state")]
+    include_state: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Only include threads in this state (e.g. runnable or timed_waiting); may be repeated
+    #[clap(long = "state", value_name = "STATE")]
+    states: Vec<ThreadState>,
+
+    /// Only include threads whose name matches this regular expression
+    #[clap(long = "thread", value_name = "REGEX")]
+    thread: Option<Regex>,
+
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// jstack output file, or STDIN if not specified
+    #[clap(value_name = "PATH")]
+    infile: Option<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Option<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.thread_states = self.states;
+        options.thread_name = self.thread;
+        options.include_state = self.include_state;
+        options.demangle = self.demangle;
+        (self.infile, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infile, options) = opt.into_parts();
+    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+}
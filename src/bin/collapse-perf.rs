@@ -4,7 +4,7 @@ use std::path::PathBuf;
 use clap::{ArgAction, Parser};
 use env_logger::Env;
 use inferno::collapse::perf::{Folder, Options};
-use inferno::collapse::{Collapse, DEFAULT_NTHREADS};
+use inferno::collapse::{Collapse, DemangleOptions, DEFAULT_NTHREADS};
 use once_cell::sync::Lazy;
 
 static NTHREADS: Lazy<String> = Lazy::new(|| DEFAULT_NTHREADS.to_string());
@@ -32,6 +32,10 @@ struct Opt {
     #[clap(long = "all")]
     all: bool,
 
+    /// Count all event types, each in its own column of multi-metric output
+    #[clap(long = "all-events", conflicts_with = "event_filter")]
+    all_events: bool,
+
     /// Annotate jit functions with a `_[j]`
     #[clap(long = "jit")]
     jit: bool,
@@ -72,6 +76,27 @@ struct Opt {
     )]
     nthreads: usize,
 
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
+    /// Write the stacks of each event type to a file of its own, named by replacing {event} in
+    /// TEMPLATE with the event type (e.g. out.{event}.folded)
+    #[clap(
+        long = "split-events",
+        value_name = "TEMPLATE",
+        conflicts_with = "event_filter",
+        conflicts_with = "all_events"
+    )]
+    split_events: Option<String>,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -87,7 +112,7 @@ struct Opt {
 }
 
 impl Opt {
-    fn into_parts(self) -> (Option<PathBuf>, Options) {
+    fn into_parts(self) -> (Option<PathBuf>, Option<String>, Options) {
         let mut options = Options::default();
         options.include_pid = self.pid;
         options.include_tid = self.tid;
@@ -95,9 +120,11 @@ impl Opt {
         options.annotate_jit = self.jit || self.all;
         options.annotate_kernel = self.kernel || self.all;
         options.event_filter = self.event_filter;
+        options.all_events = self.all_events;
         options.nthreads = self.nthreads;
         options.skip_after = self.skip_after;
-        (self.infile, options)
+        options.demangle = self.demangle;
+        (self.infile, self.split_events, options)
     }
 }
 
@@ -116,6 +143,9 @@ fn main() -> io::Result<()> {
         .init();
     }
 
-    let (infile, options) = opt.into_parts();
-    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+    let (infile, split_events, options) = opt.into_parts();
+    match split_events {
+        Some(template) => Folder::from(options).collapse_file_per_event(infile.as_ref(), &template),
+        None => Folder::from(options).collapse_file_to_stdout(infile.as_ref()),
+    }
 }
@@ -0,0 +1,98 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::collapse::pprof::{Folder, Options, SampleIndex};
+use inferno::collapse::{Collapse, DemangleOptions};
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-collapse-pprof",
+    about,
+    after_help = "\
+[1] This processes pprof protobuf profiles, such as those written by Go's
+    runtime/pprof package. Both gzip-compressed and uncompressed profiles
+    are supported. For example:
+
+    $ curl -o cpu.pprof http://localhost:6060/debug/pprof/profile
+    $ inferno-collapse-pprof cpu.pprof > out.folded
+    "
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Include raw addresses of unsymbolized locations (e.g. [libc.so.6 <0x7f0012345678>])
+    #[clap(long = "addrs")]
+    addrs: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Sample value to use as stack weight, by index or name (e.g. 0 or alloc_space)
+    /// [default: the profile's default sample type]
+    #[clap(long = "sample-index", value_name = "INDEX|NAME")]
+    sample_index: Option<String>,
+
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// pprof profile, or STDIN if not specified
+    #[clap(value_name = "PATH")]
+    infile: Option<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Option<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.sample_index = match self.sample_index {
+            None => SampleIndex::Default,
+            Some(index) => match index.parse() {
+                Ok(index) => SampleIndex::Index(index),
+                Err(_) => SampleIndex::Name(index),
+            },
+        };
+        options.include_addrs = self.addrs;
+        options.demangle = self.demangle;
+        (self.infile, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infile, options) = opt.into_parts();
+    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
+}
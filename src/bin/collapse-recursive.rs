@@ -3,7 +3,7 @@ use std::path::PathBuf;
 
 use clap::Parser;
 use inferno::collapse::recursive::{Folder, Options};
-use inferno::collapse::{Collapse, DEFAULT_NTHREADS};
+use inferno::collapse::{Collapse, DemangleOptions, DEFAULT_NTHREADS};
 use once_cell::sync::Lazy;
 
 static NTHREADS: Lazy<String> = Lazy::new(|| DEFAULT_NTHREADS.to_string());
@@ -20,6 +20,17 @@ struct Opt {
     )]
     nthreads: usize,
 
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
     #[clap(value_name = "PATH")]
     /// Collapse output file, or STDIN if not specified
     infile: Option<PathBuf>,
@@ -29,6 +40,7 @@ impl Opt {
     fn into_parts(self) -> (Option<PathBuf>, Options) {
         let mut options = Options::default();
         options.nthreads = self.nthreads;
+        options.demangle = self.demangle;
         (self.infile, options)
     }
 }
@@ -4,7 +4,7 @@ use std::path::PathBuf;
 use clap::{ArgAction, Parser};
 use env_logger::Env;
 use inferno::collapse::sample::{Folder, Options};
-use inferno::collapse::Collapse;
+use inferno::collapse::{Collapse, DemangleOptions};
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -30,6 +30,20 @@ struct Opt {
     #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
     verbose: u8,
 
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -42,6 +56,7 @@ impl Opt {
     fn into_parts(self) -> (Option<PathBuf>, Options) {
         let mut options = Options::default();
         options.no_modules = self.no_modules;
+        options.demangle = self.demangle;
         (self.infile, options)
     }
 }
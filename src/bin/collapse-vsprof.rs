@@ -3,8 +3,8 @@ use std::path::PathBuf;
 
 use clap::{ArgAction, Parser};
 use env_logger::Env;
-use inferno::collapse::vsprof::Folder;
-use inferno::collapse::Collapse;
+use inferno::collapse::vsprof::{Folder, Options};
+use inferno::collapse::{Collapse, DemangleOptions};
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -25,6 +25,20 @@ struct Opt {
     #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
     verbose: u8,
 
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Demangle function names, optionally with a comma-separated list of strip-hash,
+    /// strip-generics and simplify-closures
+    #[clap(
+        long = "demangle",
+        value_name = "OPTIONS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    demangle: Option<DemangleOptions>,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -33,6 +47,14 @@ struct Opt {
     infile: Option<PathBuf>,
 }
 
+impl Opt {
+    fn into_parts(self) -> (Option<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.demangle = self.demangle;
+        (self.infile, options)
+    }
+}
+
 fn main() -> io::Result<()> {
     let opt = Opt::parse();
 
@@ -48,5 +70,6 @@ fn main() -> io::Result<()> {
         .init();
     }
 
-    Folder::default().collapse_file_to_stdout(opt.infile)
+    let (infile, options) = opt.into_parts();
+    Folder::from(options).collapse_file_to_stdout(infile.as_ref())
 }
@@ -0,0 +1,105 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::filter::{self, Options};
+use is_terminal::IsTerminal;
+use regex::Regex;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-filter",
+    about,
+    after_help = "\
+Keeps, drops and truncates folded stacks by regular expressions.
+
+  $ inferno-filter --drop '^swapper;' --drop-frames '^\\[unknown\\]$' stacks.folded
+
+Stacks are kept or dropped first, as a whole, and then their frames are
+dropped and truncated. Stacks that end up identical are merged. Both
+counts of differential input from inferno-diff-folded are kept."
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Only keep stacks that match this regular expression; may be repeated
+    #[clap(long = "keep", value_name = "REGEX")]
+    keep: Vec<Regex>,
+
+    /// Drop stacks that match this regular expression; may be repeated
+    #[clap(long = "drop", value_name = "REGEX")]
+    drop: Vec<Regex>,
+
+    /// Drop frames whose name matches this regular expression; may be repeated
+    #[clap(long = "drop-frames", value_name = "REGEX")]
+    drop_frames: Vec<Regex>,
+
+    /// Make the innermost frame that matches this regular expression the root of its stack
+    #[clap(long = "root-at", value_name = "REGEX")]
+    root_at: Option<Regex>,
+
+    /// Make the outermost frame that matches this regular expression the leaf of its stack
+    #[clap(long = "leaf-at", value_name = "REGEX")]
+    leaf_at: Option<Regex>,
+
+    /// Only keep this many frames of each stack, starting at the root
+    #[clap(long = "max-depth", value_name = "UINT")]
+    max_depth: Option<usize>,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Collapsed perf output files. With no PATH, or PATH is -, read STDIN.
+    #[clap(name = "PATH", value_parser)]
+    infiles: Vec<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Vec<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.keep = self.keep;
+        options.drop = self.drop;
+        options.drop_frames = self.drop_frames;
+        options.root_at = self.root_at;
+        options.leaf_at = self.leaf_at;
+        options.max_depth = self.max_depth;
+        (self.infiles, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infiles, options) = opt.into_parts();
+
+    if std::io::stdout().is_terminal() {
+        filter::from_files(&options, &infiles, io::stdout().lock())
+    } else {
+        filter::from_files(&options, &infiles, io::BufWriter::new(io::stdout().lock()))
+    }
+}
@@ -7,11 +7,15 @@ use env_logger::Env;
 use inferno::flamegraph::color::{
     parse_hex_color, BackgroundColor, Color, PaletteMap, SearchColor, StrokeColor,
 };
-use inferno::flamegraph::{self, defaults, Direction, Options, Palette, TextTruncateDirection};
+use inferno::flamegraph::{
+    self, defaults, CountFormat, Direction, Options, OutputFormat, Palette, Ratio, RerootMatch,
+    TermColors, TextTruncateDirection,
+};
 
 #[cfg(feature = "nameattr")]
 use inferno::flamegraph::FuncFrameAttrsMap;
 use is_terminal::IsTerminal;
+use regex::Regex;
 
 #[derive(Debug, Parser)]
 #[clap(name = "inferno-flamegraph", about)]
@@ -110,6 +114,39 @@ struct Opt {
     )]
     factor: f64,
 
+    /// How to format sample counts: auto (as many decimal places as they have), integer, decimal
+    /// (two decimal places) or decimal:N (N decimal places)
+    #[clap(long = "count-format", default_value = "auto", value_name = "STRING")]
+    count_format: CountFormat,
+
+    /// The metric of multi-metric input that sets the widths of frames [default: the first
+    /// metric]
+    #[clap(long = "metric", value_name = "STRING")]
+    metric: Option<String>,
+
+    /// A ratio between two metrics of multi-metric input to show in tooltips, such as
+    /// IPC=instructions/cycles; may be repeated
+    #[clap(long = "ratio", value_name = "NAME=METRIC/METRIC")]
+    ratios: Vec<Ratio>,
+
+    /// Output format
+    #[clap(
+        long = "format",
+        default_value = "svg",
+        value_parser = clap::builder::PossibleValuesParser::new(OutputFormat::VARIANTS).map(|s| s.parse::<OutputFormat>().unwrap()),
+        value_name = "STRING"
+    )]
+    format: OutputFormat,
+
+    /// Colors of the terminal, for --format term [default: truecolor if $COLORTERM says so, 256
+    /// otherwise]
+    #[clap(
+        long = "term-colors",
+        value_parser = clap::builder::PossibleValuesParser::new(TermColors::VARIANTS).map(|s| s.parse::<TermColors>().unwrap()),
+        value_name = "STRING"
+    )]
+    term_colors: Option<TermColors>,
+
     /// Font size
     #[clap(
         long = "fontsize",
@@ -220,6 +257,35 @@ struct Opt {
     #[clap(long = "base", value_name = "STRING")]
     base: Vec<String>,
 
+    /// Omit samples whose stacks have no frame that matches this regular expression, and cut the
+    /// frames above the matching frame, so that all calls of the matching functions are merged no
+    /// matter where they were called from
+    #[clap(
+        long = "reroot",
+        value_name = "REGEX",
+        value_parser = |s: &str| Regex::new(s).map(|_| s.to_string())
+    )]
+    reroot: Option<String>,
+
+    /// Which match to re-root stacks at when several frames match --reroot
+    #[clap(
+        long = "reroot-match",
+        default_value = "first",
+        value_parser = clap::builder::PossibleValuesParser::new(RerootMatch::VARIANTS).map(|s| s.parse::<RerootMatch>().unwrap()),
+        value_name = "STRING"
+    )]
+    reroot_match: RerootMatch,
+
+    /// Show the merged callers of this function above it and its merged callees below it, for the
+    /// samples whose stacks contain it
+    #[clap(
+        long = "butterfly",
+        value_name = "STRING",
+        conflicts_with = "reverse",
+        conflicts_with = "flame_chart"
+    )]
+    butterfly: Option<String>,
+
     // ************ //
     // *** ARGS *** //
     // ************ //
@@ -258,21 +324,46 @@ impl<'a> Opt {
         }
         options.negate_differentials = self.negate;
         options.factor = self.factor;
+        options.count_format = self.count_format;
+        options.metric = self.metric;
+        options.ratios = self.ratios;
         options.pretty_xml = self.pretty_xml;
+        options.format = self.format;
         options.no_sort = self.no_sort;
         options.no_javascript = self.no_javascript;
         options.color_diffusion = self.color_diffusion;
         options.reverse_stack_order = self.reverse;
         options.flame_chart = self.flame_chart;
         options.base = self.base;
+        options.reroot = self.reroot;
+        options.reroot_match = self.reroot_match;
 
         if self.flame_chart && self.title == defaults::TITLE {
             options.title = defaults::CHART_TITLE.to_owned();
         }
+        if let Some(function) = &self.butterfly {
+            if self.title == defaults::TITLE {
+                options.title = format!("Butterfly Graph: {}", function);
+            }
+        }
+        options.butterfly = self.butterfly;
 
         // set style options
         options.subtitle = self.subtitle;
         options.image_width = self.width;
+        if self.format == OutputFormat::Term {
+            // Fill the terminal, and use as many colors as it says it supports.
+            if options.image_width.is_none() {
+                options.image_width =
+                    terminal_size::terminal_size().map(|(width, _)| usize::from(width.0));
+            }
+            options.term_colors =
+                self.term_colors
+                    .unwrap_or_else(|| match std::env::var("COLORTERM").as_deref() {
+                        Ok("truecolor" | "24bit") => TermColors::TrueColor,
+                        _ => TermColors::Ansi256,
+                    });
+        }
         options.frame_height = self.height;
         options.min_width = self.minwidth;
         options.font_type = self.fonttype;
@@ -376,7 +467,10 @@ fn save_consistent_palette_if_needed(
 mod tests {
     use super::Opt;
     use clap::Parser;
-    use inferno::flamegraph::{color, Direction, Options, Palette, TextTruncateDirection};
+    use inferno::flamegraph::{
+        color, CountFormat, Direction, Options, OutputFormat, Palette, Ratio, RerootMatch,
+        TextTruncateDirection,
+    };
     use pretty_assertions::assert_eq;
     use std::path::PathBuf;
     use std::str::FromStr;
@@ -428,8 +522,22 @@ mod tests {
             "--negate",
             "--factor",
             "0.1",
+            "--count-format",
+            "decimal:3",
+            "--metric",
+            "instructions",
+            "--ratio",
+            "IPC=instructions/cycles",
             "--pretty-xml",
+            "--format",
+            "json",
+            "--term-colors",
+            "256",
             "--reverse",
+            "--reroot",
+            "::poll$",
+            "--reroot-match",
+            "last",
             "--no-javascript",
             "test_infile1",
             "test_infile2",
@@ -450,6 +558,9 @@ mod tests {
         expected_options.count_name = "test count name".to_string();
         expected_options.name_type = "test name type".to_string();
         expected_options.factor = 0.1;
+        expected_options.count_format = CountFormat::Decimal(3);
+        expected_options.metric = Some("instructions".to_string());
+        expected_options.ratios = vec![Ratio::from_str("IPC=instructions/cycles").unwrap()];
         expected_options.notes = "Test notes".to_string();
         expected_options.subtitle = Some("Test Subtitle".to_string());
         expected_options.bgcolors = Some(color::BackgroundColor::Blue);
@@ -457,8 +568,11 @@ mod tests {
         expected_options.direction = Direction::Inverted;
         expected_options.negate_differentials = true;
         expected_options.pretty_xml = true;
+        expected_options.format = OutputFormat::Json;
         expected_options.no_sort = false;
         expected_options.reverse_stack_order = true;
+        expected_options.reroot = Some("::poll$".to_string());
+        expected_options.reroot_match = RerootMatch::Last;
         expected_options.no_javascript = true;
         expected_options.color_diffusion = false;
 
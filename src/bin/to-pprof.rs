@@ -0,0 +1,84 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::export::pprof::{self, Options};
+use is_terminal::IsTerminal;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-to-pprof",
+    about,
+    after_help = "\
+Converts folded stacks into a gzip-compressed pprof profile (profile.proto)
+that can be read by go tool pprof and profile storage backends.
+
+  $ inferno-collapse-perf perf.data.txt | inferno-to-pprof > profile.pb.gz
+
+Differential input from inferno-diff-folded gets two values per sample, the
+first one of which is named after the sample type with a _before suffix."
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// What the sample counts measure
+    #[clap(long = "sample-type", default_value = "samples", value_name = "STRING")]
+    sample_type: String,
+
+    /// Unit of the sample counts
+    #[clap(long = "countname", default_value = "count", value_name = "STRING")]
+    countname: String,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Collapsed perf output files. With no PATH, or PATH is -, read STDIN.
+    #[clap(name = "PATH", value_parser)]
+    infiles: Vec<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Vec<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.sample_type = self.sample_type;
+        options.count_name = self.countname;
+        (self.infiles, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infiles, options) = opt.into_parts();
+
+    if std::io::stdout().is_terminal() {
+        pprof::from_files(&options, &infiles, io::stdout().lock())
+    } else {
+        pprof::from_files(&options, &infiles, io::BufWriter::new(io::stdout().lock()))
+    }
+}
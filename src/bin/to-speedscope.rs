@@ -0,0 +1,102 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::builder::TypedValueParser;
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::export::speedscope::{self, Options, Unit};
+use is_terminal::IsTerminal;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-to-speedscope",
+    about,
+    after_help = "\
+Converts folded stacks into a speedscope file that can be opened at
+https://www.speedscope.app.
+
+  $ inferno-to-speedscope stacks.folded > profile.speedscope.json
+
+Differential input from inferno-diff-folded becomes two profiles, one
+for each set of sample counts."
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Keep the stacks in input order, so speedscope shows them as a timeline
+    #[clap(long = "flamechart")]
+    flame_chart: bool,
+
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Name of the profile [default: name of the input file]
+    #[clap(long = "name", value_name = "STRING")]
+    name: Option<String>,
+
+    /// Unit of the sample counts
+    #[clap(
+        long = "unit",
+        value_name = "STRING",
+        default_value = "none",
+        value_parser = clap::builder::PossibleValuesParser::new(Unit::VARIANTS).map(|s| s.parse::<Unit>().unwrap())
+    )]
+    unit: Unit,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Collapsed perf output files. With no PATH, or PATH is -, read STDIN.
+    #[clap(name = "PATH", value_parser)]
+    infiles: Vec<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Vec<PathBuf>, Options) {
+        let name = self.name.or_else(|| match &*self.infiles {
+            [path] if path.to_str() != Some("-") => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            _ => None,
+        });
+
+        let mut options = Options::default();
+        options.name = name;
+        options.unit = self.unit;
+        options.flame_chart = self.flame_chart;
+        (self.infiles, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infiles, options) = opt.into_parts();
+
+    if std::io::stdout().is_terminal() {
+        speedscope::from_files(&options, &infiles, io::stdout().lock())
+    } else {
+        speedscope::from_files(&options, &infiles, io::BufWriter::new(io::stdout().lock()))
+    }
+}
@@ -0,0 +1,106 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::builder::TypedValueParser;
+use clap::{ArgAction, Parser};
+use env_logger::Env;
+use inferno::report::{self, Options, ReportFormat, SortBy};
+use is_terminal::IsTerminal;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "inferno-top",
+    about,
+    after_help = "\
+Lists the functions with the most samples in folded stacks.
+
+  $ inferno-top --sort total -n 10 stacks.folded
+
+Self samples are those in which a function is the leaf frame, total
+samples those in which it is anywhere on the stack. Recursive functions
+count once per sample. Differential input from inferno-diff-folded also
+shows how each count changed."
+)]
+struct Opt {
+    // ************* //
+    // *** FLAGS *** //
+    // ************* //
+    /// Silence all log output
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Verbose logging mode (-v, -vv, -vvv)
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    // *************** //
+    // *** OPTIONS *** //
+    // *************** //
+    /// Count to sort the functions by
+    #[clap(
+        long = "sort",
+        value_name = "STRING",
+        default_value = "self",
+        value_parser = clap::builder::PossibleValuesParser::new(SortBy::VARIANTS).map(|s| s.parse::<SortBy>().unwrap())
+    )]
+    sort_by: SortBy,
+
+    /// Number of functions to list, or 0 for all of them
+    #[clap(short = 'n', long = "limit", value_name = "UINT", default_value = "20")]
+    limit: usize,
+
+    /// Format of the report
+    #[clap(
+        long = "format",
+        value_name = "STRING",
+        default_value = "table",
+        value_parser = clap::builder::PossibleValuesParser::new(ReportFormat::VARIANTS).map(|s| s.parse::<ReportFormat>().unwrap())
+    )]
+    format: ReportFormat,
+
+    /// Count type label for the summary line
+    #[clap(long = "countname", value_name = "STRING", default_value = "samples")]
+    count_name: String,
+
+    // ************ //
+    // *** ARGS *** //
+    // ************ //
+    /// Collapsed perf output files. With no PATH, or PATH is -, read STDIN.
+    #[clap(name = "PATH", value_parser)]
+    infiles: Vec<PathBuf>,
+}
+
+impl Opt {
+    fn into_parts(self) -> (Vec<PathBuf>, Options) {
+        let mut options = Options::default();
+        options.sort_by = self.sort_by;
+        options.limit = (self.limit > 0).then_some(self.limit);
+        options.format = self.format;
+        options.count_name = self.count_name;
+        (self.infiles, options)
+    }
+}
+
+fn main() -> io::Result<()> {
+    let opt = Opt::parse();
+
+    // Initialize logger
+    if !opt.quiet {
+        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }))
+        .format_timestamp(None)
+        .init();
+    }
+
+    let (infiles, options) = opt.into_parts();
+
+    if std::io::stdout().is_terminal() {
+        report::from_files(&options, &infiles, io::stdout().lock())
+    } else {
+        report::from_files(&options, &infiles, io::BufWriter::new(io::stdout().lock()))
+    }
+}
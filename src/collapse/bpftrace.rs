@@ -0,0 +1,455 @@
+use std::io::{self, BufRead};
+
+use log::warn;
+
+use crate::collapse::common::{self, DemangleOptions, Occurrences, StackParser};
+use crate::collapse::matcher::is_kernel;
+
+/// `bpftrace` folder configuration options.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Options {
+    /// Annotate kernel functions with a `_[k]` suffix.
+    ///
+    /// Default is `false`.
+    pub annotate_kernel: bool,
+
+    /// The number of threads to use.
+    ///
+    /// Default is the number of logical cores on your machine.
+    pub nthreads: usize,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            annotate_kernel: false,
+            nthreads: *common::DEFAULT_NTHREADS,
+            demangle: None,
+        }
+    }
+}
+
+/// A stack collapser for stack counts printed by `bpftrace` and the BCC tools.
+///
+/// This handles the maps printed by `bpftrace` at exit, such as those of
+/// `bpftrace -e 'profile:hz:99 { @[kstack, ustack, comm] = count(); }'`, as well as the
+/// multi-line (non-folded) output of BCC's `profile` and `offcputime`.
+///
+/// Other values in a map key (e.g. `comm` or `pid`) become root frames, in the order in which
+/// they appear in the key. If a key holds two stacks, the first one is taken to be the kernel
+/// stack, as in `@[kstack, ustack]`. The BCC tools print the kernel stack first, and separate
+/// it from the user stack with `--` when asked to. Other than those, only frames whose module
+/// is known (as in `bpftrace`'s perf mode) can be annotated with `_[k]`.
+///
+/// To construct one, either use `bpftrace::Folder::default()` or create an [`Options`] and use
+/// `bpftrace::Folder::from(options)`.
+pub struct Folder {
+    /// The number of stacks per job to send to the threadpool.
+    nstacks_per_job: usize,
+
+    /// Whether we are in the middle of a multi-line `bpftrace` map key.
+    in_key: bool,
+
+    /// Frames of the current sample, leaf first, and whether their module is the kernel.
+    frames: Vec<(String, bool)>,
+
+    /// The number of frames before the delimiter between the kernel and the user stack, if any.
+    delimiter: Option<usize>,
+
+    /// Root frames of the current sample (the other values of a map key, or the process name).
+    roots: Vec<String>,
+
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(mut opt: Options) -> Self {
+        if opt.nthreads == 0 {
+            opt.nthreads = 1;
+        }
+        Self {
+            nstacks_per_job: common::DEFAULT_NSTACKS_PER_JOB,
+            in_key: false,
+            frames: Vec::new(),
+            delimiter: None,
+            roots: Vec::new(),
+            opt,
+        }
+    }
+}
+
+impl Default for Folder {
+    fn default() -> Self {
+        Options::default().into()
+    }
+}
+
+impl StackParser for Folder {
+    fn pre_process<R>(&mut self, _: &mut R, _: &mut Occurrences) -> io::Result<()>
+    where
+        R: io::BufRead,
+    {
+        // Headers such as "Attaching 1 probe..." are skipped along with the other lines
+        // outside of stacks.
+        Ok(())
+    }
+
+    fn collapse_single_threaded<R>(
+        &mut self,
+        mut reader: R,
+        occurrences: &mut Occurrences,
+    ) -> io::Result<()>
+    where
+        R: io::BufRead,
+    {
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let s = String::from_utf8_lossy(&line);
+            self.on_line(s.trim_end(), occurrences);
+        }
+
+        if self.in_key || !self.frames.is_empty() || !self.roots.is_empty() {
+            warn!("File ended in the middle of a stack");
+            self.reset();
+        }
+        Ok(())
+    }
+
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        let mut input = input.as_bytes();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if let Ok(n) = input.read_line(&mut line) {
+                if n == 0 {
+                    break;
+                }
+            } else {
+                return Some(false);
+            }
+
+            let line = line.trim();
+            if line.starts_with("Attaching ") && line.contains(" probe") {
+                return Some(true);
+            }
+            if line.starts_with('@') && line.contains('[') {
+                return Some(true);
+            }
+            if parse_process(line).is_some() {
+                return Some(true);
+            }
+        }
+        None
+    }
+
+    fn would_end_stack(&mut self, line: &[u8]) -> bool {
+        // The end of a `bpftrace` map key, e.g. "@[bash]: 4" or ", bash]: 4".
+        if line.first().is_some_and(|b| !b.is_ascii_whitespace()) {
+            return line.windows(2).any(|w| w == b"]:");
+        }
+        // The count of a BCC stack, e.g. "        4".
+        let mut digits = line.iter().filter(|b| !b.is_ascii_whitespace()).peekable();
+        digits.peek().is_some() && digits.all(u8::is_ascii_digit)
+    }
+
+    fn clone_and_reset_stack_context(&self) -> Self {
+        Self {
+            nstacks_per_job: self.nstacks_per_job,
+            in_key: false,
+            frames: Vec::new(),
+            delimiter: None,
+            roots: Vec::new(),
+            opt: self.opt.clone(),
+        }
+    }
+
+    fn nstacks_per_job(&self) -> usize {
+        self.nstacks_per_job
+    }
+
+    fn set_nstacks_per_job(&mut self, n: usize) {
+        self.nstacks_per_job = n;
+    }
+
+    fn nthreads(&self) -> usize {
+        self.opt.nthreads
+    }
+
+    fn set_nthreads(&mut self, n: usize) {
+        self.opt.nthreads = n;
+    }
+
+    fn demangle_options(&self) -> Option<DemangleOptions> {
+        self.opt.demangle
+    }
+}
+
+impl Folder {
+    fn on_line(&mut self, line: &str, occurrences: &mut Occurrences) {
+        if self.in_key {
+            self.on_key_line(line, occurrences);
+        } else if let Some(key) = line.strip_prefix('@') {
+            // Maps without a key (e.g. "@: 4") have no stack.
+            if let Some(i) = key.find('[') {
+                self.in_key = true;
+                self.on_key_line(&key[i + 1..], occurrences);
+            }
+        } else if line.starts_with(char::is_whitespace) {
+            self.on_bcc_line(line.trim(), occurrences);
+        }
+        // Anything else is a header or a message, e.g. "Attaching 1 probe..." or "^C".
+    }
+
+    // Handle the lines of a `bpftrace` map key, which look like this:
+    //
+    // @[
+    //     native_safe_halt+14
+    //     default_idle+10
+    // ,
+    //     __GI___poll+79
+    //     main+20
+    // , bash]: 4
+    //
+    // The first line has already been stripped of its "@[".
+    fn on_key_line(&mut self, line: &str, occurrences: &mut Occurrences) {
+        if line.starts_with(char::is_whitespace) && !line.trim().is_empty() {
+            self.push_frame(line.trim());
+            return;
+        }
+
+        let (values, count) = match line.rfind("]:") {
+            Some(i) => (&line[..i], Some(line[i + 2..].trim())),
+            None => (line, None),
+        };
+        // A line with just a comma separates two stacks, as opposed to e.g. ", bash]: 4".
+        if values.trim() == "," {
+            self.delimit();
+        }
+        self.roots.extend(
+            values
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|v| v.replace(';', ":")),
+        );
+
+        if let Some(count) = count {
+            self.in_key = false;
+            match count.parse::<u64>() {
+                Ok(count) => self.on_stack_end(count, occurrences),
+                Err(_) => {
+                    warn!("Ignoring map value that is not a count: {}", count);
+                    self.reset();
+                }
+            }
+        }
+    }
+
+    // Handle the lines of a BCC stack, which look like this:
+    //
+    //     native_safe_halt
+    //     default_idle
+    //     --
+    //     __GI___poll
+    //     main
+    //     -                bash (1234)
+    //         4
+    fn on_bcc_line(&mut self, line: &str, occurrences: &mut Occurrences) {
+        if line == "--" {
+            self.delimit();
+        } else if let Some(comm) = parse_process(line) {
+            self.roots.push(comm.replace(';', ":"));
+        } else if let Ok(count) = line.parse::<u64>() {
+            self.on_stack_end(count, occurrences);
+        } else {
+            self.push_frame(line);
+        }
+    }
+
+    fn push_frame(&mut self, line: &str) {
+        let (func, module) = parse_frame(line);
+        let kernel = module.is_some_and(is_kernel);
+        self.frames.push((func.replace(';', ":"), kernel));
+    }
+
+    /// Mark the frames so far as the kernel stack, unless that has been done already.
+    fn delimit(&mut self) {
+        if self.delimiter.is_none() {
+            self.delimiter = Some(self.frames.len());
+        }
+    }
+
+    fn on_stack_end(&mut self, count: u64, occurrences: &mut Occurrences) {
+        let nkernel = self.delimiter.unwrap_or(0);
+
+        let mut stack = String::with_capacity(
+            self.roots.iter().fold(0, |a, s| a + s.len() + 1)
+                + self.frames.iter().fold(0, |a, (s, _)| a + s.len() + 5),
+        );
+        for root in self.roots.drain(..) {
+            if !stack.is_empty() {
+                stack.push(';');
+            }
+            stack.push_str(&root);
+        }
+        // Frames are listed leaf first.
+        for (i, (func, kernel)) in self.frames.drain(..).enumerate().rev() {
+            if !stack.is_empty() {
+                stack.push(';');
+            }
+            stack.push_str(&func);
+            if self.opt.annotate_kernel && (kernel || i < nkernel) && !func.ends_with("_[k]") {
+                stack.push_str("_[k]");
+            }
+        }
+
+        if !stack.is_empty() {
+            occurrences.insert_or_add(stack, count.into());
+        }
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.in_key = false;
+        self.frames.clear();
+        self.delimiter = None;
+        self.roots.clear();
+    }
+}
+
+/// Parse the process line of a BCC stack (e.g. `-                bash (1234)`), returning the
+/// process name.
+fn parse_process(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix('-')?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    match rest.rfind(" (") {
+        Some(i) if rest.ends_with(')') => Some(&rest[..i]),
+        _ => Some(rest),
+    }
+}
+
+/// Split a frame into its function, without offset, and its module, if any.
+///
+/// Frames look like `native_safe_halt+14`, `__poll`, `0x7f1e2efc1b6f`, or, in `bpftrace`'s perf
+/// mode, `ffffffff8103ce3b native_safe_halt+14 ([kernel.kallsyms])`.
+fn parse_frame(line: &str) -> (&str, Option<&str>) {
+    let mut func = line;
+    if let Some((addr, rest)) = func.split_once(' ') {
+        if addr.bytes().all(|b| b.is_ascii_hexdigit()) {
+            func = rest.trim_start();
+        }
+    }
+
+    let mut module = None;
+    if func.ends_with(')') {
+        if let Some(i) = func.rfind(" (") {
+            let m = &func[i + 2..func.len() - 1];
+            if m.starts_with('/') || m.starts_with('[') {
+                module = Some(m);
+                func = &func[..i];
+            }
+        }
+    }
+
+    if func.starts_with("0x") {
+        return ("[unknown]", module);
+    }
+    if let Some(i) = func.rfind('+') {
+        let offset = &func[i + 1..];
+        let offset = offset.strip_prefix("0x").unwrap_or(offset);
+        if !offset.is_empty() && offset.bytes().all(|b| b.is_ascii_hexdigit()) {
+            func = &func[..i];
+        }
+    }
+    (func, module)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use once_cell::sync::Lazy;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::collapse::common;
+    use crate::collapse::Collapse;
+
+    static INPUT: Lazy<Vec<PathBuf>> = Lazy::new(|| {
+        [
+            "./tests/data/collapse-bpftrace/bpftrace-kstack-ustack.txt",
+            "./tests/data/collapse-bpftrace/bpftrace-perf-mode.txt",
+            "./tests/data/collapse-bpftrace/bcc-profile.txt",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>()
+    });
+
+    #[test]
+    fn frames() {
+        assert_eq!(
+            parse_frame("native_safe_halt+14"),
+            ("native_safe_halt", None)
+        );
+        assert_eq!(parse_frame("operator+"), ("operator+", None));
+        assert_eq!(parse_frame("0x7f1e2efc1b6f"), ("[unknown]", None));
+        assert_eq!(
+            parse_frame("ffffffff8103ce3b native_safe_halt+14 ([kernel.kallsyms])"),
+            ("native_safe_halt", Some("[kernel.kallsyms]"))
+        );
+        assert_eq!(
+            parse_frame("7f1e2efc1b6f __poll+0x4f (/usr/lib/libc.so.6)"),
+            ("__poll", Some("/usr/lib/libc.so.6"))
+        );
+        assert_eq!(parse_process("-                bash (1234)"), Some("bash"));
+        assert_eq!(parse_process("--"), None);
+    }
+
+    #[test]
+    fn test_collapse_multi_bpftrace() -> io::Result<()> {
+        let mut folder = Folder {
+            nstacks_per_job: 1,
+            opt: Options {
+                annotate_kernel: true,
+                ..Options::default()
+            },
+            ..Folder::default()
+        };
+        common::testing::test_collapse_multi(&mut folder, &INPUT)
+    }
+
+    #[test]
+    fn test_collapse_bpftrace_would_end_stack() {
+        let mut folder = Folder::default();
+        assert!(folder.would_end_stack(b"@[bash]: 4\n"));
+        assert!(folder.would_end_stack(b", bash]: 4\n"));
+        assert!(folder.would_end_stack(b"]: 12\n"));
+        assert!(folder.would_end_stack(b"        12\n"));
+        assert!(!folder.would_end_stack(b"@[\n"));
+        assert!(!folder.would_end_stack(b"        native_safe_halt+14\n"));
+        assert!(!folder.would_end_stack(b"    -                bash (1234)\n"));
+        assert!(!folder.would_end_stack(b"\n"));
+
+        let input = b"@[\n    native_safe_halt+14\n]: 3\n";
+        let mut output = Vec::new();
+        <Folder as Collapse>::collapse(&mut folder, &input[..], &mut output).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "native_safe_halt 3\n"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use std::io::{self, BufRead};
+
+use ahash::{AHashMap, AHashSet};
+use log::warn;
+
+use crate::collapse::common::{DemangleOptions, Occurrences};
+use crate::collapse::Collapse;
+use crate::folded::Count;
+
+/// Calls whose share of a cost is below this are not followed any further, and their share is
+/// counted for the calling frame instead, so that the total cost is kept. Without this cut-off,
+/// call graphs with many paths through them would take exponential time to unfold.
+const MIN_COST: f64 = 0.5;
+
+/// `callgrind` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Event column to source the weight of each stack from, default is `Event::First`.
+    pub event: Event,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
+}
+
+/// Which event column of a callgrind profile to use as the weight of the output stacks.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub enum Event {
+    /// The first event in the `events:` header, which is `Ir` (instructions executed) unless
+    /// callgrind was told to collect something else.
+    #[default]
+    First,
+    /// The event with the given name, e.g. `Ir`, `Dr` or `cycles`.
+    Named(String),
+}
+
+/// A stack collapser for the output of [callgrind](https://valgrind.org/docs/manual/cl-manual.html).
+///
+/// callgrind records the self cost of every function and the inclusive cost of every call
+/// between two functions, rather than full stacks. The stacks are rebuilt by walking the call
+/// graph from the functions that are never called, splitting the cost of each function among
+/// its callers in proportion to the cost of their calls. Recursive calls are not followed, and
+/// functions that cannot be reached from a function that is never called, such as those of a call
+/// cycle without an entry, are shown as roots of their own.
+///
+/// To construct one, either use `callgrind::Folder::default()` or create an [`Options`] and use
+/// `callgrind::Folder::from(options)`.
+#[derive(Clone, Default)]
+pub struct Folder {
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder { opt }
+    }
+}
+
+/// The call graph recorded in a callgrind profile, for a single event.
+#[derive(Debug, Default)]
+struct CallGraph {
+    /// Function names, indexed by function.
+    names: Vec<String>,
+    ids: AHashMap<String, usize>,
+    /// The cost spent in each function itself.
+    self_cost: Vec<u64>,
+    /// The inclusive cost of the calls from each function to others.
+    calls: Vec<AHashMap<usize, u64>>,
+    called: AHashSet<usize>,
+}
+
+impl CallGraph {
+    fn function(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(name.replace(';', ":"));
+        self.ids.insert(name.to_string(), id);
+        self.self_cost.push(0);
+        self.calls.push(AHashMap::default());
+        id
+    }
+}
+
+/// The parser state for the body of a callgrind profile.
+#[derive(Debug, Default)]
+struct Parser {
+    /// The number of position columns (e.g. `line`, or `instr line`) before the event columns.
+    npositions: usize,
+    /// The index of the chosen event among the event columns.
+    event: Option<usize>,
+    /// Function names by their compressed id.
+    compressed: AHashMap<String, String>,
+    function: Option<usize>,
+    callee: Option<usize>,
+    /// Whether the next cost line is the cost of a call.
+    in_call: bool,
+    graph: CallGraph,
+}
+
+impl Collapse for Folder {
+    fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
+    where
+        R: io::BufRead,
+        W: io::Write,
+    {
+        let mut parser = Parser {
+            npositions: 1,
+            ..Default::default()
+        };
+
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let l = String::from_utf8_lossy(&line);
+            let l = l.trim();
+            if l.is_empty() || l.starts_with('#') {
+                continue;
+            }
+            self.on_line(l, &mut parser)?;
+        }
+
+        if parser.event.is_none() {
+            warn!("File ended before the events header");
+            return Ok(());
+        }
+
+        let mut occurrences = Occurrences::new(1);
+        occurrences.set_demangle(self.opt.demangle);
+        write_stacks(&parser.graph, &mut occurrences);
+        occurrences.write_and_clear(writer)
+    }
+
+    /// Check for the callgrind format header or an `events:` line.
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        let mut input = input.as_bytes();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if let Ok(n) = input.read_line(&mut line) {
+                if n == 0 {
+                    break;
+                }
+            } else {
+                return Some(false);
+            }
+
+            let line = line.trim();
+            if line.starts_with("# callgrind format") || line.starts_with("events:") {
+                return Some(true);
+            }
+            if line.is_empty() || line.starts_with('#') || is_header(line) {
+                continue;
+            }
+            return Some(false);
+        }
+        None
+    }
+}
+
+impl Folder {
+    // Handle the lines of a callgrind profile, which look like this:
+    //
+    // version: 1
+    // creator: callgrind-3.22.0
+    // positions: line
+    // events: Ir Dr
+    //
+    // fl=(1) main.c
+    // fn=(1) main
+    // 16 3 1
+    // cfl=(1)
+    // cfn=(2) work
+    // calls=1 0
+    // 17 1200 300
+    // 18 4
+    //
+    // fn=(2)
+    // 0 1200 300
+    fn on_line(&self, line: &str, parser: &mut Parser) -> io::Result<()> {
+        if line.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '+' | '-' | '*')) {
+            return self.on_cost_line(line, parser);
+        }
+
+        if let Some((key, value)) = line.split_once('=') {
+            match key {
+                "fn" => {
+                    let name = decompress(value, &mut parser.compressed);
+                    parser.function = Some(parser.graph.function(&name));
+                    parser.in_call = false;
+                }
+                "cfn" => {
+                    let name = decompress(value, &mut parser.compressed);
+                    parser.callee = Some(parser.graph.function(&name));
+                }
+                "calls" => parser.in_call = true,
+                // File and object names don't affect which function a cost belongs to.
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        if let Some((key, value)) = line.split_once(':') {
+            match key.trim() {
+                "positions" => parser.npositions = value.split_whitespace().count(),
+                "events" => {
+                    let events: Vec<&str> = value.split_whitespace().collect();
+                    parser.event = match self.opt.event {
+                        Event::First if !events.is_empty() => Some(0),
+                        Event::First => {
+                            return invalid_data_error!("No events in callgrind events header")
+                        }
+                        Event::Named(ref name) => match events.iter().position(|e| e == name) {
+                            Some(index) => Some(index),
+                            None => {
+                                return invalid_data_error!(
+                                    "Expected '{}' event but it was not present; found: {}",
+                                    name,
+                                    events.join(" ")
+                                )
+                            }
+                        },
+                    };
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn on_cost_line(&self, line: &str, parser: &mut Parser) -> io::Result<()> {
+        let Some(event) = parser.event else {
+            return invalid_data_error!("Cost line before the events header: {}", line);
+        };
+        // Trailing events that are zero may be left out.
+        let cost = match line.split_whitespace().nth(parser.npositions + event) {
+            Some(cost) => match cost.parse::<u64>() {
+                Ok(cost) => cost,
+                Err(_) => return invalid_data_error!("Invalid cost in line: {}", line),
+            },
+            None => 0,
+        };
+
+        let in_call = std::mem::replace(&mut parser.in_call, false);
+        let Some(function) = parser.function else {
+            // Costs outside of any function can't be attributed to a stack.
+            return Ok(());
+        };
+        let graph = &mut parser.graph;
+        if in_call {
+            let Some(callee) = parser.callee else {
+                return invalid_data_error!("Call cost without a called function: {}", line);
+            };
+            *graph.calls[function].entry(callee).or_insert(0) += cost;
+            // A function that calls itself can still be a root.
+            if callee != function {
+                graph.called.insert(callee);
+            }
+        } else {
+            graph.self_cost[function] += cost;
+        }
+        Ok(())
+    }
+}
+
+/// Resolve a (possibly) compressed name of the form `(id) name` or `(id)`.
+fn decompress(value: &str, compressed: &mut AHashMap<String, String>) -> String {
+    let value = value.trim();
+    if let Some(rest) = value.strip_prefix('(') {
+        if let Some((id, name)) = rest.split_once(')') {
+            let name = name.trim();
+            if name.is_empty() {
+                return compressed
+                    .get(id)
+                    .cloned()
+                    .unwrap_or_else(|| format!("({})", id));
+            }
+            compressed.insert(id.to_string(), name.to_string());
+            return name.to_string();
+        }
+    }
+    value.to_string()
+}
+
+fn is_header(line: &str) -> bool {
+    match line.split_once(':') {
+        Some((key, _)) => {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+/// Unfold the call graph into stacks, starting from every function that is never called.
+///
+/// Functions that cannot be reached from those, such as the functions of a call cycle without an
+/// entry, are unfolded as roots of their own, so that their cost is not lost.
+fn write_stacks(graph: &CallGraph, occurrences: &mut Occurrences) {
+    let mut costs: AHashMap<String, f64> = AHashMap::default();
+    let mut path = Vec::new();
+    let mut reached = vec![false; graph.names.len()];
+    let mut unreached = 0;
+    for pass in 0..2 {
+        for root in 0..graph.names.len() {
+            if reached[root] || (pass == 0 && graph.called.contains(&root)) {
+                continue;
+            }
+            // Calls of the root to itself are part of its other costs already.
+            let total = graph.self_cost[root]
+                + graph.calls[root]
+                    .iter()
+                    .filter(|(&callee, _)| callee != root)
+                    .map(|(_, &cost)| cost)
+                    .sum::<u64>();
+            if pass == 1 && total > 0 {
+                unreached += 1;
+            }
+            reach(graph, root, &mut reached);
+            unfold(graph, root, total as f64, &mut path, &mut costs);
+        }
+    }
+    if unreached > 0 {
+        warn!(
+            "{} function(s) with a cost were not called from any root, and were shown as roots",
+            unreached
+        );
+    }
+
+    for (stack, cost) in costs {
+        match Count::from_f64(cost) {
+            Some(cost) if cost > Count::ZERO => occurrences.insert_or_add(stack, cost),
+            _ => {}
+        }
+    }
+}
+
+/// Mark `function` and every function it calls, directly or not, as reached.
+fn reach(graph: &CallGraph, function: usize, reached: &mut [bool]) {
+    let mut pending = vec![function];
+    while let Some(function) = pending.pop() {
+        if !std::mem::replace(&mut reached[function], true) {
+            pending.extend(graph.calls[function].keys());
+        }
+    }
+}
+
+/// Attribute `cost` of the inclusive cost of `function` to the stack `path;function`, splitting
+/// it between the function's self cost and the functions it calls.
+fn unfold(
+    graph: &CallGraph,
+    function: usize,
+    cost: f64,
+    path: &mut Vec<usize>,
+    costs: &mut AHashMap<String, f64>,
+) {
+    if cost <= 0.0 {
+        return;
+    }
+    path.push(function);
+
+    // Leave out recursive calls, so that the whole cost is shared by the rest.
+    let mut calls: Vec<(usize, u64)> = graph.calls[function]
+        .iter()
+        .filter(|(callee, _)| !path.contains(callee))
+        .map(|(&callee, &cost)| (callee, cost))
+        .collect();
+    // Keep the order (and thus the floating-point sums) of the output deterministic.
+    calls.sort_unstable();
+    let self_cost = graph.self_cost[function];
+    let total = self_cost + calls.iter().map(|(_, cost)| cost).sum::<u64>();
+    let stack = |path: &[usize]| {
+        let stack: Vec<&str> = path.iter().map(|&f| graph.names[f].as_str()).collect();
+        stack.join(";")
+    };
+    if total == 0 {
+        // All of the cost was spent in recursive calls, so it stays with this frame.
+        *costs.entry(stack(path)).or_default() += cost;
+    } else {
+        let scale = cost / total as f64;
+        let mut own_cost = self_cost as f64 * scale;
+        for (callee, call_cost) in calls {
+            let call_cost = call_cost as f64 * scale;
+            if call_cost < MIN_COST {
+                own_cost += call_cost;
+            } else {
+                unfold(graph, callee, call_cost, path, costs);
+            }
+        }
+        if own_cost > 0.0 {
+            *costs.entry(stack(path)).or_default() += own_cost;
+        }
+    }
+
+    path.pop();
+}
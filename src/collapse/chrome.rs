@@ -0,0 +1,367 @@
+use std::io;
+
+use ahash::AHashMap;
+use log::warn;
+use serde::Deserialize;
+
+use crate::collapse::common::{DemangleOptions, Occurrences};
+use crate::collapse::Collapse;
+use crate::folded::Count;
+
+/// `chrome` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// What each stack is weighted by.
+    ///
+    /// Default is `Weight::Samples`.
+    pub weight: Weight,
+
+    /// Add the script URL and line number of each frame to its name
+    /// (e.g. `render (https://example.com/app.js:12)`).
+    ///
+    /// Default is `false`.
+    pub include_source: bool,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
+}
+
+/// What each stack in a Chrome profile is weighted by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Weight {
+    /// The number of samples that hit the stack.
+    #[default]
+    Samples,
+    /// The time spent in the stack in microseconds, accumulated from the profile's time deltas.
+    Time,
+}
+
+/// A stack collapser for CPU profiles produced by Chrome DevTools and Node.js.
+///
+/// Both `.cpuprofile` files (a tree of nodes plus `samples` and `timeDeltas`) and trace-event
+/// JSON files containing `Profile`/`ProfileChunk` events are supported. If a trace contains
+/// several profiles, their stacks are merged.
+///
+/// To construct one, either use `chrome::Folder::default()` or create an [`Options`] and use
+/// `chrome::Folder::from(options)`.
+#[derive(Clone, Default)]
+pub struct Folder {
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder { opt }
+    }
+}
+
+impl Collapse for Folder {
+    fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
+    where
+        R: io::BufRead,
+        W: io::Write,
+    {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let profiles = match data.iter().find(|b| !b.is_ascii_whitespace()) {
+            None => {
+                warn!("File ended before start of profile");
+                return Ok(());
+            }
+            Some(b'[') => profiles_from_trace(serde_json::from_slice(&data)?)?,
+            Some(b'{') => {
+                let file: File = serde_json::from_slice(&data)?;
+                match file.trace_events {
+                    Some(events) => profiles_from_trace(events)?,
+                    None => vec![file.profile],
+                }
+            }
+            Some(_) => return invalid_data_error!("Expected a JSON object or array"),
+        };
+        if profiles.is_empty() {
+            warn!("No CPU profiles found in trace");
+        }
+
+        let mut occurrences = Occurrences::new(1);
+        occurrences.set_demangle(self.opt.demangle);
+        for profile in &profiles {
+            self.collapse_profile(profile, &mut occurrences)?;
+        }
+        occurrences.write_and_clear(writer)
+    }
+
+    /// Check for a `.cpuprofile` node or a trace event.
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        match input.trim_start().chars().next() {
+            None => None,
+            Some('{' | '[') => {
+                if input.contains("\"callFrame\"")
+                    || input.contains("\"ProfileChunk\"")
+                    || input.contains("\"traceEvents\"")
+                {
+                    Some(true)
+                } else {
+                    None
+                }
+            }
+            Some(_) => Some(false),
+        }
+    }
+}
+
+impl Folder {
+    fn collapse_profile(&self, profile: &Profile, occurrences: &mut Occurrences) -> io::Result<()> {
+        // Build each node's stack from its parent's. Nodes either list their children, or (in
+        // trace events) name their parent.
+        let mut parents = AHashMap::with_capacity(profile.nodes.len());
+        for node in &profile.nodes {
+            if let Some(parent) = node.parent {
+                parents.insert(node.id, parent);
+            }
+            for &child in &node.children {
+                parents.insert(child, node.id);
+            }
+        }
+        let nodes: AHashMap<u64, &Node> = profile.nodes.iter().map(|n| (n.id, n)).collect();
+        let mut stacks: AHashMap<u64, String> = AHashMap::with_capacity(profile.nodes.len());
+        for node in &profile.nodes {
+            self.stack_for(node.id, &nodes, &parents, &mut stacks)?;
+        }
+
+        if profile.samples.is_empty() {
+            // Older profiles only record how often each node was hit.
+            if self.opt.weight == Weight::Time {
+                warn!("Profile has no samples; weighting stacks by hit count instead");
+            }
+            for node in &profile.nodes {
+                if node.hit_count > 0 && !stacks[&node.id].is_empty() {
+                    occurrences
+                        .insert_or_add(stacks[&node.id].clone(), Count::from(node.hit_count));
+                }
+            }
+            return Ok(());
+        }
+
+        for (i, id) in profile.samples.iter().enumerate() {
+            let stack = match stacks.get(id) {
+                Some(stack) => stack,
+                None => return invalid_data_error!("Sample refers to unknown node {}", id),
+            };
+            let weight = match self.opt.weight {
+                Weight::Samples => Count::ONE,
+                Weight::Time => profile.sample_duration(i),
+            };
+            if weight > Count::ZERO && !stack.is_empty() {
+                occurrences.insert_or_add(stack.clone(), weight);
+            }
+        }
+        Ok(())
+    }
+
+    fn stack_for(
+        &self,
+        id: u64,
+        nodes: &AHashMap<u64, &Node>,
+        parents: &AHashMap<u64, u64>,
+        stacks: &mut AHashMap<u64, String>,
+    ) -> io::Result<()> {
+        // Walk up to the closest ancestor whose stack we already know, then build the stacks
+        // on the way back down. This avoids recursion on deep trees.
+        let mut path = Vec::new();
+        let mut current = id;
+        while !stacks.contains_key(&current) {
+            if path.len() > nodes.len() {
+                return invalid_data_error!("Cycle in profile node tree at node {}", id);
+            }
+            path.push(current);
+            match parents.get(&current) {
+                Some(&parent) => current = parent,
+                None => break,
+            }
+        }
+
+        for id in path.into_iter().rev() {
+            let node = match nodes.get(&id) {
+                Some(node) => node,
+                None => return invalid_data_error!("Profile refers to unknown node {}", id),
+            };
+            let mut stack = parents
+                .get(&id)
+                .and_then(|parent| stacks.get(parent))
+                .cloned()
+                .unwrap_or_default();
+            // The root node is a placeholder rather than a real frame.
+            if node.call_frame.function_name != "(root)" {
+                if !stack.is_empty() {
+                    stack.push(';');
+                }
+                self.push_frame(&node.call_frame, &mut stack);
+            }
+            stacks.insert(id, stack);
+        }
+        Ok(())
+    }
+
+    fn push_frame(&self, frame: &CallFrame, stack: &mut String) {
+        let name = if frame.function_name.is_empty() {
+            "(anonymous)"
+        } else {
+            &frame.function_name
+        };
+        stack.push_str(&name.replace(';', ":"));
+        if self.opt.include_source && !frame.url.is_empty() {
+            stack.push_str(" (");
+            stack.push_str(&frame.url.replace(';', ":"));
+            if frame.line_number >= 0 {
+                // Line numbers are zero-based.
+                stack.push(':');
+                stack.push_str(itoa::Buffer::new().format(frame.line_number + 1));
+            }
+            stack.push(')');
+        }
+    }
+}
+
+/// Chunks belong to the profile with the same process and profile id.
+type ProfileKey = (Option<serde_json::Value>, Option<serde_json::Value>);
+
+/// Gather the profiles spread across the `Profile` and `ProfileChunk` events of a trace.
+fn profiles_from_trace(events: Vec<TraceEvent>) -> io::Result<Vec<Profile>> {
+    let mut profiles: Vec<(ProfileKey, Profile)> = Vec::new();
+    for event in events {
+        let data = match (event.name.as_str(), event.args) {
+            ("Profile" | "ProfileChunk" | "CpuProfile", Some(mut args)) => {
+                match args.get_mut("data").map(serde_json::Value::take) {
+                    Some(data) => data,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        let data: EventData = serde_json::from_value(data)?;
+
+        if event.name == "CpuProfile" {
+            // Older traces embed the whole profile in a single event.
+            if let Some(profile) = data.cpu_profile {
+                profiles.push(((None, None), profile));
+            }
+            continue;
+        }
+
+        let key = (event.pid, event.id);
+        let index = match profiles.iter().position(|(k, _)| *k == key) {
+            Some(index) => index,
+            None => {
+                profiles.push((key, Profile::default()));
+                profiles.len() - 1
+            }
+        };
+        let profile = &mut profiles[index].1;
+        if data.start_time.is_some() {
+            profile.start_time = data.start_time;
+        }
+        if let Some(chunk) = data.cpu_profile {
+            profile.nodes.extend(chunk.nodes);
+            profile.samples.extend(chunk.samples);
+            if chunk.end_time.is_some() {
+                profile.end_time = chunk.end_time;
+            }
+        }
+        profile.time_deltas.extend(data.time_deltas);
+        if data.end_time.is_some() {
+            profile.end_time = data.end_time;
+        }
+    }
+    Ok(profiles.into_iter().map(|(_, profile)| profile).collect())
+}
+
+/// The top level of a `.cpuprofile` or trace-event JSON object.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct File {
+    trace_events: Option<Vec<TraceEvent>>,
+    #[serde(flatten)]
+    profile: Profile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Profile {
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    samples: Vec<u64>,
+    #[serde(default)]
+    time_deltas: Vec<i64>,
+    start_time: Option<f64>,
+    end_time: Option<f64>,
+}
+
+impl Profile {
+    /// The time until the next sample, in microseconds.
+    fn sample_duration(&self, i: usize) -> Count {
+        let duration = match self.time_deltas.get(i + 1) {
+            Some(&delta) => delta as f64,
+            None => match (self.start_time, self.end_time) {
+                (Some(start), Some(end)) => {
+                    let last: i64 = self.time_deltas.iter().sum();
+                    end - start - last as f64
+                }
+                _ => 0.0,
+            },
+        };
+        Count::from_f64(duration).unwrap_or(Count::ZERO)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Node {
+    id: u64,
+    call_frame: CallFrame,
+    #[serde(default)]
+    hit_count: u64,
+    #[serde(default)]
+    children: Vec<u64>,
+    parent: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallFrame {
+    #[serde(default)]
+    function_name: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default = "unknown_line")]
+    line_number: i64,
+}
+
+fn unknown_line() -> i64 {
+    -1
+}
+
+#[derive(Debug, Deserialize)]
+struct TraceEvent {
+    #[serde(default)]
+    name: String,
+    pid: Option<serde_json::Value>,
+    id: Option<serde_json::Value>,
+    // Kept as a raw value since its shape depends on the event.
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventData {
+    cpu_profile: Option<Profile>,
+    #[serde(default)]
+    time_deltas: Vec<i64>,
+    start_time: Option<f64>,
+    end_time: Option<f64>,
+}
@@ -1,14 +1,19 @@
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::io;
 #[cfg(feature = "multithreaded")]
 use std::mem;
-#[cfg(feature = "multithreaded")]
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use ahash::AHashMap;
 #[cfg(feature = "multithreaded")]
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::folded::Count;
 
 macro_rules! invalid_data_error {
     ($($arg:tt)*) => {{
@@ -21,14 +26,22 @@ macro_rules! invalid_data_error {
 
 const CAPACITY_HASHMAP: usize = 512;
 
+/// Separates the stack from the metric in the keys of stacks counted by metric.
+const METRIC_SEPARATOR: char = '\0';
+
+/// The metric that stacks counted without one are counted for, if other stacks are counted by
+/// metric.
+pub(crate) const UNNAMED_METRIC: &str = "samples";
+
 pub(crate) const CAPACITY_READER: usize = 128 * 1024;
 
-/// Internal parameter (not exposed to users) that determines how many stacks of
-/// input data make up a "chunk" (unit that is sent to the threadpool for
-/// processing). Chosen by benchmarking various values using the following tests:
+/// The default number of stacks of input data that make up a "chunk" (unit that is sent to
+/// the threadpool for processing), for use in [`StackParser::nstacks_per_job`].
+///
+/// Chosen by benchmarking various values using the following tests:
 /// * cargo test bench_nstacks_dtrace --release -- --ignored --nocapture
 /// * cargo test bench_nstacks_perf --release -- --ignored --nocapture
-pub(crate) const DEFAULT_NSTACKS_PER_JOB: usize = 100;
+pub const DEFAULT_NSTACKS_PER_JOB: usize = 100;
 
 /// A guess at the number of bytes contained in any given stack of any given format.
 /// Used to calculate the initial capacity of the vector used for sending input
@@ -38,21 +51,32 @@ const NBYTES_PER_STACK_GUESS: usize = 1024;
 
 const RUST_HASH_LENGTH: usize = 17;
 
+/// The default number of threads to use, which is the number of logical cores on your machine
+/// (or 1 without the `multithreaded` feature).
 #[cfg(feature = "multithreaded")]
-#[doc(hidden)]
 pub static DEFAULT_NTHREADS: Lazy<usize> =
     Lazy::new(|| std::thread::available_parallelism().unwrap().into());
+/// The default number of threads to use, which is the number of logical cores on your machine
+/// (or 1 without the `multithreaded` feature).
 #[cfg(not(feature = "multithreaded"))]
-#[doc(hidden)]
 pub static DEFAULT_NTHREADS: Lazy<usize> = Lazy::new(|| 1);
 
-/// Sealed trait for internal library authors.
+/// A parser for a line-based stack format, from which a [`Collapse`] implementation is derived.
 ///
 /// If you implement this trait, your type will implement the public-facing
-/// `Collapse` trait as well. Implementing this trait gives you parallelism
+/// [`Collapse`] trait as well. Implementing this trait gives you parallelism
 /// for free as long as you adhere to the requirements described in the
-/// comments below.
-pub trait CollapsePrivate: Send + Sized {
+/// comments below: the input is split into chunks of whole stacks (see
+/// [`would_end_stack`](StackParser::would_end_stack)), which are processed on
+/// a threadpool and counted in a shared [`Occurrences`].
+///
+/// This is how the `perf`, `dtrace` and `bpftrace` collapsers are implemented, and it can be used
+/// to add collapsers for other formats outside of this crate. To have
+/// [`guess::Folder`](crate::collapse::guess::Folder) detect such a format, register it with
+/// [`guess::Folder::register`](crate::collapse::guess::Folder::register).
+///
+///   [`Collapse`]: crate::collapse::Collapse
+pub trait StackParser: Send + Sized {
     // *********************************************************** //
     // ********************* REQUIRED METHODS ******************** //
     // *********************************************************** //
@@ -148,36 +172,71 @@ pub trait CollapsePrivate: Send + Sized {
     // ******************** PROVIDED METHODS ********************* //
     // *********************************************************** //
 
-    fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
+    /// Returns how to demangle the frames of the collapsed stacks, if at all.
+    ///
+    /// Override this to pass a folder's demangling options on to the [`Occurrences`] created by
+    /// [`collapse`](StackParser::collapse). By default, frames are written as they are.
+    fn demangle_options(&self) -> Option<DemangleOptions> {
+        None
+    }
+
+    /// Collapses the contents of the provided `reader` and writes folded stack lines to the
+    /// provided `writer`, using several threads if [`nthreads`](StackParser::nthreads) is more
+    /// than 1.
+    ///
+    /// This is what [`Collapse::collapse`](crate::collapse::Collapse::collapse) does for
+    /// implementors of this trait; there should be no need to override it.
+    fn collapse<R, W>(&mut self, reader: R, writer: W) -> io::Result<()>
     where
         R: io::BufRead,
         W: io::Write,
     {
         let mut occurrences = Occurrences::new(self.nthreads());
+        occurrences.set_demangle(self.demangle_options());
+        self.collapse_into(reader, &mut occurrences)?;
 
+        // Write results.
+        occurrences.write_and_clear(writer)
+    }
+
+    /// Collapses the contents of the provided `reader` into `occurrences`, using several threads
+    /// if `occurrences` [is concurrent](Occurrences::is_concurrent), without writing them.
+    ///
+    /// This is the part of [`collapse`](StackParser::collapse) that counts the stacks, for when
+    /// they are written in another way; there should be no need to override it.
+    fn collapse_into<R>(&mut self, mut reader: R, occurrences: &mut Occurrences) -> io::Result<()>
+    where
+        R: io::BufRead,
+    {
         // Consume the header, if any, and do any other pre-processing
         // that needs to occur.
-        self.pre_process(&mut reader, &mut occurrences)?;
+        self.pre_process(&mut reader, occurrences)?;
 
         // Do collapsing.
         if occurrences.is_concurrent() {
-            self.collapse_multi_threaded(reader, &mut occurrences)?;
+            self.collapse_multi_threaded(reader, occurrences)
         } else {
-            self.collapse_single_threaded(reader, &mut occurrences)?;
+            self.collapse_single_threaded(reader, occurrences)
         }
-
-        // Write results.
-        occurrences.write_and_clear(writer)
     }
 
+    /// Processes all samples in the input on a single thread, since the `multithreaded` feature
+    /// is disabled; there should be no need to override this.
     #[cfg(not(feature = "multithreaded"))]
-    fn collapse_multi_threaded<R>(&mut self, _: R, _: &mut Occurrences) -> io::Result<()>
+    fn collapse_multi_threaded<R>(
+        &mut self,
+        reader: R,
+        occurrences: &mut Occurrences,
+    ) -> io::Result<()>
     where
         R: io::BufRead,
     {
-        unimplemented!();
+        self.collapse_single_threaded(reader, occurrences)
     }
 
+    /// Processes all samples in the input on a threadpool, in chunks of
+    /// [`nstacks_per_job`](StackParser::nstacks_per_job) stacks; there should be no need to
+    /// override this.
     #[cfg(feature = "multithreaded")]
     fn collapse_multi_threaded<R>(
         &mut self,
@@ -349,22 +408,40 @@ pub trait CollapsePrivate: Send + Sized {
     }
 }
 
-/// Occurrences is a HashMap, which uses:
+/// The number of occurrences of each stack, as counted by a [`StackParser`].
+///
+/// This is a HashMap, which uses:
 /// * AHashMap if single-threaded
 /// * DashMap if multi-threaded
 ///
-/// This is public because it is part of the sealed `CollapsePrivate` trait's API, but it
-/// is in a crate-private module so is not nameable by downstream library users.
+/// Clones share the same map when multi-threaded, which is how the counts of the worker threads
+/// end up in one place.
+///
+/// If [demangling](Occurrences::set_demangle) is enabled, the frames of the stacks are demangled
+/// when they are written, which merges stacks that only differed in their mangled names.
 #[derive(Clone, Debug)]
-pub enum Occurrences {
-    SingleThreaded(AHashMap<String, usize>),
+pub struct Occurrences {
+    map: Map,
+    demangle: Option<DemangleOptions>,
+    /// Whether stacks have been counted by metric, shared by clones like the map.
+    metrics: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Debug)]
+enum Map {
+    SingleThreaded(AHashMap<String, Count>),
     #[cfg(feature = "multithreaded")]
-    MultiThreaded(Arc<DashMap<String, usize, ahash::RandomState>>),
+    MultiThreaded(Arc<DashMap<String, Count, ahash::RandomState>>),
 }
 
 impl Occurrences {
+    /// Creates an empty map, which can be shared between threads if `nthreads` is more than 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `nthreads` is 0.
     #[cfg(feature = "multithreaded")]
-    pub(crate) fn new(nthreads: usize) -> Self {
+    pub fn new(nthreads: usize) -> Self {
         assert_ne!(nthreads, 0);
         if nthreads == 1 {
             Self::new_single_threaded()
@@ -373,8 +450,13 @@ impl Occurrences {
         }
     }
 
+    /// Creates an empty map, which can be shared between threads if `nthreads` is more than 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `nthreads` is 0.
     #[cfg(not(feature = "multithreaded"))]
-    pub(crate) fn new(nthreads: usize) -> Self {
+    pub fn new(nthreads: usize) -> Self {
         assert_ne!(nthreads, 0);
         Self::new_single_threaded()
     }
@@ -382,64 +464,195 @@ impl Occurrences {
     fn new_single_threaded() -> Self {
         let map =
             AHashMap::with_capacity_and_hasher(CAPACITY_HASHMAP, ahash::RandomState::default());
-        Occurrences::SingleThreaded(map)
+        Occurrences {
+            map: Map::SingleThreaded(map),
+            demangle: None,
+            metrics: Arc::default(),
+        }
     }
 
     #[cfg(feature = "multithreaded")]
     fn new_multi_threaded() -> Self {
         let map =
             DashMap::with_capacity_and_hasher(CAPACITY_HASHMAP, ahash::RandomState::default());
-        Occurrences::MultiThreaded(Arc::new(map))
+        Occurrences {
+            map: Map::MultiThreaded(Arc::new(map)),
+            demangle: None,
+            metrics: Arc::default(),
+        }
     }
 
     /// Inserts a key-count pair into the map. If the map did not have this key
     /// present, `None` is returned. If the map did have this key present, the
     /// value is updated, and the old value is returned.
-    pub(crate) fn insert(&mut self, key: String, count: usize) -> Option<usize> {
-        use self::Occurrences::*;
-        match self {
-            SingleThreaded(map) => map.insert(key, count),
+    pub fn insert(&mut self, key: String, count: Count) -> Option<Count> {
+        match self.map {
+            Map::SingleThreaded(ref mut map) => map.insert(key, count),
             #[cfg(feature = "multithreaded")]
-            MultiThreaded(arc) => arc.insert(key, count),
+            Map::MultiThreaded(ref arc) => arc.insert(key, count),
         }
     }
 
     /// Inserts a key-count pair into the map if the key does not already exist.
     /// If the key does already exist, adds count to the current value of the
     /// existing key.
-    pub(crate) fn insert_or_add(&mut self, key: String, count: usize) {
-        use self::Occurrences::*;
-        match self {
-            SingleThreaded(map) => *map.entry(key).or_insert(0) += count,
+    pub fn insert_or_add(&mut self, key: String, count: Count) {
+        match self.map {
+            Map::SingleThreaded(ref mut map) => *map.entry(key).or_default() += count,
             #[cfg(feature = "multithreaded")]
-            MultiThreaded(arc) => *arc.entry(key).or_insert(0) += count,
+            Map::MultiThreaded(ref arc) => *arc.entry(key).or_default() += count,
         }
     }
 
-    pub(crate) fn is_concurrent(&self) -> bool {
-        use self::Occurrences::*;
-        match self {
-            SingleThreaded(_) => false,
+    /// Adds `count` to the count of `key` for the given metric, such as a `perf` event type.
+    ///
+    /// If stacks are counted this way, they are written as
+    /// [multi-metric](crate::folded::parse_metrics_header) folded stacks, with one sample count
+    /// for each metric, and a count of zero for the metrics a stack wasn't counted for. Stacks
+    /// that are counted without a metric as well are counted for the metric `samples`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `metric` is empty or contains whitespace, since it could not be written.
+    pub fn insert_or_add_metric(&mut self, mut key: String, metric: &str, count: Count) {
+        assert!(
+            !metric.is_empty() && !metric.contains(char::is_whitespace),
+            "Invalid metric name '{}'",
+            metric
+        );
+        self.metrics.store(true, Ordering::Relaxed);
+        key.push(METRIC_SEPARATOR);
+        key.push_str(metric);
+        self.insert_or_add(key, count);
+    }
+
+    /// Sets how the frames of the stacks are demangled when they are written, or `None` to write
+    /// them as they are, which is the default.
+    pub fn set_demangle(&mut self, demangle: Option<DemangleOptions>) {
+        self.demangle = demangle;
+    }
+
+    /// Returns whether this map is shared between threads.
+    pub fn is_concurrent(&self) -> bool {
+        match self.map {
+            Map::SingleThreaded(_) => false,
             #[cfg(feature = "multithreaded")]
-            MultiThreaded(_) => true,
+            Map::MultiThreaded(_) => true,
         }
     }
 
-    pub(crate) fn write_and_clear<W>(&mut self, mut writer: W) -> io::Result<()>
+    /// Writes the stacks in the folded stack format (`frame;frame;frame count`), sorted, and
+    /// empties the map.
+    ///
+    /// Stacks counted with [`insert_or_add_metric`](Occurrences::insert_or_add_metric) are
+    /// written as [multi-metric](crate::folded::parse_metrics_header) folded stacks instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map is shared between threads and another thread still holds a clone of it.
+    pub fn write_and_clear<W>(&mut self, mut writer: W) -> io::Result<()>
     where
         W: io::Write,
     {
-        use self::Occurrences::*;
-        match self {
-            SingleThreaded(ref mut map) => {
-                let mut contents: Vec<_> = map.drain().collect();
-                contents.sort();
-                for (key, value) in contents {
-                    writeln!(writer, "{} {}", key, value)?;
-                }
+        let mut contents = self.drain();
+        if self.metrics.swap(false, Ordering::Relaxed) {
+            return self.write_metrics(contents, writer);
+        }
+        if let Some(demangle) = self.demangle {
+            contents = demangle_stacks(&demangle, contents);
+        }
+        contents.sort();
+        for (key, value) in contents {
+            writeln!(writer, "{} {}", key, value)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the stacks of each metric counted with
+    /// [`insert_or_add_metric`](Occurrences::insert_or_add_metric) in the folded stack format,
+    /// sorted, to the writer that `writer_for` returns for the metric, and empties the map.
+    ///
+    /// `writer_for` is called once for each metric, in the order of their names. Stacks counted
+    /// without a metric are written for the metric `samples`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map is shared between threads and another thread still holds a clone of it.
+    pub fn write_by_metric_and_clear<F, W>(&mut self, mut writer_for: F) -> io::Result<()>
+    where
+        F: FnMut(&str) -> io::Result<W>,
+        W: io::Write,
+    {
+        let contents = self.drain();
+        self.metrics.store(false, Ordering::Relaxed);
+        for (metric, contents) in self.split_metrics(contents) {
+            let mut writer = writer_for(&metric)?;
+            for (key, value) in contents {
+                writeln!(writer, "{} {}", key, value)?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes stacks counted by metric as multi-metric folded stacks, with one column for each
+    /// metric in the order of their names.
+    fn write_metrics<W>(&self, contents: Vec<(String, Count)>, mut writer: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let metrics = self.split_metrics(contents);
+        let mut stacks: BTreeMap<String, Vec<Count>> = BTreeMap::new();
+        for (i, contents) in metrics.values().enumerate() {
+            for (key, count) in contents {
+                stacks
+                    .entry(key.clone())
+                    .or_insert_with(|| vec![Count::ZERO; metrics.len()])[i] = *count;
+            }
+        }
+
+        crate::folded::write_metrics_header(&mut writer, &metrics.keys().collect::<Vec<_>>())?;
+        for (key, counts) in stacks {
+            write!(writer, "{}", key)?;
+            for count in counts {
+                write!(writer, " {}", count)?;
             }
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Splits stacks counted by metric into the stacks of each metric, demangled and sorted.
+    fn split_metrics(
+        &self,
+        contents: Vec<(String, Count)>,
+    ) -> BTreeMap<String, Vec<(String, Count)>> {
+        let mut metrics: BTreeMap<String, Vec<(String, Count)>> = BTreeMap::new();
+        for (mut key, count) in contents {
+            let (metric, separator) = match key.rfind(METRIC_SEPARATOR) {
+                Some(i) => (key[i + 1..].to_string(), i),
+                None => (UNNAMED_METRIC.to_string(), key.len()),
+            };
+            key.truncate(separator);
+            metrics.entry(metric).or_default().push((key, count));
+        }
+        for contents in metrics.values_mut() {
+            if let Some(demangle) = self.demangle {
+                *contents = demangle_stacks(&demangle, std::mem::take(contents));
+            }
+            contents.sort();
+        }
+        metrics
+    }
+
+    /// Removes all stacks from the map, and returns them with their counts.
+    fn drain(&mut self) -> Vec<(String, Count)> {
+        match self.map {
+            Map::SingleThreaded(ref mut map) => map.drain().collect(),
             #[cfg(feature = "multithreaded")]
-            MultiThreaded(ref mut arc) => {
+            Map::MultiThreaded(ref mut arc) => {
                 let map = match Arc::get_mut(arc) {
                     Some(map) => map,
                     None => panic!(
@@ -455,17 +668,267 @@ impl Occurrences {
                         ahash::RandomState::default(),
                     ),
                 );
-                let contents = map.iter().collect::<Vec<_>>();
-                let mut pairs = contents.iter().map(|pair| pair.pair()).collect::<Vec<_>>();
-                pairs.sort();
-                for (key, value) in pairs {
-                    writeln!(writer, "{} {}", key, value)?;
+                map.into_iter().collect()
+            }
+        }
+    }
+}
+
+/// Demangles the frames of `stacks`, and sums up the counts of the stacks that become identical.
+fn demangle_stacks(opt: &DemangleOptions, stacks: Vec<(String, Count)>) -> Vec<(String, Count)> {
+    let mut frames: AHashMap<String, String> = AHashMap::default();
+    let mut demangled: AHashMap<String, Count> =
+        AHashMap::with_capacity_and_hasher(stacks.len(), ahash::RandomState::default());
+    for (stack, count) in stacks {
+        let mut key = String::with_capacity(stack.len());
+        for (i, frame) in stack.split(';').enumerate() {
+            if i != 0 {
+                key.push(';');
+            }
+            match frames.get(frame) {
+                Some(frame) => key.push_str(frame),
+                None => {
+                    let new = opt.demangle(frame).into_owned();
+                    key.push_str(&new);
+                    frames.insert(frame.to_string(), new);
                 }
             }
         }
-        writer.flush()?;
-        Ok(())
+        *demangled.entry(key).or_default() += count;
+    }
+    demangled.into_iter().collect()
+}
+
+/// How the frames of collapsed stacks are demangled.
+///
+/// Frames with Itanium C++ (`_Z`), Rust legacy (`_ZN...h<hash>E`) and v0 (`_R`), and, with the
+/// `swift-demangle` feature, Swift (`$s`) mangled names are demangled. The name may follow a
+/// module name and a backtick, as in DTrace output, and may be followed by an offset or an
+/// annotation such as `_[k]`, which are kept. The other options also apply to frames that were
+/// already demangled by the profiler.
+///
+/// The options can be parsed from a comma-separated list of their names, such as
+/// `strip-hash,simplify-closures`, in which an empty string enables none of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DemangleOptions {
+    /// Remove the hashes from the end of Rust symbols, such as `::h0123456789abcdef`.
+    ///
+    /// Default is `false`.
+    pub strip_hash: bool,
+
+    /// Remove the arguments of C++ templates and Rust and Swift generics, so that
+    /// `std::vector<int>::push_back` becomes `std::vector::push_back`.
+    ///
+    /// Default is `false`.
+    pub strip_generics: bool,
+
+    /// Remove the numbering of closures, so that the closures of a function are merged into one
+    /// frame, such as `{closure}` for Rust, `{lambda}` for C++ and `closure in` for Swift.
+    ///
+    /// Default is `false`.
+    pub simplify_closures: bool,
+}
+
+impl DemangleOptions {
+    /// The names of the options, in the form that [`FromStr`] accepts them.
+    pub const VARIANTS: &'static [&'static str] =
+        &["strip-hash", "strip-generics", "simplify-closures"];
+
+    /// Demangles the symbol in `frame`, and applies the other options to it.
+    pub fn demangle<'a>(&self, frame: &'a str) -> Cow<'a, str> {
+        let mut frame = match demangle_frame(frame, self.strip_hash) {
+            Some(demangled) => Cow::Owned(demangled),
+            None => Cow::Borrowed(frame),
+        };
+        if self.strip_hash {
+            if let Cow::Owned(stripped) = RUST_HASH.replace_all(&frame, "$1") {
+                frame = Cow::Owned(stripped);
+            }
+        }
+        if self.simplify_closures {
+            for (re, replacement) in CLOSURES.iter() {
+                if let Cow::Owned(simplified) = re.replace_all(&frame, *replacement) {
+                    frame = Cow::Owned(simplified);
+                }
+            }
+        }
+        if self.strip_generics {
+            if let Cow::Owned(stripped) = strip_generics(&frame) {
+                frame = Cow::Owned(stripped);
+            }
+        }
+        frame
+    }
+}
+
+impl FromStr for DemangleOptions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut opt = Self::default();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name {
+                "strip-hash" => opt.strip_hash = true,
+                "strip-generics" => opt.strip_generics = true,
+                "simplify-closures" => opt.simplify_closures = true,
+                unknown => return Err(format!("unknown demangle option: {}", unknown)),
+            }
+        }
+        Ok(opt)
+    }
+}
+
+/// A Rust hash at the end of a demangled name, followed by the rest of the frame.
+static RUST_HASH: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"::h[0-9a-f]{16}(_\[|[^0-9A-Za-z_]|$)").unwrap());
+
+/// Numbered closures, and what they are simplified to.
+static CLOSURES: Lazy<[(Regex, &str); 3]> = Lazy::new(|| {
+    [
+        (
+            Regex::new(r"\{\{closure\}\}|\{closure#\d+\}").unwrap(),
+            "{closure}",
+        ),
+        (Regex::new(r"\{lambda\(.*?\)#\d+\}").unwrap(), "{lambda}"),
+        (
+            Regex::new(r"closure #\d+ (?:\(.*?\) )?in ").unwrap(),
+            "closure in ",
+        ),
+    ]
+});
+
+/// Demangles the symbol in `frame`, keeping what comes before and after it. Returns `None` if
+/// there is no mangled symbol.
+fn demangle_frame(frame: &str, strip_hash: bool) -> Option<String> {
+    // The symbol starts at the beginning of the frame or after the module, as in `module`symbol`.
+    let start = frame.find('`').map_or(0, |i| i + 1);
+    let rest = &frame[start..];
+    let len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$')))
+        .unwrap_or(rest.len());
+    let mut symbol = &rest[..len];
+    if rest[len..].starts_with('[') {
+        // An annotation such as `_[k]`.
+        symbol = symbol.strip_suffix('_').unwrap_or(symbol);
+    }
+
+    let demangled = demangle_symbol(symbol, strip_hash)?;
+    Some(format!(
+        "{}{}{}",
+        &frame[..start],
+        demangled,
+        &rest[symbol.len()..]
+    ))
+}
+
+fn demangle_symbol(symbol: &str, strip_hash: bool) -> Option<String> {
+    if let Ok(demangled) = rustc_demangle::try_demangle(symbol) {
+        return Some(if strip_hash {
+            format!("{:#}", demangled)
+        } else {
+            demangled.to_string()
+        });
+    }
+    if symbol.starts_with("_Z") || symbol.starts_with("__Z") {
+        return cpp_demangle::Symbol::new(symbol)
+            .ok()
+            .and_then(|symbol| symbol.demangle().ok());
+    }
+    demangle_swift(symbol)
+}
+
+#[cfg(feature = "swift-demangle")]
+fn demangle_swift(symbol: &str) -> Option<String> {
+    use symbolic_common::{Language, Name, NameMangling};
+    use symbolic_demangle::Demangle;
+
+    const PREFIXES: &[&str] = &["$s", "$S", "$e", "_$s", "_$S", "_$e", "_T0"];
+    if !PREFIXES.iter().any(|prefix| symbol.starts_with(prefix)) {
+        return None;
+    }
+    Name::new(symbol, NameMangling::Mangled, Language::Swift)
+        .demangle(symbolic_demangle::DemangleOptions::complete().return_type(false))
+}
+
+#[cfg(not(feature = "swift-demangle"))]
+fn demangle_swift(_symbol: &str) -> Option<String> {
+    None
+}
+
+/// Removes the arguments of templates and generics from a demangled name.
+///
+/// Qualified paths such as `<T as Trait>::method` and the comparison and shift operators of C++
+/// are kept.
+fn strip_generics(name: &str) -> Cow<'_, str> {
+    if !name.contains('<') {
+        return Cow::Borrowed(name);
     }
+
+    let bytes = name.as_bytes();
+    let mut stripped = String::with_capacity(name.len());
+    let mut i = 0;
+    while i < name.len() {
+        if bytes[i] == b'<' && is_generic(&stripped, &name[i..]) {
+            if let Some(len) = generic_len(&name[i..]) {
+                // Turbofish, as in `foo::<T>`.
+                if stripped.ends_with("::") {
+                    stripped.truncate(stripped.len() - 2);
+                }
+                i += len;
+                continue;
+            }
+        }
+        let c = name[i..].chars().next().unwrap();
+        stripped.push(c);
+        i += c.len_utf8();
+    }
+    Cow::Owned(stripped)
+}
+
+/// Whether the `<` at the start of `rest` opens the arguments of a generic.
+fn is_generic(before: &str, rest: &str) -> bool {
+    if let Some((_, operator)) = before.rsplit_once("operator") {
+        if operator.is_empty() || operator == "<" {
+            // The `<` is part of the operator, as in `operator<<`.
+            return false;
+        }
+        if operator
+            .trim_end()
+            .chars()
+            .all(|c| c.is_ascii_punctuation())
+        {
+            return true;
+        }
+    }
+    if before.ends_with("::") {
+        // Java constructors, as in `Ljava/lang/Object;::<init>`.
+        return !rest.starts_with("<init>") && !rest.starts_with("<clinit>");
+    }
+    before
+        .chars()
+        .next_back()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// The length of the generic arguments at the start of `rest`, including the angle brackets.
+fn generic_len(rest: &str) -> Option<usize> {
+    let bytes = rest.as_bytes();
+    let mut depth = 0;
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'<' => depth += 1,
+            // Not the arrow of a function type.
+            b'>' if i == 0 || bytes[i - 1] != b'-' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
 }
 
 /// Demangles partially demangled Rust symbols that were demangled incorrectly by profilers like
@@ -480,7 +943,7 @@ impl Occurrences {
 ///
 /// Based on code in https://github.com/alexcrichton/rustc-demangle/blob/master/src/legacy.rs
 #[allow(clippy::cognitive_complexity)]
-pub(crate) fn fix_partially_demangled_rust_symbol(symbol: &str) -> Cow<str> {
+pub(crate) fn fix_partially_demangled_rust_symbol(symbol: &str) -> Cow<'_, str> {
     // Rust hashes are hex digits with an `h` prepended.
     let is_rust_hash =
         |s: &str| s.starts_with('h') && s[1..].chars().all(|c| c.is_ascii_hexdigit());
@@ -603,7 +1066,7 @@ pub(crate) mod testing {
 
     pub(crate) fn test_collapse_multi<C, P>(folder: &mut C, inputs: &[P]) -> io::Result<()>
     where
-        C: Collapse + CollapsePrivate,
+        C: Collapse + StackParser,
         P: AsRef<Path>,
     {
         const MAX_THREADS: usize = 16;
@@ -634,7 +1097,7 @@ pub(crate) mod testing {
 
     pub(crate) fn bench_nstacks<C, P>(folder: &mut C, inputs: &[P]) -> io::Result<()>
     where
-        C: CollapsePrivate,
+        C: StackParser,
         P: AsRef<Path>,
     {
         const MIN_LINES: usize = 2000;
@@ -663,7 +1126,7 @@ pub(crate) mod testing {
                 stdout: &mut io::StdoutLock,
             ) -> io::Result<Option<Self>>
             where
-                C: CollapsePrivate,
+                C: StackParser,
             {
                 let default = folder.nstacks_per_job();
 
@@ -688,7 +1151,7 @@ pub(crate) mod testing {
                     let avg_duration =
                         (durations.iter().sum::<u128>() as f64 / durations.len() as f64) as u64;
                     results.insert(nstacks_per_job, avg_duration);
-                    stdout.write_all(&[b'.'])?;
+                    stdout.write_all(b".")?;
                     stdout.flush()?;
                 }
                 Ok(Some(Self {
@@ -771,12 +1234,12 @@ pub(crate) mod testing {
             if let Some(foo) = Foo::new(folder, path, bytes, &mut stdout)? {
                 foos.push(foo);
             }
-            stdout.write_all(&[b'\n'])?;
+            stdout.write_all(b"\n")?;
             stdout.flush()?;
         }
-        stdout.write_all(&[b'\n'])?;
+        stdout.write_all(b"\n")?;
         stdout.flush()?;
-        foos.sort_by(|a, b| b.nstacks.cmp(&a.nstacks));
+        foos.sort_by_key(|foo| std::cmp::Reverse(foo.nstacks));
         for foo in foos {
             stdout.write_fmt(format_args!("{}", foo))?;
             stdout.flush()?;
@@ -876,4 +1339,126 @@ mod tests {
         t_unchanged!("<grep_searcher::searcher::glue::ReadByLine<'s, M, R, S>>::run");
         t_unchanged!("<alloc::raw_vec::RawVec<T, A>>::reserve_internal");
     }
+
+    #[test]
+    fn count_with_and_without_metrics() {
+        use super::Occurrences;
+        use crate::folded::Count;
+
+        let mut occurrences = Occurrences::new(1);
+        occurrences.insert_or_add("main;a".to_string(), Count::from(2u64));
+        occurrences.insert_or_add_metric("main;b".to_string(), "cycles", Count::from(5u64));
+        occurrences.insert_or_add_metric("main;a".to_string(), "cycles", Count::from(1u64));
+        let mut out = Vec::new();
+        occurrences.write_and_clear(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "# metrics: cycles samples\nmain;a 1 2\nmain;b 5 0\n"
+        );
+
+        // Once cleared, stacks are written without metrics again.
+        occurrences.insert_or_add("main;a".to_string(), Count::from(3u64));
+        let mut out = Vec::new();
+        occurrences.write_and_clear(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "main;a 3\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid metric name")]
+    fn reject_metric_names_with_whitespace() {
+        let mut occurrences = super::Occurrences::new(1);
+        occurrences.insert_or_add_metric(
+            "main".to_string(),
+            "cache misses",
+            crate::folded::Count::ONE,
+        );
+    }
+
+    #[test]
+    fn demangle_frames() {
+        use super::DemangleOptions;
+
+        let demangle = |opt: &str, frame: &str| {
+            opt.parse::<DemangleOptions>()
+                .unwrap()
+                .demangle(frame)
+                .into_owned()
+        };
+        assert_eq!(
+            demangle("", "_ZNSt6vectorIiSaIiEE9push_backERKi"),
+            "std::vector<int, std::allocator<int> >::push_back(int const&)"
+        );
+        assert_eq!(
+            demangle(
+                "strip-generics",
+                "libstdc++.so`_ZNSt6vectorIiSaIiEE9push_backERKi+0x1f"
+            ),
+            "libstdc++.so`std::vector::push_back(int const&)+0x1f"
+        );
+        assert_eq!(
+            demangle("", "_ZN4core3ptr13drop_in_place17h0123456789abcdefE_[k]"),
+            "core::ptr::drop_in_place::h0123456789abcdef_[k]"
+        );
+        assert_eq!(
+            demangle(
+                "strip-hash",
+                "_ZN4core3ptr13drop_in_place17h0123456789abcdefE_[k]"
+            ),
+            "core::ptr::drop_in_place_[k]"
+        );
+        assert_eq!(
+            demangle("strip-hash", "core::ptr::drop_in_place::h0123456789abcdef"),
+            "core::ptr::drop_in_place"
+        );
+        assert_eq!(
+            demangle("", "_RNvNtCs1234_7mycrate3foo3bar"),
+            "mycrate[3c1c0]::foo::bar"
+        );
+        assert_eq!(
+            demangle("strip-hash", "_RNvNtCs1234_7mycrate3foo3bar"),
+            "mycrate::foo::bar"
+        );
+        assert_eq!(
+            demangle(
+                "strip-hash,simplify-closures",
+                "_ZN4main4main28_$u7b$$u7b$closure$u7d$$u7d$17h0123456789abcdefE"
+            ),
+            "main::main::{closure}"
+        );
+        assert_eq!(
+            demangle(
+                "simplify-closures,strip-generics",
+                "<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop::{closure#2}"
+            ),
+            "<alloc::vec::Vec as core::ops::drop::Drop>::drop::{closure}"
+        );
+        assert_eq!(
+            demangle("strip-generics", "mycrate::parse::<u8>"),
+            "mycrate::parse"
+        );
+        assert_eq!(
+            demangle(
+                "strip-generics",
+                "std::operator<< <std::char_traits<char> >(int)"
+            ),
+            "std::operator<< (int)"
+        );
+        assert_eq!(
+            demangle("strip-generics", "Ljava/lang/Object;::<init>"),
+            "Ljava/lang/Object;::<init>"
+        );
+        assert_eq!(demangle("strip-generics", "[unknown]"), "[unknown]");
+        assert!("strip-everything".parse::<DemangleOptions>().is_err());
+    }
+
+    #[cfg(feature = "swift-demangle")]
+    #[test]
+    fn demangle_swift_frames() {
+        let opt = super::DemangleOptions {
+            simplify_closures: true,
+            ..Default::default()
+        };
+        assert_eq!(opt.demangle("$s4main3fooyyF"), "foo()");
+        assert_eq!(opt.demangle("$s4main3fooyyFyycfU_"), "closure in foo()");
+    }
 }
@@ -4,7 +4,7 @@ use std::io::{self, prelude::*};
 
 use log::warn;
 
-use crate::collapse::common::{self, CollapsePrivate, Occurrences};
+use crate::collapse::common::{self, DemangleOptions, Occurrences, StackParser};
 
 /// `dtrace` folder configuration options.
 #[derive(Clone, Debug)]
@@ -19,6 +19,11 @@ pub struct Options {
     ///
     /// Default is the number of logical cores on your machine.
     pub nthreads: usize,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
 }
 
 impl Default for Options {
@@ -26,6 +31,7 @@ impl Default for Options {
         Self {
             includeoffset: false,
             nthreads: *common::DEFAULT_NTHREADS,
+            demangle: None,
         }
     }
 }
@@ -71,7 +77,7 @@ impl Default for Folder {
     }
 }
 
-impl CollapsePrivate for Folder {
+impl StackParser for Folder {
     fn pre_process<R>(&mut self, reader: &mut R, _: &mut Occurrences) -> io::Result<()>
     where
         R: io::BufRead,
@@ -109,7 +115,7 @@ impl CollapsePrivate for Folder {
             let line = s.trim();
             if line.is_empty() {
                 continue;
-            } else if let Ok(count) = line.parse::<usize>() {
+            } else if let Ok(count) = line.parse::<u64>() {
                 self.on_stack_end(count, occurrences);
             } else {
                 self.on_stack_line(line);
@@ -148,7 +154,7 @@ impl CollapsePrivate for Folder {
             if line.is_empty() {
                 found_empty_line = true;
             } else if found_empty_line {
-                if line.parse::<usize>().is_ok() {
+                if line.parse::<u64>().is_ok() {
                     return Some(found_stack_line);
                 } else if line.contains('`')
                     || (line.starts_with("0x") && usize::from_str_radix(&line[2..], 16).is_ok())
@@ -169,7 +175,7 @@ impl CollapsePrivate for Folder {
     //     match std::str::from_utf8(line) {
     //         Ok(line) => {
     //             let line = line.trim();
-    //             match line.parse::<usize>() {
+    //             match line.parse::<u64>() {
     //                 Ok(_) => true,
     //                 Err(_) => false,
     //             }
@@ -179,7 +185,7 @@ impl CollapsePrivate for Folder {
     // }
     // ```
     // But it is much faster since it works directly on bytes and because all we're interested in is
-    // whether the provided bytes **can** be parsed into a `usize`, not which `usize` they parse into.
+    // whether the provided bytes **can** be parsed into a `u64`, not which `u64` they parse into.
     // Also, we don't need to validate that the input is utf8.
     //
     // Benchmarking results for the two methods:
@@ -254,6 +260,10 @@ impl CollapsePrivate for Folder {
     fn set_nthreads(&mut self, n: usize) {
         self.opt.nthreads = n;
     }
+
+    fn demangle_options(&self) -> Option<DemangleOptions> {
+        self.opt.demangle
+    }
 }
 
 impl Folder {
@@ -261,7 +271,7 @@ impl Folder {
     // from https://github.com/brendangregg/FlameGraph/blob/1b1c6deede9c33c5134c920bdb7a44cc5528e9a7/stackcollapse.pl#L88
     fn uncpp(probe: &str) -> &str {
         if let Some(scope) = probe.find("::") {
-            if let Some(open) = probe[scope + 2..].rfind(|c| c == '(' || c == '<') {
+            if let Some(open) = probe[scope + 2..].rfind(['(', '<']) {
                 &probe[..scope + 2 + open]
             } else {
                 probe
@@ -371,7 +381,7 @@ impl Folder {
         }
     }
 
-    fn on_stack_end(&mut self, count: usize, occurrences: &mut Occurrences) {
+    fn on_stack_end(&mut self, count: u64, occurrences: &mut Occurrences) {
         // allocate a string that is long enough to hold the entire stack string
         let mut stack_str = String::with_capacity(self.stack_str_size);
 
@@ -393,7 +403,7 @@ impl Folder {
         }
 
         // count it!
-        occurrences.insert_or_add(stack_str, count);
+        occurrences.insert_or_add(stack_str, count.into());
 
         // reset for the next event
         self.stack_str_size = 0;
@@ -568,6 +578,7 @@ mod tests {
             let options = Options {
                 includeoffset: rng.gen(),
                 nthreads: rng.gen_range(2..=32),
+                demangle: rng.gen::<bool>().then(Default::default),
             };
 
             for (path, input) in inputs.iter() {
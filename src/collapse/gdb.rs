@@ -0,0 +1,413 @@
+use std::io::{self, BufRead};
+
+use crate::collapse::common::{DemangleOptions, Occurrences};
+use crate::collapse::Collapse;
+use crate::folded::Count;
+
+/// `gdb` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Keep the function arguments printed by gdb (e.g. `worker_loop (arg=0x0)`).
+    ///
+    /// Default is `false`.
+    pub include_args: bool,
+
+    /// Keep the source location of each frame (e.g. `worker_loop at worker.c:42`).
+    ///
+    /// Default is `false`.
+    pub include_source: bool,
+
+    /// Add a root frame for each thread (e.g. `Thread 12347 (worker)`).
+    ///
+    /// Default is `false`.
+    pub include_thread: bool,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
+}
+
+/// A stack collapser for backtraces printed by `gdb` (e.g. `thread apply all bt`) or
+/// `eu-stack`.
+///
+/// The input may contain several dumps taken one after another, in which case identical
+/// stacks are counted across all of them.
+///
+/// To construct one, either use `gdb::Folder::default()` or create an [`Options`] and use
+/// `gdb::Folder::from(options)`.
+#[derive(Clone, Default)]
+pub struct Folder {
+    /// Root frame for the current thread, if any.
+    thread: Option<String>,
+
+    /// Function on the stack in this entry thus far, leaf first.
+    stack: Vec<String>,
+
+    /// A frame whose arguments continue on the next line.
+    partial: Option<String>,
+
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder {
+            opt,
+            ..Default::default()
+        }
+    }
+}
+
+impl Collapse for Folder {
+    fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
+    where
+        R: io::BufRead,
+        W: io::Write,
+    {
+        let mut occurrences = Occurrences::new(1);
+        occurrences.set_demangle(self.opt.demangle);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let l = String::from_utf8_lossy(&line);
+            self.on_line(l.trim_end(), &mut occurrences);
+        }
+        self.finish_stack(&mut occurrences);
+
+        // Write the results...
+        occurrences.write_and_clear(writer)?;
+
+        // Reset the state...
+        self.thread = None;
+        Ok(())
+    }
+
+    /// Check for a thread header or a frame line.
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        let mut input = input.as_bytes();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if let Ok(n) = input.read_line(&mut line) {
+                if n == 0 {
+                    break;
+                }
+            } else {
+                return Some(false);
+            }
+
+            let line = line.trim();
+            if thread_header(line).is_some() {
+                return Some(true);
+            }
+            if let Some((0, _)) = frame_index(line) {
+                return Some(true);
+            }
+        }
+        None
+    }
+}
+
+impl Folder {
+    // Handle backtrace lines of the forms (gdb):
+    //
+    // Thread 3 (Thread 0x7f1e2a7fc700 (LWP 12347) "worker"):
+    // #0  0x00007f1e2f0b1ad3 in futex_wait_cancelable (private=<optimized out>, expected=0,
+    //     futex_word=0x55d4a8c0a0c8) at ../sysdeps/nptl/futex-internal.h:183
+    // #1  worker_loop (arg=0x0) at worker.c:42
+    // #2  0x00007f1e2f0a9609 in start_thread () from /lib/x86_64-linux-gnu/libpthread.so.0
+    //
+    // and (eu-stack):
+    //
+    // TID 12347:
+    // #0  0x00007f1e2f0b1ad3 futex_wait_cancelable - /usr/lib/libc.so.6
+    //     ../sysdeps/nptl/futex-internal.h:183
+    // #1  0x000055d4a6b2c1e3 worker_loop
+    fn on_line(&mut self, line: &str, occurrences: &mut Occurrences) {
+        let trimmed = line.trim_start();
+
+        if let Some(thread) = thread_header(trimmed) {
+            self.finish_stack(occurrences);
+            self.thread = Some(thread);
+            return;
+        }
+
+        if let Some((index, frame)) = frame_index(trimmed) {
+            self.finish_frame();
+            if index == 0 {
+                // Plain `bt` output has no thread headers, so a new stack starts at every #0.
+                self.finish_stack(occurrences);
+            }
+            self.partial = Some(frame.to_string());
+            return;
+        }
+
+        if let Some(ref mut partial) = self.partial {
+            if line.starts_with(char::is_whitespace) && !trimmed.is_empty() {
+                // Either gdb wrapped a long frame, or eu-stack printed its source location,
+                // which is kept on a line of its own.
+                partial.push(if split_pc(partial).1 { ' ' } else { '\n' });
+                partial.push_str(trimmed);
+                return;
+            }
+        }
+
+        // Anything else (blank lines, "Backtrace stopped: ...", gdb chatter) ends the stack.
+        self.finish_stack(occurrences);
+        if trimmed.is_empty() {
+            self.thread = None;
+        }
+    }
+
+    fn finish_frame(&mut self) {
+        if let Some(frame) = self.partial.take() {
+            let frame = self.tidy_frame(&frame);
+            self.stack.push(frame.replace(';', ":"));
+        }
+    }
+
+    fn finish_stack(&mut self, occurrences: &mut Occurrences) {
+        self.finish_frame();
+        if self.stack.is_empty() {
+            return;
+        }
+
+        let mut stack = String::new();
+        if self.opt.include_thread {
+            if let Some(ref thread) = self.thread {
+                stack.push_str(&thread.replace(';', ":"));
+            }
+        }
+        // Frames are listed leaf first.
+        for frame in self.stack.drain(..).rev() {
+            if !stack.is_empty() {
+                stack.push(';');
+            }
+            stack.push_str(&frame);
+        }
+        occurrences.insert_or_add(stack, Count::ONE);
+    }
+
+    /// Turn the part of a frame line after `#N` into a frame name.
+    fn tidy_frame(&self, frame: &str) -> String {
+        let (frame, is_gdb) = split_pc(frame);
+        let (func, args, source, module) = if is_gdb {
+            parse_gdb_frame(frame)
+        } else {
+            parse_eu_stack_frame(frame)
+        };
+
+        let mut name = match func {
+            "" | "??" => match module {
+                // Fall back to the module, like collapse::perf does.
+                Some(module) => format!("[{}]", &module[module.rfind('/').map_or(0, |i| i + 1)..]),
+                None => "[unknown]".to_string(),
+            },
+            func => func.to_string(),
+        };
+        if self.opt.include_args {
+            if let Some(args) = args {
+                name.push(' ');
+                name.push_str(args);
+            }
+        }
+        if self.opt.include_source {
+            if let Some(source) = source {
+                name.push_str(" at ");
+                name.push_str(source);
+            }
+        }
+        name
+    }
+}
+
+/// Skip the program counter of a frame, and tell whether it was printed by gdb or eu-stack.
+///
+/// gdb follows the program counter by " in " (and leaves it out for some frames), eu-stack
+/// just by the function.
+fn split_pc(frame: &str) -> (&str, bool) {
+    match frame.strip_prefix("0x") {
+        Some(rest) => {
+            let rest = rest.trim_start_matches(|c: char| c.is_ascii_hexdigit());
+            match rest.trim_start().strip_prefix("in ") {
+                Some(rest) => (rest, true),
+                None => (rest.trim_start(), false),
+            }
+        }
+        None => (frame, true),
+    }
+}
+
+/// Split a gdb frame (without its program counter) into function, arguments, source location
+/// and module.
+fn parse_gdb_frame(frame: &str) -> (&str, Option<&str>, Option<&str>, Option<&str>) {
+    let mut rest = frame.trim();
+    let mut source = None;
+    let mut module = None;
+    if let Some(i) = rest.rfind(" at ") {
+        // Arguments can contain " at " too (e.g. in strings), so check for "file:line".
+        let location = &rest[i + 4..];
+        if location
+            .rsplit_once(':')
+            .is_some_and(|(_, line)| !line.is_empty() && line.bytes().all(|b| b.is_ascii_digit()))
+        {
+            source = Some(location);
+            rest = rest[..i].trim_end();
+        }
+    }
+    if source.is_none() {
+        if let Some(i) = rest.rfind(" from ") {
+            if rest[..i].ends_with(')') {
+                module = Some(&rest[i + 6..]);
+                rest = rest[..i].trim_end();
+            }
+        }
+    }
+
+    // The arguments are the last balanced parenthesized group. Function names can contain
+    // parentheses and spaces of their own, e.g. `(anonymous namespace)::foo<int (*)()>`.
+    if rest.ends_with(')') {
+        let mut depth = 0;
+        for (i, c) in rest.char_indices().rev() {
+            match c {
+                ')' => depth += 1,
+                '(' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        if rest[..i].ends_with(' ') {
+                            return (rest[..i].trim_end(), Some(&rest[i..]), source, module);
+                        }
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    (rest, None, source, module)
+}
+
+/// Split an eu-stack frame (without its program counter) into function, arguments, source
+/// location and module.
+fn parse_eu_stack_frame(frame: &str) -> (&str, Option<&str>, Option<&str>, Option<&str>) {
+    // With -s, the source location follows on a line of its own.
+    let (frame, source) = match frame.split_once('\n') {
+        Some((frame, source)) => (frame, Some(source.trim())),
+        None => (frame, None),
+    };
+    // With -m, the module follows " - ".
+    let (func, module) = match frame.rsplit_once(" - ") {
+        Some((func, module)) => (func.trim(), Some(module.trim())),
+        None => (frame.trim(), None),
+    };
+    (func, None, source, module)
+}
+
+/// Parse a thread header, returning the name of its root frame.
+///
+/// gdb: `Thread 3 (Thread 0x7f1e2a7fc700 (LWP 12347) "worker"):`
+/// eu-stack: `TID 12347:`
+fn thread_header(line: &str) -> Option<String> {
+    if let Some(rest) = line.strip_prefix("TID ") {
+        let tid = rest.strip_suffix(':')?;
+        tid.parse::<u64>().ok()?;
+        return Some(format!("Thread {}", tid));
+    }
+
+    let rest = line.strip_prefix("Thread ")?.strip_suffix(':')?;
+    let (num, rest) = rest.split_once(' ')?;
+    num.parse::<u64>().ok()?;
+    if !rest.starts_with('(') {
+        return None;
+    }
+    let tid = match rest.find("LWP ") {
+        Some(i) => rest[i + 4..]
+            .split(|c: char| !c.is_ascii_digit())
+            .next()
+            .unwrap_or(num),
+        None => num,
+    };
+    let name = match (rest.find('"'), rest.rfind('"')) {
+        (Some(start), Some(end)) if start < end => Some(&rest[start + 1..end]),
+        _ => None,
+    };
+    Some(match name {
+        Some(name) => format!("Thread {} ({})", tid, name),
+        None => format!("Thread {}", tid),
+    })
+}
+
+/// Parse the `#N` prefix of a frame line, returning `N` and the rest of the line.
+fn frame_index(line: &str) -> Option<(usize, &str)> {
+    let rest = line.strip_prefix('#')?;
+    let end = rest.find(|c: char| !c.is_ascii_digit())?;
+    let index = rest[..end].parse().ok()?;
+    let frame = &rest[end..];
+    if !frame.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some((index, frame.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gdb_frames() {
+        assert_eq!(
+            parse_gdb_frame("worker_loop (arg=0x0) at worker.c:42"),
+            ("worker_loop", Some("(arg=0x0)"), Some("worker.c:42"), None)
+        );
+        assert_eq!(
+            parse_gdb_frame("?? () from /lib/x86_64-linux-gnu/libc.so.6"),
+            (
+                "??",
+                Some("()"),
+                None,
+                Some("/lib/x86_64-linux-gnu/libc.so.6")
+            )
+        );
+        assert_eq!(
+            parse_gdb_frame("log (msg=0x5555 \"look at this\")"),
+            ("log", Some("(msg=0x5555 \"look at this\")"), None, None)
+        );
+        assert_eq!(
+            parse_gdb_frame(
+                "std::vector<int, std::allocator<int> >::push_back (this=0x7ffc, __x=@0x7ffd: 1) at /usr/include/c++/11/bits/stl_vector.h:1198"
+            ),
+            (
+                "std::vector<int, std::allocator<int> >::push_back",
+                Some("(this=0x7ffc, __x=@0x7ffd: 1)"),
+                Some("/usr/include/c++/11/bits/stl_vector.h:1198"),
+                None
+            )
+        );
+        assert_eq!(
+            parse_gdb_frame("<signal handler called>"),
+            ("<signal handler called>", None, None, None)
+        );
+    }
+
+    #[test]
+    fn thread_headers() {
+        assert_eq!(
+            thread_header("Thread 3 (Thread 0x7f1e2a7fc700 (LWP 12347) \"worker\"):").as_deref(),
+            Some("Thread 12347 (worker)")
+        );
+        assert_eq!(
+            thread_header("Thread 1 (Thread 0x7f1e2b000740 (LWP 12345)):").as_deref(),
+            Some("Thread 12345")
+        );
+        assert_eq!(thread_header("TID 12347:").as_deref(), Some("Thread 12347"));
+        assert_eq!(
+            thread_header("Thread debugging using libthread_db enabled"),
+            None
+        );
+    }
+}
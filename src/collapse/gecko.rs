@@ -0,0 +1,299 @@
+use std::io;
+
+use ahash::AHashMap;
+use log::warn;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::collapse::common::{DemangleOptions, Occurrences};
+use crate::collapse::Collapse;
+use crate::folded::Count;
+
+/// `gecko` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Only include threads whose name matches this regular expression.
+    ///
+    /// Default is `None`.
+    pub thread_name: Option<Regex>,
+
+    /// Add the name of the process each stack was sampled in as its root frame.
+    ///
+    /// Default is `false`.
+    pub include_process: bool,
+
+    /// Add the name of the thread each stack was sampled on as its root frame (below the process
+    /// name, if that is included too).
+    ///
+    /// Default is `false`.
+    pub include_thread: bool,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
+}
+
+/// A stack collapser for profiles in the processed format of the
+/// [Firefox Profiler](https://profiler.firefox.com), as written by e.g.
+/// [samply](https://github.com/mstange/samply).
+///
+/// Each sample of a thread refers to an entry of its stack table, which in turn refers to a
+/// frame and to its parent (prefix) entry. Stacks are rebuilt by following these prefix links.
+/// Samples are weighted by their weight if the profile has one, which may be fractional, and
+/// counted otherwise. The stacks of all included threads are merged.
+///
+/// To construct one, either use `gecko::Folder::default()` or create an [`Options`] and use
+/// `gecko::Folder::from(options)`.
+#[derive(Clone, Default)]
+pub struct Folder {
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder { opt }
+    }
+}
+
+impl Collapse for Folder {
+    fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
+    where
+        R: io::BufRead,
+        W: io::Write,
+    {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        if data.iter().all(u8::is_ascii_whitespace) {
+            warn!("File ended before start of profile");
+            return Ok(());
+        }
+
+        let profile: Profile = serde_json::from_slice(&data)?;
+        let shared_strings = profile.shared.map(|s| s.string_array).unwrap_or_default();
+
+        let mut weights: AHashMap<String, Count> = AHashMap::default();
+        let mut nthreads = 0;
+        for thread in &profile.threads {
+            if let Some(ref regex) = self.opt.thread_name {
+                if !regex.is_match(&thread.name) {
+                    continue;
+                }
+            }
+            nthreads += 1;
+            let strings = thread.string_array.as_deref().unwrap_or(&shared_strings);
+            self.collapse_thread(thread, strings, &mut weights)?;
+        }
+        if nthreads == 0 {
+            warn!("No threads found in profile");
+        }
+
+        let mut occurrences = Occurrences::new(1);
+        occurrences.set_demangle(self.opt.demangle);
+        for (stack, weight) in weights {
+            if weight > Count::ZERO {
+                occurrences.insert_or_add(stack, weight);
+            }
+        }
+        occurrences.write_and_clear(writer)
+    }
+
+    /// Check for the stack and frame tables of a thread.
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        match input.trim_start().chars().next() {
+            None => None,
+            Some('{') if input.contains("\"stackTable\"") => Some(input.contains("\"frameTable\"")),
+            Some('{') => None,
+            Some(_) => Some(false),
+        }
+    }
+}
+
+impl Folder {
+    fn collapse_thread(
+        &self,
+        thread: &Thread,
+        strings: &[String],
+        weights: &mut AHashMap<String, Count>,
+    ) -> io::Result<()> {
+        let mut root = String::new();
+        if self.opt.include_process {
+            match thread.process_name {
+                Some(ref name) if !name.is_empty() => root.push_str(&name.replace(';', ":")),
+                _ => root.push_str(&format!("pid {}", display_id(&thread.pid))),
+            }
+        }
+        if self.opt.include_thread {
+            if !root.is_empty() {
+                root.push(';');
+            }
+            if thread.name.is_empty() {
+                root.push_str(&format!("tid {}", display_id(&thread.tid)));
+            } else {
+                root.push_str(&thread.name.replace(';', ":"));
+            }
+        }
+
+        // A stack's prefix always comes before it in the table, so each stack can be built
+        // from the one of its prefix.
+        let table = &thread.stack_table;
+        if table.frame.len() != table.prefix.len() {
+            return invalid_data_error!(
+                "Stack table of thread '{}' has {} frames but {} prefixes",
+                thread.name,
+                table.frame.len(),
+                table.prefix.len()
+            );
+        }
+        let mut stacks: Vec<String> = Vec::with_capacity(table.frame.len());
+        for (i, (&frame, &prefix)) in table.frame.iter().zip(&table.prefix).enumerate() {
+            let mut stack = match prefix {
+                Some(prefix) if prefix < i => stacks[prefix].clone(),
+                Some(prefix) => {
+                    return invalid_data_error!(
+                        "Stack {} of thread '{}' has prefix {}, which does not come before it",
+                        i,
+                        thread.name,
+                        prefix
+                    )
+                }
+                None => root.clone(),
+            };
+            if !stack.is_empty() {
+                stack.push(';');
+            }
+            stack.push_str(&thread.function_name(frame, strings)?.replace(';', ":"));
+            stacks.push(stack);
+        }
+
+        let samples = &thread.samples;
+        if let Some(ref sample_weights) = samples.weight {
+            if sample_weights.len() != samples.stack.len() {
+                return invalid_data_error!(
+                    "Thread '{}' has {} samples but {} weights",
+                    thread.name,
+                    samples.stack.len(),
+                    sample_weights.len()
+                );
+            }
+        }
+        for (i, stack) in samples.stack.iter().enumerate() {
+            // Samples without a stack were taken while the thread was idle.
+            let Some(stack) = *stack else {
+                continue;
+            };
+            let Some(stack) = stacks.get(stack) else {
+                return invalid_data_error!(
+                    "Sample of thread '{}' refers to unknown stack {}",
+                    thread.name,
+                    stack
+                );
+            };
+            let weight = match samples.weight {
+                Some(ref sample_weights) => match Count::from_f64(sample_weights[i]) {
+                    Some(weight) => weight,
+                    None => {
+                        return invalid_data_error!(
+                            "Sample of thread '{}' has invalid weight {}",
+                            thread.name,
+                            sample_weights[i]
+                        )
+                    }
+                },
+                None => Count::ONE,
+            };
+            *weights.entry(stack.clone()).or_default() += weight;
+        }
+        Ok(())
+    }
+}
+
+/// Format a pid or tid, which may be given as either a number or a string.
+fn display_id(id: &Option<serde_json::Value>) -> String {
+    match id {
+        Some(serde_json::Value::String(id)) => id.clone(),
+        Some(id) => id.to_string(),
+        None => "?".to_string(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Profile {
+    #[serde(default)]
+    threads: Vec<Thread>,
+    shared: Option<Shared>,
+}
+
+/// Newer versions of the format share one string table between all threads.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Shared {
+    #[serde(default)]
+    string_array: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Thread {
+    #[serde(default)]
+    name: String,
+    process_name: Option<String>,
+    pid: Option<serde_json::Value>,
+    tid: Option<serde_json::Value>,
+    samples: Samples,
+    stack_table: StackTable,
+    frame_table: FrameTable,
+    func_table: FuncTable,
+    #[serde(alias = "stringTable")]
+    string_array: Option<Vec<String>>,
+}
+
+impl Thread {
+    fn function_name<'a>(&self, frame: usize, strings: &'a [String]) -> io::Result<&'a str> {
+        let Some(&func) = self.frame_table.func.get(frame) else {
+            return invalid_data_error!(
+                "Stack of thread '{}' refers to unknown frame {}",
+                self.name,
+                frame
+            );
+        };
+        let Some(&name) = self.func_table.name.get(func) else {
+            return invalid_data_error!(
+                "Frame of thread '{}' refers to unknown function {}",
+                self.name,
+                func
+            );
+        };
+        match strings.get(name) {
+            Some(name) => Ok(name),
+            None => invalid_data_error!(
+                "Function of thread '{}' refers to unknown string {}",
+                self.name,
+                name
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Samples {
+    stack: Vec<Option<usize>>,
+    weight: Option<Vec<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StackTable {
+    frame: Vec<usize>,
+    prefix: Vec<Option<usize>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FrameTable {
+    func: Vec<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FuncTable {
+    name: Vec<usize>,
+}
@@ -2,8 +2,9 @@ use std::io::{self, BufRead};
 
 use log::warn;
 
-use crate::collapse::common::Occurrences;
+use crate::collapse::common::{DemangleOptions, Occurrences};
 use crate::collapse::Collapse;
+use crate::folded::Count;
 
 // These are the identifying words of the callgraph table, note that ticks and bytes columns are optional so not present
 static START_LINE: &[&str] = &[
@@ -16,6 +17,11 @@ static START_LINE: &[&str] = &[
 pub struct Options {
     /// Column to source associated value from, default is `Source::PercentTime`.
     pub source: Source,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
 }
 
 /// Which prof column to use as the cost centre of the output stacks
@@ -23,7 +29,8 @@ pub struct Options {
 #[non_exhaustive]
 pub enum Source {
     #[default]
-    /// The indivial %time column representing individual time as a percent of the total
+    /// The indivial %time column representing individual time as a percent of the total, with
+    /// its fractional part (e.g. `30.4`)
     PercentTime,
     /// The ticks column representing individual runtime ticks
     Ticks,
@@ -38,7 +45,7 @@ pub enum Source {
 #[derive(Clone, Default)]
 pub struct Folder {
     /// Cost for the current stack frame.
-    current_cost: usize,
+    current_cost: Count,
 
     /// Function on the stack in this entry thus far.
     stack: Vec<String>,
@@ -103,6 +110,7 @@ impl Collapse for Folder {
 
         // Process the data...
         let mut occurrences = Occurrences::new(1);
+        occurrences.set_demangle(self.opt.demangle);
         loop {
             line.clear();
             if reader.read_until(b'\n', &mut line)? == 0 {
@@ -122,7 +130,7 @@ impl Collapse for Folder {
         occurrences.write_and_clear(writer)?;
 
         // Reset the state...
-        self.current_cost = 0;
+        self.current_cost = Count::ZERO;
         self.stack.clear();
         Ok(())
     }
@@ -218,16 +226,11 @@ impl Folder {
                     .collect::<String>()
             };
             let cost = string_range(cols.source);
-            if let Ok(cost) = cost.trim().parse::<f64>() {
+            // The columns we extract costs from all exclude the cost of their children
+            if let Some(cost) = cost.trim().parse::<f64>().ok().and_then(Count::from_f64) {
                 let func = string_range(cols.cost_centre);
                 let module = string_range(cols.module);
-                // The columns we extract costs from all exclude the cost of their children
-                self.current_cost = match self.opt.source {
-                    // We must `insert_or_add` a `usize` so convert to per-mille to not lose the 1dp
-                    Source::PercentTime => cost * 10.0,
-                    Source::Ticks => cost,
-                    Source::Bytes => cost,
-                } as usize;
+                self.current_cost = cost;
                 self.stack
                     .push(format!("{}.{}", module.trim(), func.trim()));
                 // identical stacks from other threads can appear so need to insert or add
@@ -1,9 +1,13 @@
 use std::io::prelude::*;
 use std::io::{self, Cursor};
+use std::sync::Arc;
 
 use log::{error, info};
 
-use crate::collapse::{self, dtrace, ghcprof, perf, sample, vsprof, vtune, Collapse};
+use crate::collapse::{
+    self, dtrace, gdb, ghcprof, perf, pprof, sample, speedscope, vsprof, vtune, Collapse,
+    DemangleOptions,
+};
 
 const LINES_PER_ITERATION: usize = 10;
 
@@ -15,29 +19,46 @@ pub struct Options {
     ///
     /// Default is the number of logical cores on your machine.
     pub nthreads: usize,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             nthreads: *collapse::DEFAULT_NTHREADS,
+            demangle: None,
         }
     }
 }
 
+/// Creates a fresh folder for a registered format.
+type NewFolder = Arc<dyn Fn() -> Box<dyn DynCollapse> + Send + Sync>;
+
 /// A collapser that tries to find an appropriate implementation of `Collapse`
 /// based on the input, then delegates to that collapser if one is found.
 ///
+/// Formats that are not built into this crate can be added with [`Folder::register`].
+///
 /// If no applicable collapser is found, an error will be logged and
 /// nothing will be written.
 #[derive(Clone)]
 pub struct Folder {
+    /// Extra formats, tried in the order they were registered.
+    formats: Vec<(String, NewFolder)>,
+
     opt: Options,
 }
 
 impl From<Options> for Folder {
     fn from(opt: Options) -> Self {
-        Self { opt }
+        Self {
+            formats: Vec::new(),
+            opt,
+        }
     }
 }
 
@@ -47,15 +68,34 @@ impl Default for Folder {
     }
 }
 
+impl Folder {
+    /// Registers an extra format to detect, such as one implemented with
+    /// [`StackParser`](crate::collapse::StackParser).
+    ///
+    /// Registered formats are tried before the built-in ones, in the order they were registered.
+    /// Each input is checked and collapsed with a fresh clone of `folder`. `name` is used to
+    /// log which collapser was picked.
+    pub fn register<C>(&mut self, name: &str, folder: C) -> &mut Self
+    where
+        C: Collapse + Clone + Send + Sync + 'static,
+    {
+        let factory = move || Box::new(folder.clone()) as Box<dyn DynCollapse>;
+        self.formats.push((name.to_string(), Arc::new(factory)));
+        self
+    }
+}
+
 impl Collapse for Folder {
     fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
     where
         R: io::BufRead,
         W: io::Write,
     {
+        let demangle = self.opt.demangle;
         let mut dtrace = {
             let options = dtrace::Options {
                 nthreads: self.opt.nthreads,
+                demangle,
                 ..Default::default()
             };
             dtrace::Folder::from(options)
@@ -63,18 +103,53 @@ impl Collapse for Folder {
         let mut perf = {
             let options = perf::Options {
                 nthreads: self.opt.nthreads,
+                demangle,
                 ..Default::default()
             };
             perf::Folder::from(options)
         };
-        let mut sample = sample::Folder::default();
-        let mut vtune = vtune::Folder::default();
-        let mut vsprof = vsprof::Folder::default();
-        let mut ghcprof = ghcprof::Folder::default();
+        let mut sample = sample::Folder::from(sample::Options {
+            demangle,
+            ..Default::default()
+        });
+        let mut vtune = vtune::Folder::from(vtune::Options {
+            demangle,
+            ..Default::default()
+        });
+        let mut vsprof = vsprof::Folder::from(vsprof::Options { demangle });
+        let mut ghcprof = ghcprof::Folder::from(ghcprof::Options {
+            demangle,
+            ..Default::default()
+        });
+        let mut speedscope = speedscope::Folder::from(speedscope::Options {
+            demangle,
+            ..Default::default()
+        });
+        let mut gdb = gdb::Folder::from(gdb::Options {
+            demangle,
+            ..Default::default()
+        });
+
+        // pprof profiles are binary, so they have to be recognized from the raw bytes before
+        // we start reading the input as lines of text.
+        if pprof::is_pprof(reader.fill_buf()?) == Some(true) {
+            info!("Using pprof collapser");
+            let options = pprof::Options {
+                demangle,
+                ..Default::default()
+            };
+            return pprof::Folder::from(options).collapse(reader, writer);
+        }
+
+        let mut formats: Vec<_> = self
+            .formats
+            .iter()
+            .map(|(name, factory)| (name.as_str(), factory(), false))
+            .collect();
 
         // Each Collapse impl gets its own flag in this array.
         // It gets set to true when the impl has been ruled out.
-        let mut not_applicable = [false; 6];
+        let mut not_applicable = [false; 8];
 
         let mut buffer = String::new();
         loop {
@@ -85,6 +160,22 @@ impl Collapse for Folder {
                 }
             }
 
+            for (name, folder, not_applicable) in &mut formats {
+                if *not_applicable {
+                    continue;
+                }
+                match folder.is_applicable_dyn(&buffer) {
+                    Some(false) => *not_applicable = true,
+                    Some(true) => {
+                        info!("Using {} collapser", name);
+                        let mut cursor = Cursor::new(buffer).chain(reader);
+                        let mut writer = writer;
+                        return folder.collapse_dyn(&mut cursor, &mut writer);
+                    }
+                    None => (),
+                }
+            }
+
             macro_rules! try_collapse_impl {
                 ($collapse:ident, $index:expr) => {
                     if !not_applicable[$index] {
@@ -110,6 +201,8 @@ impl Collapse for Folder {
             try_collapse_impl!(vtune, 3);
             try_collapse_impl!(vsprof, 4);
             try_collapse_impl!(ghcprof, 5);
+            try_collapse_impl!(speedscope, 6);
+            try_collapse_impl!(gdb, 7);
 
             if eof {
                 break;
@@ -125,3 +218,20 @@ impl Collapse for Folder {
         unreachable!()
     }
 }
+
+/// An object-safe version of [`Collapse`], so that folders of any type can be registered.
+trait DynCollapse {
+    fn collapse_dyn(&mut self, reader: &mut dyn BufRead, writer: &mut dyn Write) -> io::Result<()>;
+
+    fn is_applicable_dyn(&mut self, input: &str) -> Option<bool>;
+}
+
+impl<C: Collapse> DynCollapse for C {
+    fn collapse_dyn(&mut self, reader: &mut dyn BufRead, writer: &mut dyn Write) -> io::Result<()> {
+        self.collapse(reader, writer)
+    }
+
+    fn is_applicable_dyn(&mut self, input: &str) -> Option<bool> {
+        self.is_applicable(input)
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use log::warn;
+use regex::Regex;
+
+use crate::collapse::common::{DemangleOptions, Occurrences};
+use crate::collapse::Collapse;
+use crate::folded::Count;
+
+/// `jstack` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Only include threads in one of these states. If empty, threads in any state are included.
+    ///
+    /// Threads without a Java stack (such as GC and compiler threads) never have a state.
+    ///
+    /// Default is empty.
+    pub thread_states: Vec<ThreadState>,
+
+    /// Only include threads whose name matches this regular expression.
+    ///
+    /// Default is `None`.
+    pub thread_name: Option<Regex>,
+
+    /// Add the state of each thread as a leaf frame (e.g. `[WAITING]`), so it can be told apart
+    /// from the Java frames when using the `java` palette.
+    ///
+    /// Default is `false`.
+    pub include_state: bool,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
+}
+
+/// The state of a Java thread, as reported on its `java.lang.Thread.State:` line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ThreadState {
+    /// The thread has not been started yet.
+    New,
+    /// The thread is executing, or ready to execute.
+    Runnable,
+    /// The thread is waiting to acquire a monitor lock.
+    Blocked,
+    /// The thread is waiting indefinitely for another thread.
+    Waiting,
+    /// The thread is waiting for another thread for a limited time.
+    TimedWaiting,
+    /// The thread has exited.
+    Terminated,
+}
+
+impl ThreadState {
+    fn as_str(self) -> &'static str {
+        match self {
+            ThreadState::New => "NEW",
+            ThreadState::Runnable => "RUNNABLE",
+            ThreadState::Blocked => "BLOCKED",
+            ThreadState::Waiting => "WAITING",
+            ThreadState::TimedWaiting => "TIMED_WAITING",
+            ThreadState::Terminated => "TERMINATED",
+        }
+    }
+}
+
+impl fmt::Display for ThreadState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ThreadState {
+    type Err = String;
+
+    /// Parse a thread state as printed by `jstack` (e.g. `TIMED_WAITING`), ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_uppercase().as_str() {
+            "NEW" => ThreadState::New,
+            "RUNNABLE" => ThreadState::Runnable,
+            "BLOCKED" => ThreadState::Blocked,
+            "WAITING" => ThreadState::Waiting,
+            "TIMED_WAITING" => ThreadState::TimedWaiting,
+            "TERMINATED" => ThreadState::Terminated,
+            _ => return Err(format!("unknown thread state: {}", s)),
+        })
+    }
+}
+
+/// A stack collapser for Java thread dumps, as printed by `jstack` or `jcmd <pid> Thread.print`.
+///
+/// The input may contain several dumps taken one after another, in which case identical
+/// stacks are counted across all of them. This makes it possible to build a "poor man's
+/// profile" from repeated dumps.
+///
+/// To construct one, either use `jstack::Folder::default()` or create an [`Options`] and use
+/// `jstack::Folder::from(options)`.
+#[derive(Clone, Default)]
+pub struct Folder {
+    /// Name of the thread whose stack is currently being read, if any.
+    thread: Option<String>,
+
+    /// State of the current thread.
+    state: Option<ThreadState>,
+
+    /// Function on the stack in this entry thus far, leaf first.
+    stack: Vec<String>,
+
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder {
+            opt,
+            ..Default::default()
+        }
+    }
+}
+
+impl Collapse for Folder {
+    fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
+    where
+        R: io::BufRead,
+        W: io::Write,
+    {
+        let mut occurrences = Occurrences::new(1);
+        occurrences.set_demangle(self.opt.demangle);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let l = String::from_utf8_lossy(&line);
+            self.on_line(l.trim(), &mut occurrences);
+        }
+        self.finish_thread(&mut occurrences);
+
+        // Write the results...
+        occurrences.write_and_clear(writer)?;
+
+        // Reset the state...
+        self.thread = None;
+        self.state = None;
+        self.stack.clear();
+        Ok(())
+    }
+
+    /// Check for a thread dump header or a thread state line.
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        let mut input = input.as_bytes();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if let Ok(n) = input.read_line(&mut line) {
+                if n == 0 {
+                    break;
+                }
+            } else {
+                return Some(false);
+            }
+
+            let line = line.trim();
+            if line.starts_with("Full thread dump") || line.starts_with("java.lang.Thread.State:") {
+                return Some(true);
+            }
+        }
+        None
+    }
+}
+
+impl Folder {
+    // Handle thread dump lines of the form:
+    //
+    // "main" #1 prio=5 os_prio=0 cpu=130.12ms elapsed=5.02s tid=0x00007f... nid=0x1a03 runnable  [0x00007f...]
+    //    java.lang.Thread.State: RUNNABLE
+    //         at java.io.FileInputStream.readBytes(java.base@17.0.2/Native Method)
+    //         at com.example.App.main(App.java:12)
+    //         - locked <0x000000062a8b1e18> (a java.lang.Object)
+    fn on_line(&mut self, line: &str, occurrences: &mut Occurrences) {
+        if line.starts_with('"') {
+            self.finish_thread(occurrences);
+            // Thread names may themselves contain quotes, but the rest of the line does not.
+            let end = line.rfind('"').unwrap_or(0).max(1);
+            self.thread = Some(line[1..end].to_string());
+        } else if line.is_empty() {
+            self.finish_thread(occurrences);
+        } else if self.thread.is_none() {
+            // Dump headers and trailers, e.g. "Full thread dump ..." or "JNI global refs: ...".
+        } else if let Some(state) = line.strip_prefix("java.lang.Thread.State:") {
+            // e.g. "WAITING (on object monitor)"
+            let state = state.split_whitespace().next().unwrap_or("");
+            self.state = match state.parse() {
+                Ok(state) => Some(state),
+                Err(e) => {
+                    warn!("Ignoring {}", e);
+                    None
+                }
+            };
+        } else if let Some(frame) = line.strip_prefix("at ") {
+            // Drop the source location, e.g. "(App.java:12)" or "(Native Method)".
+            let frame = match frame.find('(') {
+                Some(i) => &frame[..i],
+                None => frame,
+            };
+            self.stack.push(frame.trim().replace(';', ":"));
+        }
+    }
+
+    fn finish_thread(&mut self, occurrences: &mut Occurrences) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        let state = self.state.take();
+        if self.stack.is_empty() || !self.is_included(&thread, state) {
+            self.stack.clear();
+            return;
+        }
+
+        let mut stack = String::with_capacity(
+            self.stack.iter().fold(0, |a, s| a + s.len() + 1) + "[TIMED_WAITING]".len(),
+        );
+        // Frames are listed leaf first.
+        for (i, frame) in self.stack.drain(..).rev().enumerate() {
+            if i > 0 {
+                stack.push(';');
+            }
+            stack.push_str(&frame);
+        }
+        if self.opt.include_state {
+            if let Some(state) = state {
+                stack.push_str(";[");
+                stack.push_str(state.as_str());
+                stack.push(']');
+            }
+        }
+        occurrences.insert_or_add(stack, Count::ONE);
+    }
+
+    fn is_included(&self, thread: &str, state: Option<ThreadState>) -> bool {
+        if !self.opt.thread_states.is_empty()
+            && !state.is_some_and(|state| self.opt.thread_states.contains(&state))
+        {
+            return false;
+        }
+        match self.opt.thread_name {
+            Some(ref regex) => regex.is_match(thread),
+            None => true,
+        }
+    }
+}
@@ -1,6 +1,30 @@
 #[macro_use]
 pub(crate) mod common;
 
+/// Stack collapsing for stack counts printed by [`bpftrace`](https://github.com/bpftrace/bpftrace)
+/// and the [BCC](https://github.com/iovisor/bcc) tools (e.g. `profile` and `offcputime`).
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod bpftrace;
+
+/// Stack collapsing for the output of [callgrind](https://valgrind.org/docs/manual/cl-manual.html).
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod callgrind;
+
+/// Stack collapsing for CPU profiles from [Chrome DevTools](https://developer.chrome.com/docs/devtools/performance)
+/// and [Node.js](https://nodejs.org/en/docs/guides/simple-profiling) (`.cpuprofile` files and
+/// trace-event JSON).
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod chrome;
+
 /// Stack collapsing for the output of [`dtrace`](https://www.joyent.com/dtrace).
 ///
 /// See the [crate-level documentation] for details.
@@ -8,9 +32,33 @@ pub(crate) mod common;
 ///   [crate-level documentation]: ../../index.html
 pub mod dtrace;
 
+/// Stack collapsing for profiles in the processed format of the
+/// [Firefox Profiler](https://profiler.firefox.com) (e.g. from [samply](https://github.com/mstange/samply)).
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod gecko;
+
+/// Stack collapsing for backtraces printed by [`gdb`](https://sourceware.org/gdb/) (e.g. with
+/// `thread apply all bt`) or [`eu-stack`](https://sourceware.org/elfutils/).
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod gdb;
+
 /// Attempts to use whichever Collapse implementation is appropriate for a given input
 pub mod guess;
 
+/// Stack collapsing for Java thread dumps, as printed by [`jstack`](https://docs.oracle.com/en/java/javase/17/docs/specs/man/jstack.html)
+/// or `jcmd <pid> Thread.print`.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod jstack;
+
 /// Stack collapsing for the output of [`perf script`](https://linux.die.net/man/1/perf-script).
 ///
 /// See the [crate-level documentation] for details.
@@ -28,6 +76,14 @@ pub(crate) mod matcher;
 ///   [crate-level documentation]: ../../index.html
 pub mod sample;
 
+/// Stack collapsing for files in the [speedscope](https://github.com/jlfwong/speedscope/wiki/Importing-from-custom-sources)
+/// file format, as exported by profilers such as rbspy, py-spy and samply.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod speedscope;
+
 /// Stack collapsing for the output of [`VTune`](https://software.intel.com/en-us/vtune-amplifier-help-command-line-interface).
 ///
 /// See the [crate-level documentation] for details.
@@ -67,18 +123,24 @@ pub mod vsprof;
 ///   [crate-level documentation]: ../../index.html
 pub mod ghcprof;
 
+/// Stack collapsing for [pprof](https://github.com/google/pprof) protobuf profiles, such as
+/// those produced by Go's `runtime/pprof`.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod pprof;
+
 use is_terminal::IsTerminal;
 
-// DEFAULT_NTHREADS is public because we use it in the help text of the binaries,
-// but it doesn't need to be exposed to library users, hence #[doc(hidden)].
-#[doc(hidden)]
-pub use self::common::DEFAULT_NTHREADS;
+pub use self::common::{
+    DemangleOptions, Occurrences, StackParser, DEFAULT_NSTACKS_PER_JOB, DEFAULT_NTHREADS,
+};
 
-use std::fs::File;
 use std::io;
 use std::path::Path;
 
-use self::common::{CollapsePrivate, CAPACITY_READER};
+use crate::input;
 
 /// The abstract behavior of stack collapsing.
 ///
@@ -101,24 +163,19 @@ pub trait Collapse {
 
     /// Collapses the contents of the provided file (or of STDIN if `infile` is `None`) and
     /// writes folded stack lines to provided `writer`.
+    ///
+    /// With the `compression` feature, input that is compressed with gzip, zstd or xz is
+    /// decompressed on the fly.
     fn collapse_file<P, W>(&mut self, infile: Option<P>, writer: W) -> io::Result<()>
     where
         P: AsRef<Path>,
         W: io::Write,
     {
-        match infile {
-            Some(ref path) => {
-                let file = File::open(path)?;
-                let reader = io::BufReader::with_capacity(CAPACITY_READER, file);
-                self.collapse(reader, writer)
-            }
-            None => {
-                let stdin = io::stdin();
-                let stdin_guard = stdin.lock();
-                let reader = io::BufReader::with_capacity(CAPACITY_READER, stdin_guard);
-                self.collapse(reader, writer)
-            }
-        }
+        let reader = match infile {
+            Some(ref path) => input::open(path)?,
+            None => input::stdin()?,
+        };
+        self.collapse(reader, writer)
     }
 
     /// Collapses the contents of the provided file (or of STDIN if `infile` is `None`) and
@@ -145,17 +202,17 @@ pub trait Collapse {
 
 impl<T> Collapse for T
 where
-    T: CollapsePrivate,
+    T: StackParser,
 {
     fn collapse<R, W>(&mut self, reader: R, writer: W) -> io::Result<()>
     where
         R: io::BufRead,
         W: io::Write,
     {
-        <Self as CollapsePrivate>::collapse(self, reader, writer)
+        <Self as StackParser>::collapse(self, reader, writer)
     }
 
     fn is_applicable(&mut self, input: &str) -> Option<bool> {
-        <Self as CollapsePrivate>::is_applicable(self, input)
+        <Self as StackParser>::is_applicable(self, input)
     }
 }
@@ -1,8 +1,11 @@
 use std::collections::VecDeque;
-use std::io::{self, BufRead};
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter};
+use std::path::Path;
 
-use crate::collapse::common::{self, CollapsePrivate, Occurrences};
+use crate::collapse::common::{self, DemangleOptions, Occurrences, StackParser, UNNAMED_METRIC};
 use crate::collapse::matcher::is_kernel;
+use crate::input;
 
 const TIDY_GENERIC: bool = true;
 const TIDY_JAVA: bool = true;
@@ -14,6 +17,10 @@ mod logging {
         info!("Filtering for events of type: {}", ty);
     }
 
+    pub(super) fn writing_event_to(event: &str, path: &str) {
+        info!("Writing events of type {} to {}", event, path);
+    }
+
     pub(super) fn weird_event_line(line: &str) {
         warn!("Weird event line: {}", line);
     }
@@ -50,6 +57,19 @@ pub struct Options {
     /// Default is `None`.
     pub event_filter: Option<String>,
 
+    /// Count the samples of all event types, each in its own column of
+    /// [multi-metric](crate::folded::parse_metrics_header) folded stacks, instead of only those of
+    /// `event_filter`, which is ignored. The columns are in the order of the event names, and
+    /// samples without an event type, as in the output of `perf script -F comm,tid,time,ip,sym`,
+    /// are counted as `samples`.
+    ///
+    /// With `perf record -e cycles,instructions`, this gives both counts of every stack in one
+    /// pass, from which a flame graph can show the instructions per cycle of each frame (see
+    /// [`flamegraph::Options::ratios`](crate::flamegraph::Options::ratios)).
+    ///
+    /// Default is `false`.
+    pub all_events: bool,
+
     /// Include raw addresses (e.g., `0xbfff0836`) where symbols can't be found.
     ///
     /// Default is `false`.
@@ -76,6 +96,11 @@ pub struct Options {
     /// In case no function is matched the whole stack is returned.
     /// Default is not omitting any.
     pub skip_after: Vec<String>,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
 }
 
 impl Default for Options {
@@ -84,11 +109,13 @@ impl Default for Options {
             annotate_jit: false,
             annotate_kernel: false,
             event_filter: None,
+            all_events: false,
             include_addrs: false,
             include_pid: false,
             include_tid: false,
             nthreads: *common::DEFAULT_NTHREADS,
             skip_after: Vec::default(),
+            demangle: None,
         }
     }
 }
@@ -113,6 +140,9 @@ pub struct Folder {
     ///   different event types, such as instructions and cycles, would produce misleading results.
     event_filter: Option<String>,
 
+    /// The event type of the current event, if all event types are counted.
+    event: String,
+
     /// All lines until the next empty line are stack lines.
     in_event: bool,
 
@@ -131,7 +161,7 @@ pub struct Folder {
     stack: VecDeque<String>,
 
     /// period of current event
-    period: Option<usize>,
+    period: Option<u64>,
 
     // Options...
     opt: Options,
@@ -146,6 +176,7 @@ impl From<Options> for Folder {
         Self {
             cache_line: Vec::default(),
             event_filter: opt.event_filter.clone(),
+            event: String::default(),
             in_event: false,
             nstacks_per_job: common::DEFAULT_NSTACKS_PER_JOB,
             pname: String::default(),
@@ -163,13 +194,13 @@ impl Default for Folder {
     }
 }
 
-impl CollapsePrivate for Folder {
+impl StackParser for Folder {
     fn pre_process<R>(&mut self, reader: &mut R, occurrences: &mut Occurrences) -> io::Result<()>
     where
         R: io::BufRead,
     {
-        // If user has provided an event filter, do nothing...
-        if self.event_filter.is_some() {
+        // If user has provided an event filter, or all events are counted, do nothing...
+        if self.event_filter.is_some() || self.opt.all_events {
             return Ok(());
         }
 
@@ -261,6 +292,7 @@ impl CollapsePrivate for Folder {
         Self {
             cache_line: self.cache_line.clone(),
             event_filter: self.event_filter.clone(),
+            event: String::new(),
             in_event: false,
             nstacks_per_job: self.nstacks_per_job,
             pname: String::new(),
@@ -286,9 +318,69 @@ impl CollapsePrivate for Folder {
     fn set_nthreads(&mut self, n: usize) {
         self.opt.nthreads = n;
     }
+
+    fn demangle_options(&self) -> Option<DemangleOptions> {
+        self.opt.demangle
+    }
 }
 
 impl Folder {
+    /// Collapses the samples of each event type in the provided `reader` into folded stacks of
+    /// their own, in one pass, and writes them to the writer that `writer_for` returns for the
+    /// event type.
+    ///
+    /// This is like setting [`Options::all_events`], but with the stacks of each event type
+    /// written on their own instead of as columns. `writer_for` is called once for each event
+    /// type, in the order of their names, after all samples are counted.
+    pub fn collapse_per_event<R, F, W>(&mut self, reader: R, writer_for: F) -> io::Result<()>
+    where
+        R: io::BufRead,
+        F: FnMut(&str) -> io::Result<W>,
+        W: io::Write,
+    {
+        let mut occurrences = Occurrences::new(self.opt.nthreads);
+        occurrences.set_demangle(self.opt.demangle);
+        let all_events = std::mem::replace(&mut self.opt.all_events, true);
+        let result = self.collapse_into(reader, &mut occurrences);
+        self.opt.all_events = all_events;
+        result?;
+        occurrences.write_by_metric_and_clear(writer_for)
+    }
+
+    /// Collapses the samples of each event type in the provided file (or in STDIN if `infile` is
+    /// `None`) into a file of its own, named by replacing `{event}` in `template` with the event
+    /// type, as in `out.{event}.folded`.
+    ///
+    /// See [`collapse_per_event`](Folder::collapse_per_event) for details. Path separators in
+    /// event types are replaced by `_`.
+    pub fn collapse_file_per_event<P>(
+        &mut self,
+        infile: Option<P>,
+        template: &str,
+    ) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        if !template.contains("{event}") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "output file template {} does not contain {{event}}",
+                    template
+                ),
+            ));
+        }
+        let reader = match infile {
+            Some(ref path) => input::open(path)?,
+            None => input::stdin()?,
+        };
+        self.collapse_per_event(reader, |event| {
+            let path = template.replace("{event}", &event.replace(['/', '\\'], "_"));
+            logging::writing_event_to(event, &path);
+            Ok(BufWriter::new(File::create(path)?))
+        })
+    }
+
     /// Processes a stack. On success, returns `true` if at end of data; `false` otherwise.
     fn process_single_stack<R>(
         &mut self,
@@ -383,10 +475,20 @@ impl Folder {
             let event = by_colons.next().and_then(|period_and_event| {
                 let mut it = period_and_event.rsplit(' ');
                 let event_name = it.next();
-                self.period = it.next().and_then(|s| s.parse::<usize>().ok());
+                self.period = it.next().and_then(|s| s.parse::<u64>().ok());
                 event_name
             });
-            if let Some(event) = event {
+            if self.opt.all_events {
+                // `perf script -F` output may leave out the event type of samples.
+                let event = event.filter(|event| !event.is_empty());
+                if event.is_some_and(|event| event.contains(char::is_whitespace)) {
+                    logging::weird_event_line(line);
+                    self.stack_filter = StackFilter::Skip;
+                    return;
+                }
+                self.event.clear();
+                self.event.push_str(event.unwrap_or(UNNAMED_METRIC));
+            } else if let Some(event) = event {
                 if let Some(ref event_filter) = self.event_filter {
                     if event != event_filter {
                         self.stack_filter = StackFilter::Skip;
@@ -413,10 +515,7 @@ impl Folder {
                 // the first should not be handled as a stack, whereas the latter two both should
                 // the trick is going to be to trim until we encounter a space or a :, whichever
                 // comes first, and then evaluate from there.
-                let post_event_start = post_event
-                    .find(|c| c == ':' || c == ' ')
-                    .map(|i| i + 1)
-                    .unwrap_or(0);
+                let post_event_start = post_event.find([':', ' ']).map(|i| i + 1).unwrap_or(0);
                 let post_event = post_event[post_event_start..].trim();
                 if !post_event.is_empty() {
                     // we have a stack!
@@ -549,10 +648,9 @@ impl Folder {
                 } else if self.opt.annotate_jit
                     && ((module.starts_with("/tmp/perf-") && module.ends_with(".map"))
                         || (module.contains("/jitted-") && module.ends_with(".so")))
+                    && !func.contains("_[j]")
                 {
-                    if !func.contains("_[j]") {
-                        func.push_str("_[j]"); // jitted
-                    }
+                    func.push_str("_[j]"); // jitted
                 }
 
                 self.cache_line.push(func);
@@ -598,7 +696,12 @@ impl Folder {
             stack_str.pop();
 
             // count it!
-            occurrences.insert_or_add(stack_str, self.period.unwrap_or(1));
+            let count = self.period.unwrap_or(1).into();
+            if self.opt.all_events {
+                occurrences.insert_or_add_metric(stack_str, &self.event, count);
+            } else {
+                occurrences.insert_or_add(stack_str, count);
+            }
         }
 
         // reset for the next event
@@ -890,11 +993,13 @@ mod tests {
                 annotate_jit: rng.gen(),
                 annotate_kernel: rng.gen(),
                 event_filter: None,
+                all_events: rng.gen(),
                 include_addrs: rng.gen(),
                 include_pid: rng.gen(),
                 include_tid: rng.gen(),
                 nthreads: rng.gen_range(2..=32),
                 skip_after: Vec::default(),
+                demangle: rng.gen::<bool>().then(Default::default),
             };
 
             for (path, input) in inputs.iter() {
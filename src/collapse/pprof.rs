@@ -0,0 +1,553 @@
+use std::io::{self, Read};
+
+use ahash::AHashMap;
+use log::{info, warn};
+
+use crate::collapse::common::{DemangleOptions, Occurrences};
+use crate::collapse::Collapse;
+use crate::folded::Count;
+
+/// The first two bytes of a gzip stream. `go tool pprof` and most other producers write
+/// gzip-compressed profiles.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// `pprof` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Which of each sample's values to use as the weight of its stack.
+    ///
+    /// Default is `SampleIndex::Default`.
+    pub sample_index: SampleIndex,
+
+    /// Include the address of unsymbolized locations in their frame names
+    /// (e.g. `[libc.so.6 <0x7f0012345678>]` rather than `[libc.so.6]`).
+    ///
+    /// Default is `false`.
+    pub include_addrs: bool,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
+}
+
+/// Which of a sample's values to use as the weight of its stack.
+///
+/// A pprof profile can record several values per sample (e.g. both the number of samples and
+/// the CPU time they represent), each described by one of the profile's sample types.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub enum SampleIndex {
+    /// The profile's default sample type, or its last sample type if it does not name one.
+    /// This matches the behavior of `go tool pprof`.
+    #[default]
+    Default,
+    /// The value at the given (zero-based) index.
+    Index(usize),
+    /// The value whose sample type has the given name (e.g. `alloc_space`).
+    Name(String),
+}
+
+/// A stack collapser for [pprof](https://github.com/google/pprof) protobuf profiles.
+///
+/// Both gzip-compressed and uncompressed profiles are supported.
+///
+/// To construct one, either use `pprof::Folder::default()` or create an [`Options`] and use
+/// `pprof::Folder::from(options)`.
+#[derive(Clone, Default)]
+pub struct Folder {
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder { opt }
+    }
+}
+
+impl Collapse for Folder {
+    fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
+    where
+        R: io::BufRead,
+        W: io::Write,
+    {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        if data.is_empty() {
+            warn!("File ended before start of profile");
+            return Ok(());
+        }
+
+        if data.starts_with(&GZIP_MAGIC) {
+            let mut decompressed = Vec::new();
+            libflate::gzip::Decoder::new(&data[..])?.read_to_end(&mut decompressed)?;
+            data = decompressed;
+        }
+
+        let profile = Profile::parse(&data)?;
+        let index = self.sample_index(&profile)?;
+        if let Some(&(ty, unit)) = profile.sample_types.get(index) {
+            info!(
+                "Using sample type {}/{}",
+                profile.string(ty),
+                profile.string(unit)
+            );
+        }
+
+        // Resolve every location to its (root-first) frames once, rather than once per sample.
+        let mut frames = AHashMap::with_capacity(profile.locations.len());
+        for (&id, location) in &profile.locations {
+            frames.insert(id, self.location_frames(&profile, location));
+        }
+
+        let mut occurrences = Occurrences::new(1);
+        occurrences.set_demangle(self.opt.demangle);
+        let mut stack = String::new();
+        for sample in &profile.samples {
+            let value = match sample.values.get(index) {
+                Some(&value) if value > 0 => Count::from(value as u64),
+                _ => continue,
+            };
+
+            stack.clear();
+            // Location ids are stored leaf-first.
+            for id in sample.location_ids.iter().rev() {
+                let frame = match frames.get(id) {
+                    Some(frame) => frame,
+                    None => return invalid_data_error!("Sample refers to unknown location {}", id),
+                };
+                if !stack.is_empty() {
+                    stack.push(';');
+                }
+                stack.push_str(frame);
+            }
+
+            if !stack.is_empty() {
+                occurrences.insert_or_add(stack.clone(), value);
+            }
+        }
+
+        occurrences.write_and_clear(writer)
+    }
+
+    /// Check for a pprof profile.
+    ///
+    /// pprof profiles are binary, so this is only meaningful for the raw bytes of the input.
+    /// Gzip-compressed input is assumed to be a profile.
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        is_pprof(input.as_bytes())
+    }
+}
+
+impl Folder {
+    fn sample_index(&self, profile: &Profile) -> io::Result<usize> {
+        let ntypes = profile.sample_types.len();
+        match self.opt.sample_index {
+            SampleIndex::Default => {
+                if profile.default_sample_type != 0 {
+                    if let Some(index) = profile
+                        .sample_types
+                        .iter()
+                        .position(|&(ty, _)| ty == profile.default_sample_type)
+                    {
+                        return Ok(index);
+                    }
+                }
+                Ok(ntypes.saturating_sub(1))
+            }
+            SampleIndex::Index(index) if index < ntypes => Ok(index),
+            SampleIndex::Index(index) => invalid_data_error!(
+                "Sample index {} is out of range; the profile has {} sample types",
+                index,
+                ntypes
+            ),
+            SampleIndex::Name(ref name) => {
+                match profile
+                    .sample_types
+                    .iter()
+                    .position(|&(ty, _)| profile.string(ty) == name)
+                {
+                    Some(index) => Ok(index),
+                    None => invalid_data_error!("Profile has no sample type named '{}'", name),
+                }
+            }
+        }
+    }
+
+    fn location_frames(&self, profile: &Profile, location: &Location) -> String {
+        let mut frames = String::new();
+
+        // Lines are stored innermost first; the last one is the function the location
+        // was inlined into.
+        for function_id in location.function_ids.iter().rev() {
+            let name = match profile.functions.get(function_id) {
+                Some(function) if !profile.string(function.name).is_empty() => {
+                    profile.string(function.name)
+                }
+                Some(function) if !profile.string(function.system_name).is_empty() => {
+                    profile.string(function.system_name)
+                }
+                _ => "[unknown]",
+            };
+            if !frames.is_empty() {
+                frames.push(';');
+            }
+            frames.push_str(&name.replace(';', ":"));
+        }
+
+        if frames.is_empty() {
+            // This location was never symbolized, so fall back to the name of its module.
+            let module = profile
+                .mappings
+                .get(&location.mapping_id)
+                .map(|&filename| profile.string(filename))
+                .filter(|filename| !filename.is_empty())
+                .map(|filename| &filename[filename.rfind('/').map(|i| i + 1).unwrap_or(0)..])
+                .unwrap_or("unknown");
+            frames.push('[');
+            frames.push_str(&module.replace(';', ":"));
+            if self.opt.include_addrs {
+                frames.push_str(&format!(" <{:#x}>", location.address));
+            }
+            frames.push(']');
+        }
+
+        frames
+    }
+}
+
+/// Check whether the given bytes look like the start of a pprof profile.
+///
+/// Returns `None` if there are not enough bytes to tell.
+pub(crate) fn is_pprof(bytes: &[u8]) -> Option<bool> {
+    if bytes.len() < GZIP_MAGIC.len() {
+        return None;
+    }
+    if bytes.starts_with(&GZIP_MAGIC) {
+        return Some(true);
+    }
+
+    // Uncompressed profiles start with their sample types: a length-delimited field 1 holding
+    // a `ValueType` message, which in turn only contains varint fields 1 (type) and 2 (unit).
+    if bytes[0] != 0x0a {
+        return Some(false);
+    }
+    let mut rest = &bytes[1..];
+    let len = match read_varint(&mut rest) {
+        Ok(len) => len as usize,
+        Err(_) => return None,
+    };
+    if len == 0 {
+        return Some(false);
+    }
+    for field in Fields::new(rest.get(..len)?) {
+        match field {
+            Ok((1 | 2, Value::Varint(_))) => {}
+            _ => return Some(false),
+        }
+    }
+    Some(true)
+}
+
+/// The parts of a decoded `perftools.profiles.Profile` that are needed to collapse its stacks.
+///
+/// See <https://github.com/google/pprof/blob/main/proto/profile.proto>.
+#[derive(Debug, Default)]
+struct Profile {
+    /// `(type, unit)` string table indices.
+    sample_types: Vec<(i64, i64)>,
+    samples: Vec<Sample>,
+    /// Mapping id to the string table index of its file name.
+    mappings: AHashMap<u64, i64>,
+    locations: AHashMap<u64, Location>,
+    functions: AHashMap<u64, Function>,
+    strings: Vec<String>,
+    default_sample_type: i64,
+}
+
+#[derive(Debug, Default)]
+struct Sample {
+    location_ids: Vec<u64>,
+    values: Vec<i64>,
+}
+
+#[derive(Debug, Default)]
+struct Location {
+    mapping_id: u64,
+    address: u64,
+    /// The function of each line, innermost first.
+    function_ids: Vec<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Function {
+    name: i64,
+    system_name: i64,
+}
+
+impl Profile {
+    fn parse(buf: &[u8]) -> io::Result<Self> {
+        let mut profile = Profile::default();
+        for field in Fields::new(buf) {
+            match field? {
+                (1, Value::Bytes(b)) => {
+                    let (mut ty, mut unit) = (0, 0);
+                    for field in Fields::new(b) {
+                        match field? {
+                            (1, v) => ty = v.int()? as i64,
+                            (2, v) => unit = v.int()? as i64,
+                            _ => {}
+                        }
+                    }
+                    profile.sample_types.push((ty, unit));
+                }
+                (2, Value::Bytes(b)) => {
+                    let mut sample = Sample::default();
+                    for field in Fields::new(b) {
+                        match field? {
+                            (1, v) => v.ints(&mut sample.location_ids)?,
+                            (2, v) => {
+                                let mut values = Vec::new();
+                                v.ints(&mut values)?;
+                                sample.values.extend(values.into_iter().map(|v| v as i64));
+                            }
+                            _ => {}
+                        }
+                    }
+                    profile.samples.push(sample);
+                }
+                (3, Value::Bytes(b)) => {
+                    let (mut id, mut filename) = (0, 0);
+                    for field in Fields::new(b) {
+                        match field? {
+                            (1, v) => id = v.int()?,
+                            (5, v) => filename = v.int()? as i64,
+                            _ => {}
+                        }
+                    }
+                    profile.mappings.insert(id, filename);
+                }
+                (4, Value::Bytes(b)) => {
+                    let mut id = 0;
+                    let mut location = Location::default();
+                    for field in Fields::new(b) {
+                        match field? {
+                            (1, v) => id = v.int()?,
+                            (2, v) => location.mapping_id = v.int()?,
+                            (3, v) => location.address = v.int()?,
+                            (4, Value::Bytes(line)) => {
+                                let mut function_id = 0;
+                                for field in Fields::new(line) {
+                                    if let (1, v) = field? {
+                                        function_id = v.int()?;
+                                    }
+                                }
+                                location.function_ids.push(function_id);
+                            }
+                            _ => {}
+                        }
+                    }
+                    profile.locations.insert(id, location);
+                }
+                (5, Value::Bytes(b)) => {
+                    let mut id = 0;
+                    let mut function = Function::default();
+                    for field in Fields::new(b) {
+                        match field? {
+                            (1, v) => id = v.int()?,
+                            (2, v) => function.name = v.int()? as i64,
+                            (3, v) => function.system_name = v.int()? as i64,
+                            _ => {}
+                        }
+                    }
+                    profile.functions.insert(id, function);
+                }
+                (6, Value::Bytes(b)) => profile
+                    .strings
+                    .push(String::from_utf8_lossy(b).into_owned()),
+                (14, v) => profile.default_sample_type = v.int()? as i64,
+                (1..=6, _) => {
+                    return invalid_data_error!("Unexpected wire type in pprof profile");
+                }
+                _ => {}
+            }
+        }
+        Ok(profile)
+    }
+
+    fn string(&self, index: i64) -> &str {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.strings.get(index))
+            .map(String::as_str)
+            .unwrap_or("")
+    }
+}
+
+/// A single field value as it is encoded in the protobuf wire format.
+#[derive(Clone, Copy, Debug)]
+enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> Value<'a> {
+    fn int(self) -> io::Result<u64> {
+        match self {
+            Value::Varint(v) | Value::Fixed64(v) => Ok(v),
+            Value::Fixed32(v) => Ok(u64::from(v)),
+            Value::Bytes(_) => invalid_data_error!("Expected an integer in pprof profile"),
+        }
+    }
+
+    /// Append the value(s) of a repeated integer field, which may or may not be packed.
+    fn ints(self, out: &mut Vec<u64>) -> io::Result<()> {
+        match self {
+            Value::Bytes(mut b) => {
+                while !b.is_empty() {
+                    out.push(read_varint(&mut b)?);
+                }
+            }
+            v => out.push(v.int()?),
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the `(field number, value)` pairs of an encoded protobuf message.
+struct Fields<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Fields { buf }
+    }
+
+    fn next_field(&mut self) -> io::Result<(u64, Value<'a>)> {
+        let key = read_varint(&mut self.buf)?;
+        let value = match key & 0x7 {
+            0 => Value::Varint(read_varint(&mut self.buf)?),
+            1 => Value::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            2 => {
+                let len = read_varint(&mut self.buf)?;
+                match usize::try_from(len) {
+                    Ok(len) => Value::Bytes(self.take(len)?),
+                    Err(_) => return invalid_data_error!("Field is too long: {} bytes", len),
+                }
+            }
+            5 => Value::Fixed32(u32::from_le_bytes(self.take(4)?.try_into().unwrap())),
+            wire_type => return invalid_data_error!("Unsupported wire type {}", wire_type),
+        };
+        Ok((key >> 3, value))
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return invalid_data_error!("Unexpected end of pprof profile");
+        }
+        let (taken, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(taken)
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = io::Result<(u64, Value<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let field = self.next_field();
+        if field.is_err() {
+            // Don't keep reading garbage after an error.
+            self.buf = &[];
+        }
+        Some(field)
+    }
+}
+
+fn read_varint(buf: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let Some((&byte, rest)) = buf.split_first() else {
+            return invalid_data_error!("Unexpected end of pprof profile");
+        };
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    invalid_data_error!("Varint is too long")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint() {
+        let mut buf: &[u8] = &[0x96, 0x01, 0x7f];
+        assert_eq!(read_varint(&mut buf).unwrap(), 150);
+        assert_eq!(read_varint(&mut buf).unwrap(), 127);
+        assert!(buf.is_empty());
+        assert!(read_varint(&mut &[0x80][..]).is_err());
+    }
+
+    #[test]
+    fn packed_and_unpacked_ints() {
+        let mut out = Vec::new();
+        Value::Bytes(&[0x01, 0x96, 0x01]).ints(&mut out).unwrap();
+        Value::Varint(7).ints(&mut out).unwrap();
+        assert_eq!(out, vec![1, 150, 7]);
+    }
+
+    fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn message(field: u64, contents: &[u8], out: &mut Vec<u8>) {
+        write_varint(field << 3 | 2, out);
+        write_varint(contents.len() as u64, out);
+        out.extend_from_slice(contents);
+    }
+
+    #[test]
+    fn values_above_u32_max() {
+        let mut sample = vec![0x08, 1, 0x10];
+        write_varint(5_000_000_000, &mut sample);
+        let mut location = vec![0x08, 1];
+        message(4, &[0x08, 1], &mut location);
+
+        let mut profile = Vec::new();
+        message(1, &[0x08, 1, 0x10, 2], &mut profile);
+        message(2, &sample, &mut profile);
+        message(4, &location, &mut profile);
+        message(5, &[0x08, 1, 0x10, 3], &mut profile);
+        for string in ["", "alloc_space", "bytes", "main"] {
+            message(6, string.as_bytes(), &mut profile);
+        }
+
+        let mut out = Vec::new();
+        Folder::default().collapse(&profile[..], &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "main 5000000000\n");
+    }
+
+    #[test]
+    fn detects_pprof() {
+        assert_eq!(is_pprof(&[0x1f, 0x8b, 0x08]), Some(true));
+        assert_eq!(is_pprof(&[0x0a, 0x04, 0x08, 0x01, 0x10, 0x02]), Some(true));
+        assert_eq!(is_pprof(&[0x0a, 0x04, 0x08]), None);
+        assert_eq!(is_pprof(b"main;foo 1\n"), Some(false));
+        assert_eq!(is_pprof(b"\n\x02ab"), Some(false));
+    }
+}
@@ -1,4 +1,5 @@
-use super::common::{self, CollapsePrivate};
+use super::common::{self, DemangleOptions, StackParser};
+use crate::folded::{self, FoldedLine};
 use std::{borrow::Cow, io};
 
 /// Recursive backtrace folder configuration options.
@@ -9,12 +10,18 @@ pub struct Options {
     ///
     /// Default is the number of logical cores on your machine.
     pub nthreads: usize,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             nthreads: *common::DEFAULT_NTHREADS,
+            demangle: None,
         }
     }
 }
@@ -49,7 +56,7 @@ impl Default for Folder {
     }
 }
 
-impl CollapsePrivate for Folder {
+impl StackParser for Folder {
     fn pre_process<R>(
         &mut self,
         _reader: &mut R,
@@ -72,8 +79,24 @@ impl CollapsePrivate for Folder {
     {
         for line in reader.lines() {
             let line = line?;
-            let (stack, count) = Self::line_parts(&line)
-                .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+            if folded::is_comment(&line) {
+                continue;
+            }
+            let (stack, count) = match FoldedLine::parse(&line) {
+                Some(FoldedLine {
+                    stack,
+                    count,
+                    before: None,
+                    ..
+                }) => (stack, count),
+                Some(_) => {
+                    return invalid_data_error!(
+                        "Differential stack lines are not supported: {}",
+                        line
+                    )
+                }
+                None => return invalid_data_error!("Unable to parse stack line: {}", line),
+            };
 
             occurrences.insert_or_add(Self::collapse_stack(stack.into()).into_owned(), count);
         }
@@ -110,14 +133,13 @@ impl CollapsePrivate for Folder {
     fn set_nthreads(&mut self, n: usize) {
         self.opt.nthreads = n;
     }
-}
 
-impl Folder {
-    fn line_parts(line: &str) -> Option<(&str, usize)> {
-        line.rsplit_once(' ')
-            .and_then(|(stack, count)| Some((stack, count.parse().ok()?)))
+    fn demangle_options(&self) -> Option<DemangleOptions> {
+        self.opt.demangle
     }
+}
 
+impl Folder {
     fn collapse_stack(stack: Cow<str>) -> Cow<str> {
         // First, determine whether we can avoid allocation by just returning
         // the original stack (in the case that there is no recursion, which is
@@ -131,7 +153,7 @@ impl Folder {
         let mut result = String::with_capacity(stack.len());
         let mut last = None;
         for frame in stack.split(';') {
-            if last.map_or(true, |l| l != frame) {
+            if last != Some(frame) {
                 result.push_str(frame);
                 result.push(';')
             }
@@ -152,13 +174,12 @@ impl Folder {
                 None => {
                     last = Some(current);
                 }
-                Some(l) => {
-                    if l == current {
-                        // Recursion!
-                        return true;
-                    } else {
-                        last = Some(current);
-                    }
+                Some(l) if l == current => {
+                    // Recursion!
+                    return true;
+                }
+                Some(_) => {
+                    last = Some(current);
                 }
             }
         }
@@ -188,14 +209,4 @@ mod test {
             "co;recursive;co;recursive"
         );
     }
-
-    #[test]
-    fn test_line_parts() {
-        assert_eq!(
-            Folder::line_parts("foo;bar;baz 42"),
-            Some(("foo;bar;baz", 42))
-        );
-        assert_eq!(Folder::line_parts(""), None);
-        assert_eq!(Folder::line_parts("no;number"), None);
-    }
 }
@@ -2,7 +2,7 @@ use std::io::{self, BufRead};
 
 use log::warn;
 
-use crate::collapse::common::{self, Occurrences};
+use crate::collapse::common::{self, DemangleOptions, Occurrences};
 use crate::collapse::Collapse;
 
 // The set of symbols to ignore for 'waiting' threads, for ease of use.
@@ -36,6 +36,11 @@ pub struct Options {
     ///
     /// Default is `false`.
     pub no_modules: bool,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
 }
 
 /// A stack collapser for the output of `sample` on macOS.
@@ -45,7 +50,7 @@ pub struct Options {
 #[derive(Clone, Default)]
 pub struct Folder {
     /// Number of samples for the current stack frame.
-    current_samples: usize,
+    current_samples: u64,
 
     /// Function on the stack in this entry thus far.
     stack: Vec<String>,
@@ -75,6 +80,7 @@ impl Collapse for Folder {
 
         // Process the data...
         let mut occurrences = Occurrences::new(1);
+        occurrences.set_demangle(self.opt.demangle);
         loop {
             line.clear();
             if reader.read_until(0x0A, &mut line)? == 0 {
@@ -208,7 +214,7 @@ impl Folder {
             }
 
             if let Some((samples, func, module)) = self.line_parts(&line[4 + indent_chars..]) {
-                if let Ok(samples) = samples.parse::<usize>() {
+                if let Ok(samples) = samples.parse::<u64>() {
                     // The sample counts of the direct children of a non-leaf entry will always
                     // add up to that node's sample count so we only need to keep track of the
                     // sample count at the top of the stack.
@@ -242,6 +248,6 @@ impl Folder {
                 }
             }
         }
-        occurrences.insert(self.stack.join(";"), self.current_samples);
+        occurrences.insert(self.stack.join(";"), self.current_samples.into());
     }
 }
@@ -0,0 +1,266 @@
+use std::io;
+
+use ahash::AHashMap;
+use log::warn;
+use serde::Deserialize;
+
+use crate::collapse::common::{DemangleOptions, Occurrences};
+use crate::collapse::Collapse;
+use crate::folded::Count;
+
+/// The value of the `$schema` key that identifies a speedscope file.
+const SCHEMA: &str = "https://www.speedscope.app/file-format-schema.json";
+
+/// `speedscope` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Add the name of each profile (typically a thread or process) as the root frame of its
+    /// stacks.
+    ///
+    /// Default is `false`.
+    pub include_profile_name: bool,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
+}
+
+/// A stack collapser for files in the [speedscope](https://www.speedscope.app) file format.
+///
+/// Both "sampled" and "evented" profiles are supported. Stacks of sampled profiles are weighted
+/// by their sample weights, and stacks of evented profiles by the time spent in them, both in
+/// the unit of the profile, which may be fractional. If the file contains several profiles,
+/// their stacks are merged.
+///
+/// To construct one, either use `speedscope::Folder::default()` or create an [`Options`] and use
+/// `speedscope::Folder::from(options)`.
+#[derive(Clone, Default)]
+pub struct Folder {
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder { opt }
+    }
+}
+
+impl Collapse for Folder {
+    fn collapse<R, W>(&mut self, mut reader: R, writer: W) -> io::Result<()>
+    where
+        R: io::BufRead,
+        W: io::Write,
+    {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        if data.iter().all(u8::is_ascii_whitespace) {
+            warn!("File ended before start of profile");
+            return Ok(());
+        }
+
+        let file: File = serde_json::from_slice(&data)?;
+        if file.schema.as_deref() != Some(SCHEMA) {
+            warn!("File does not declare the speedscope schema; trying to read it anyway");
+        }
+
+        let frames: Vec<String> = file
+            .shared
+            .frames
+            .iter()
+            .map(|frame| frame.name.replace(';', ":"))
+            .collect();
+
+        let mut weights: AHashMap<String, Count> = AHashMap::default();
+        for (i, profile) in file.profiles.iter().enumerate() {
+            let root = if self.opt.include_profile_name {
+                match profile.name() {
+                    "" => Some(format!("profile {}", i)),
+                    name => Some(name.replace(';', ":")),
+                }
+            } else {
+                None
+            };
+            match *profile {
+                Profile::Sampled {
+                    ref samples,
+                    weights: ref sample_weights,
+                    ..
+                } => collapse_sampled(samples, sample_weights, &frames, root, &mut weights)?,
+                Profile::Evented { ref events, .. } => {
+                    collapse_evented(events, &frames, root, &mut weights)?
+                }
+            }
+        }
+
+        let mut occurrences = Occurrences::new(1);
+        occurrences.set_demangle(self.opt.demangle);
+        for (stack, weight) in weights {
+            if weight > Count::ZERO {
+                occurrences.insert_or_add(stack, weight);
+            }
+        }
+        occurrences.write_and_clear(writer)
+    }
+
+    /// Check for the speedscope `$schema` key.
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        match input.trim_start().chars().next() {
+            None => None,
+            Some('{') if input.contains("\"$schema\"") => Some(input.contains(SCHEMA)),
+            Some('{') => None,
+            Some(_) => Some(false),
+        }
+    }
+}
+
+fn collapse_sampled(
+    samples: &[Vec<usize>],
+    sample_weights: &[f64],
+    frames: &[String],
+    root: Option<String>,
+    weights: &mut AHashMap<String, Count>,
+) -> io::Result<()> {
+    if samples.len() != sample_weights.len() {
+        return invalid_data_error!(
+            "Sampled profile has {} samples but {} weights",
+            samples.len(),
+            sample_weights.len()
+        );
+    }
+
+    let mut stack = String::new();
+    for (sample, &weight) in samples.iter().zip(sample_weights) {
+        let Some(weight) = Count::from_f64(weight) else {
+            return invalid_data_error!("Sample has invalid weight {}", weight);
+        };
+        stack.clear();
+        stack.extend(root.as_deref());
+        // Samples list their frames from the root down.
+        for &frame in sample {
+            let Some(frame) = frames.get(frame) else {
+                return invalid_data_error!("Sample refers to unknown frame {}", frame);
+            };
+            if !stack.is_empty() {
+                stack.push(';');
+            }
+            stack.push_str(frame);
+        }
+        if !sample.is_empty() {
+            *weights.entry(stack.clone()).or_default() += weight;
+        }
+    }
+    Ok(())
+}
+
+fn collapse_evented(
+    events: &[Event],
+    frames: &[String],
+    root: Option<String>,
+    weights: &mut AHashMap<String, Count>,
+) -> io::Result<()> {
+    let mut open: Vec<usize> = Vec::new();
+    let mut stack = String::new();
+    let mut last_at = None;
+    for event in events {
+        // Attribute the time since the previous event to the frames that were open during it.
+        if let Some(last_at) = last_at {
+            if !open.is_empty() && event.at > last_at {
+                stack.clear();
+                stack.extend(root.as_deref());
+                for &frame in &open {
+                    if !stack.is_empty() {
+                        stack.push(';');
+                    }
+                    stack.push_str(&frames[frame]);
+                }
+                let Some(duration) = Count::from_f64(event.at - last_at) else {
+                    return invalid_data_error!("Invalid event time {}", event.at);
+                };
+                *weights.entry(stack.clone()).or_default() += duration;
+            }
+        }
+        last_at = Some(event.at);
+
+        if event.frame >= frames.len() {
+            return invalid_data_error!("Event refers to unknown frame {}", event.frame);
+        }
+        match event.kind {
+            EventKind::Open => open.push(event.frame),
+            EventKind::Close => match open.pop() {
+                Some(frame) if frame == event.frame => {}
+                _ => {
+                    return invalid_data_error!(
+                        "Close event for frame {} at {} does not match the innermost open frame",
+                        event.frame,
+                        event.at
+                    )
+                }
+            },
+        }
+    }
+    if !open.is_empty() {
+        warn!("Evented profile ended with {} open frames", open.len());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct File {
+    #[serde(rename = "$schema")]
+    schema: Option<String>,
+    shared: Shared,
+    #[serde(default)]
+    profiles: Vec<Profile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Shared {
+    frames: Vec<Frame>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Frame {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Profile {
+    Sampled {
+        #[serde(default)]
+        name: String,
+        samples: Vec<Vec<usize>>,
+        weights: Vec<f64>,
+    },
+    Evented {
+        #[serde(default)]
+        name: String,
+        events: Vec<Event>,
+    },
+}
+
+impl Profile {
+    fn name(&self) -> &str {
+        match self {
+            Profile::Sampled { name, .. } | Profile::Evented { name, .. } => name,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Event {
+    #[serde(rename = "type")]
+    kind: EventKind,
+    frame: usize,
+    at: f64,
+}
+
+#[derive(Debug, Deserialize)]
+enum EventKind {
+    #[serde(rename = "O")]
+    Open,
+    #[serde(rename = "C")]
+    Close,
+}
@@ -2,15 +2,40 @@ use std::{cmp::Ordering, io};
 
 use log::warn;
 
-use crate::collapse::{common::Occurrences, Collapse};
+use crate::collapse::common::{DemangleOptions, Occurrences};
+use crate::collapse::Collapse;
 
 static START_LINE: &str = "Level,Function Name,Number of Calls,Elapsed Inclusive Time %,Elapsed Exclusive Time %,Avg Elapsed Inclusive Time,Avg Elapsed Exclusive Time,Module Name,";
 
+/// `vsprof` folder configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
+}
+
 /// A stack collapser for the output of the Visual Studio built in profiler.
+///
+/// To construct one, either use `vsprof::Folder::default()` or create an [`Options`] and use
+/// `vsprof::Folder::from(options)`.
 #[derive(Default)]
 pub struct Folder {
     /// Function entries on the stack in this entry thus far.
     stack: Vec<(String, usize)>,
+
+    opt: Options,
+}
+
+impl From<Options> for Folder {
+    fn from(opt: Options) -> Self {
+        Folder {
+            opt,
+            ..Default::default()
+        }
+    }
 }
 
 impl Collapse for Folder {
@@ -37,6 +62,7 @@ impl Collapse for Folder {
 
         // Process the data
         let mut occurences = Occurrences::new(1);
+        occurences.set_demangle(self.opt.demangle);
         loop {
             line.clear();
             if reader.read_until(b'\n', &mut line)? == 0 {
@@ -170,7 +196,7 @@ impl Folder {
     fn write_stack(&self, occurrences: &mut Occurrences) {
         if let Some(nsamples) = self.stack.last().map(|(_, n)| *n).filter(|n| *n > 0) {
             let functions: Vec<_> = self.stack.iter().map(|(f, _)| &f[..]).collect();
-            occurrences.insert(functions.join(";"), nsamples);
+            occurrences.insert(functions.join(";"), nsamples.into());
         }
     }
 }
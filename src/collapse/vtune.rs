@@ -2,8 +2,9 @@ use std::io::{self, BufRead};
 
 use log::warn;
 
-use crate::collapse::common::Occurrences;
+use crate::collapse::common::{DemangleOptions, Occurrences};
 use crate::collapse::Collapse;
+use crate::folded::Count;
 
 // The call graph begins after this line.
 static HEADER: &str = "Function Stack,CPU Time:Self,Module";
@@ -16,6 +17,11 @@ pub struct Options {
     ///
     /// Default is `false`.
     pub no_modules: bool,
+
+    /// Demangle the function names of frames, with these options.
+    ///
+    /// Default is `None`, which keeps the function names as they are.
+    pub demangle: Option<DemangleOptions>,
 }
 
 /// A stack collapser for CSV call graphs created with the VTune `amplxe-cl` tool.
@@ -52,6 +58,7 @@ impl Collapse for Folder {
 
         // Process the data...
         let mut occurrences = Occurrences::new(1);
+        occurrences.set_demangle(self.opt.demangle);
         loop {
             line.clear();
             if reader.read_until(0x0A, &mut line)? == 0 {
@@ -145,14 +152,17 @@ impl Folder {
             }
 
             if let Some((func, time, module)) = self.line_parts(&line[spaces..]) {
-                if let Ok(time) = time.parse::<f64>() {
-                    let time_ms = (time * 1000.0).round() as usize;
+                let time_ms = time
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|t| Count::from_f64(t * 1000.0));
+                if let Some(time_ms) = time_ms {
                     if module.is_empty() {
                         self.stack.push(func.to_string());
                     } else {
                         self.stack.push(format!("{}`{}", module, func));
                     }
-                    if time_ms > 0 {
+                    if time_ms > Count::ZERO {
                         self.write_stack(occurrences, time_ms);
                     }
                 } else {
@@ -166,7 +176,7 @@ impl Folder {
         Ok(())
     }
 
-    fn write_stack(&self, occurrences: &mut Occurrences, time: usize) {
+    fn write_stack(&self, occurrences: &mut Occurrences, time: Count) {
         occurrences.insert(self.stack.join(";"), time);
     }
 }
@@ -0,0 +1,13 @@
+/// Export to the [pprof](https://github.com/google/pprof) protobuf format.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod pprof;
+
+/// Export to the JSON file format of [speedscope](https://www.speedscope.app).
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../../index.html
+pub mod speedscope;
@@ -0,0 +1,260 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use ahash::AHashMap;
+
+use crate::folded::{Count, FoldedStacks};
+
+/// The sample type if none is given.
+const DEFAULT_SAMPLE_TYPE: &str = "samples";
+
+/// The unit of the sample counts if none is given.
+const DEFAULT_COUNT_NAME: &str = "count";
+
+/// `pprof` export configuration options.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Options {
+    /// What the sample counts measure (e.g. `cpu` or `alloc_space`).
+    ///
+    /// Default is `samples`.
+    pub sample_type: String,
+
+    /// The unit of the sample counts (e.g. `nanoseconds` or `bytes`). This is the pprof
+    /// counterpart of [`flamegraph::Options::count_name`](crate::flamegraph::Options::count_name).
+    ///
+    /// Default is `count`.
+    pub count_name: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            sample_type: DEFAULT_SAMPLE_TYPE.to_string(),
+            count_name: DEFAULT_COUNT_NAME.to_string(),
+        }
+    }
+}
+
+/// Write folded stacks as a gzip-compressed [pprof](https://github.com/google/pprof) profile
+/// (`profile.proto`).
+///
+/// Identical stacks are merged into one sample. Every distinct frame name becomes one function,
+/// with one location that refers to it, which all samples share.
+///
+/// If the stacks are [differential](FoldedStacks::is_differential), each sample has two values:
+/// the first sample count, as the sample type suffixed with `_before`, and the second sample
+/// count, as the sample type itself, which is also the default sample type of the profile.
+///
+/// Sample values in pprof profiles are integers, so fractional sample counts are rounded.
+pub fn from_stacks<W: Write>(opt: &Options, stacks: &FoldedStacks, writer: W) -> io::Result<()> {
+    let stacks = stacks.clone().merge();
+    let differential = stacks.is_differential();
+
+    let mut profile = Profile::new();
+    let sample_type = profile.string(&opt.sample_type);
+    let count_name = profile.string(&opt.count_name);
+    if differential {
+        let before = profile.string(&format!("{}_before", opt.sample_type));
+        profile.sample_types.push((before, count_name));
+    }
+    profile.sample_types.push((sample_type, count_name));
+    profile.default_sample_type = sample_type;
+
+    for stack in &stacks {
+        let mut values = Vec::with_capacity(2);
+        if differential {
+            values.push(sample_value(stack.before.unwrap_or_default()));
+        }
+        values.push(sample_value(stack.count));
+        if values.iter().all(|&v| v == 0) {
+            continue;
+        }
+
+        // Locations are stored leaf-first.
+        let mut location_ids: Vec<u64> = stack.frames().map(|f| profile.location(f)).collect();
+        location_ids.reverse();
+        profile.samples.push((location_ids, values));
+    }
+
+    let mut encoder = libflate::gzip::Encoder::new(writer)?;
+    encoder.write_all(&profile.encode())?;
+    encoder.finish().into_result()?.flush()
+}
+
+/// Read folded stacks from `reader` and write them as a gzip-compressed pprof profile.
+///
+/// See [`from_stacks`] for details.
+pub fn from_reader<R, W>(opt: &Options, reader: R, writer: W) -> io::Result<()>
+where
+    R: io::BufRead,
+    W: Write,
+{
+    from_stacks(opt, &FoldedStacks::from_reader(reader)?, writer)
+}
+
+/// Read folded stacks from files and write them as a gzip-compressed pprof profile.
+///
+/// If `files` is empty, or is just `-`, STDIN will be used as input. Stacks from several files
+/// are combined into one profile.
+pub fn from_files<W: Write>(opt: &Options, files: &[PathBuf], writer: W) -> io::Result<()> {
+    from_stacks(opt, &crate::folded::read_files(files)?, writer)
+}
+
+/// A sample count as a pprof sample value, rounded to a whole number.
+fn sample_value(count: Count) -> i64 {
+    i64::try_from(count.round()).unwrap_or(i64::MAX)
+}
+
+/// The parts of a `perftools.profiles.Profile` that folded stacks can fill in.
+///
+/// See <https://github.com/google/pprof/blob/main/proto/profile.proto>.
+#[derive(Debug, Default)]
+struct Profile {
+    /// `(type, unit)` string table indices.
+    sample_types: Vec<(i64, i64)>,
+    /// The location ids (leaf-first) and values of each sample.
+    samples: Vec<(Vec<u64>, Vec<i64>)>,
+    /// The string table index of the name of each function. The id of a function, and of the
+    /// one location that refers to it, is its index plus one.
+    functions: Vec<i64>,
+    /// Function name to function id.
+    function_ids: AHashMap<String, u64>,
+    strings: Vec<String>,
+    string_indices: AHashMap<String, i64>,
+    default_sample_type: i64,
+}
+
+impl Profile {
+    fn new() -> Self {
+        let mut profile = Profile::default();
+        // The first entry of the string table must be the empty string.
+        profile.string("");
+        profile
+    }
+
+    /// The string table index of `s`, adding it to the table if needed.
+    fn string(&mut self, s: &str) -> i64 {
+        if let Some(&index) = self.string_indices.get(s) {
+            return index;
+        }
+        let index = self.strings.len() as i64;
+        self.strings.push(s.to_string());
+        self.string_indices.insert(s.to_string(), index);
+        index
+    }
+
+    /// The id of the location of the function named `name`, adding both if needed.
+    fn location(&mut self, name: &str) -> u64 {
+        if let Some(&id) = self.function_ids.get(name) {
+            return id;
+        }
+        let name_index = self.string(name);
+        self.functions.push(name_index);
+        let id = self.functions.len() as u64;
+        self.function_ids.insert(name.to_string(), id);
+        id
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut message = Vec::new();
+        for &(ty, unit) in &self.sample_types {
+            message.clear();
+            write_int_field(&mut message, 1, ty as u64);
+            write_int_field(&mut message, 2, unit as u64);
+            write_bytes_field(&mut buf, 1, &message);
+        }
+        for (location_ids, values) in &self.samples {
+            message.clear();
+            write_packed_field(&mut message, 1, location_ids.iter().copied());
+            write_packed_field(&mut message, 2, values.iter().map(|&v| v as u64));
+            write_bytes_field(&mut buf, 2, &message);
+        }
+        let mut line = Vec::new();
+        for id in 1..=self.functions.len() as u64 {
+            line.clear();
+            write_int_field(&mut line, 1, id);
+            message.clear();
+            write_int_field(&mut message, 1, id);
+            write_bytes_field(&mut message, 4, &line);
+            write_bytes_field(&mut buf, 4, &message);
+        }
+        for (id, &name) in (1..).zip(&self.functions) {
+            message.clear();
+            write_int_field(&mut message, 1, id);
+            write_int_field(&mut message, 2, name as u64);
+            write_int_field(&mut message, 3, name as u64);
+            write_bytes_field(&mut buf, 5, &message);
+        }
+        for s in &self.strings {
+            write_bytes_field(&mut buf, 6, s.as_bytes());
+        }
+        write_int_field(&mut buf, 14, self.default_sample_type as u64);
+        buf
+    }
+}
+
+/// Write a varint field, leaving it out if it has the default value of zero.
+fn write_int_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    if value != 0 {
+        write_varint(buf, field << 3);
+        write_varint(buf, value);
+    }
+}
+
+/// Write a length-delimited field.
+fn write_bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(buf, field << 3 | 2);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Write a packed repeated varint field.
+fn write_packed_field<I>(buf: &mut Vec<u8>, field: u64, values: I)
+where
+    I: IntoIterator<Item = u64>,
+{
+    let mut packed = Vec::new();
+    for value in values {
+        write_varint(&mut packed, value);
+    }
+    if !packed.is_empty() {
+        write_bytes_field(buf, field, &packed);
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint() {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 150);
+        write_varint(&mut buf, 127);
+        assert_eq!(buf, vec![0x96, 0x01, 0x7f]);
+    }
+
+    #[test]
+    fn deduplicates_functions_and_strings() {
+        let mut profile = Profile::new();
+        let main = profile.location("main");
+        let foo = profile.location("foo");
+        assert_eq!(profile.location("main"), main);
+        assert_ne!(main, foo);
+        assert_eq!(profile.functions.len(), 2);
+
+        let samples = profile.string("samples");
+        assert_eq!(profile.string("samples"), samples);
+        assert_eq!(profile.strings, vec!["", "main", "foo", "samples"]);
+    }
+}
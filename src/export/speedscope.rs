@@ -0,0 +1,263 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use ahash::AHashMap;
+use serde::Serialize;
+
+use crate::folded::{Count, FoldedStacks};
+
+/// The value of the `$schema` key that identifies a speedscope file.
+const SCHEMA: &str = "https://www.speedscope.app/file-format-schema.json";
+
+/// The name of the profile if none is given.
+const DEFAULT_NAME: &str = "inferno";
+
+/// `speedscope` export configuration options.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// The name of the profile, as shown by speedscope.
+    ///
+    /// Default is `None`, which names the profile "inferno".
+    pub name: Option<String>,
+
+    /// The unit of the sample counts.
+    ///
+    /// Default is [`Unit::None`].
+    pub unit: Unit,
+
+    /// Keep the stacks in the order of the input, and don't merge identical stacks, so that
+    /// speedscope's "Time Order" view shows them as a timeline. This is the counterpart of the
+    /// flame chart mode of [`flamegraph`](crate::flamegraph::Options::flame_chart).
+    ///
+    /// Default is `false`, which merges identical stacks and sorts them.
+    pub flame_chart: bool,
+}
+
+/// The unit of the sample counts of a speedscope profile.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Unit {
+    /// Sample counts without a unit.
+    #[default]
+    None,
+    /// Nanoseconds.
+    Nanoseconds,
+    /// Microseconds.
+    Microseconds,
+    /// Milliseconds.
+    Milliseconds,
+    /// Seconds.
+    Seconds,
+    /// Bytes.
+    Bytes,
+}
+
+impl Unit {
+    /// The valid set of units (via `FromStr`).
+    pub const VARIANTS: &'static [&'static str] = &[
+        "none",
+        "nanoseconds",
+        "microseconds",
+        "milliseconds",
+        "seconds",
+        "bytes",
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Unit::None => "none",
+            Unit::Nanoseconds => "nanoseconds",
+            Unit::Microseconds => "microseconds",
+            Unit::Milliseconds => "milliseconds",
+            Unit::Seconds => "seconds",
+            Unit::Bytes => "bytes",
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "none" => Unit::None,
+            "nanoseconds" => Unit::Nanoseconds,
+            "microseconds" => Unit::Microseconds,
+            "milliseconds" => Unit::Milliseconds,
+            "seconds" => Unit::Seconds,
+            "bytes" => Unit::Bytes,
+            _ => return Err(format!("unknown unit: {}", s)),
+        })
+    }
+}
+
+/// Write folded stacks as a [speedscope](https://www.speedscope.app) file with a "sampled"
+/// profile.
+///
+/// Each stack becomes one sample, weighted by its sample count. Frames with the same name share
+/// an entry in the frame table of the file.
+///
+/// If the stacks are [differential](FoldedStacks::is_differential), the file has two profiles:
+/// one with the first sample counts (named "before"), and one with the second sample counts
+/// (named "after").
+pub fn from_stacks<W: Write>(opt: &Options, stacks: &FoldedStacks, writer: W) -> io::Result<()> {
+    let name = opt.name.as_deref().unwrap_or(DEFAULT_NAME);
+    let merged;
+    let stacks = if opt.flame_chart {
+        stacks
+    } else {
+        merged = stacks.clone().merge();
+        &merged
+    };
+
+    let mut frames = Frames::default();
+    let samples: Vec<Vec<usize>> = stacks
+        .iter()
+        .map(|s| s.frames().map(|f| frames.index(f)).collect())
+        .collect();
+
+    let profiles = if stacks.is_differential() {
+        vec![
+            Profile::new(
+                format!("{} (before)", name),
+                opt.unit,
+                &samples,
+                stacks.iter().map(|s| s.before.unwrap_or_default()),
+            ),
+            Profile::new(
+                format!("{} (after)", name),
+                opt.unit,
+                &samples,
+                stacks.iter().map(|s| s.count),
+            ),
+        ]
+    } else {
+        vec![Profile::new(
+            name.to_string(),
+            opt.unit,
+            &samples,
+            stacks.iter().map(|s| s.count),
+        )]
+    };
+
+    let file = File {
+        schema: SCHEMA,
+        shared: Shared {
+            frames: frames.names.iter().map(|&name| Frame { name }).collect(),
+        },
+        profiles,
+        name,
+        active_profile_index: 0,
+        exporter: concat!("inferno ", env!("CARGO_PKG_VERSION")),
+    };
+    let mut writer = writer;
+    serde_json::to_writer(&mut writer, &file)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+/// Read folded stacks from `reader` and write them as a speedscope file.
+///
+/// See [`from_stacks`] for details.
+pub fn from_reader<R, W>(opt: &Options, reader: R, writer: W) -> io::Result<()>
+where
+    R: io::BufRead,
+    W: Write,
+{
+    from_stacks(opt, &FoldedStacks::from_reader(reader)?, writer)
+}
+
+/// Read folded stacks from files and write them as a speedscope file.
+///
+/// If `files` is empty, or is just `-`, STDIN will be used as input. Stacks from several files
+/// are combined into one profile.
+pub fn from_files<W: Write>(opt: &Options, files: &[PathBuf], writer: W) -> io::Result<()> {
+    from_stacks(opt, &crate::folded::read_files(files)?, writer)
+}
+
+/// The frame table of the file, which gives every distinct frame name an index.
+#[derive(Debug, Default)]
+struct Frames<'a> {
+    names: Vec<&'a str>,
+    indices: AHashMap<&'a str, usize>,
+}
+
+impl<'a> Frames<'a> {
+    fn index(&mut self, name: &'a str) -> usize {
+        let next = self.names.len();
+        *self.indices.entry(name).or_insert_with(|| {
+            self.names.push(name);
+            next
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct File<'a> {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    shared: Shared<'a>,
+    profiles: Vec<Profile<'a>>,
+    name: &'a str,
+    active_profile_index: usize,
+    exporter: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct Shared<'a> {
+    frames: Vec<Frame<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Frame<'a> {
+    name: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Profile<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: String,
+    unit: Unit,
+    start_value: Count,
+    end_value: Count,
+    samples: Vec<&'a [usize]>,
+    weights: Vec<Count>,
+}
+
+impl<'a> Profile<'a> {
+    /// A sampled profile of the stacks with the given weights, leaving out stacks that weigh
+    /// nothing.
+    fn new<I>(name: String, unit: Unit, stacks: &'a [Vec<usize>], weights: I) -> Self
+    where
+        I: IntoIterator<Item = Count>,
+    {
+        let (samples, weights): (Vec<&[usize]>, Vec<Count>) = stacks
+            .iter()
+            .zip(weights)
+            .filter(|(_, weight)| *weight > Count::ZERO)
+            .map(|(stack, weight)| (stack.as_slice(), weight))
+            .unzip();
+        Profile {
+            kind: "sampled",
+            name,
+            unit,
+            start_value: Count::ZERO,
+            end_value: weights.iter().copied().sum(),
+            samples,
+            weights,
+        }
+    }
+}
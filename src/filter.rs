@@ -0,0 +1,183 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::folded::FoldedStacks;
+
+/// `filter` configuration options.
+///
+/// The options are applied in the order in which they are listed here: whole stacks are kept or
+/// dropped first, based on all of their frames, and only then are frames dropped and stacks
+/// truncated.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Only keep the stacks that match one of these regular expressions.
+    ///
+    /// The expressions are matched against the semicolon-separated frames of a stack, without its
+    /// sample counts, so `^swapper;` matches stacks whose root frame is `swapper`.
+    ///
+    /// Default is empty, which keeps all stacks.
+    pub keep: Vec<Regex>,
+
+    /// Drop the stacks that match one of these regular expressions.
+    ///
+    /// Like `keep`, the expressions are matched against the semicolon-separated frames of a stack.
+    ///
+    /// Default is empty.
+    pub drop: Vec<Regex>,
+
+    /// Drop the frames whose name matches one of these regular expressions from every stack.
+    ///
+    /// Stacks that are left without any frames are dropped.
+    ///
+    /// Default is empty.
+    pub drop_frames: Vec<Regex>,
+
+    /// Make the innermost frame whose name matches this regular expression the root of its stack,
+    /// by removing the frames of its callers.
+    ///
+    /// This is the regular expression counterpart of
+    /// [`perf::Options::skip_after`](crate::collapse::perf::Options::skip_after). Stacks without a
+    /// matching frame are kept as they are.
+    ///
+    /// Default is `None`.
+    pub root_at: Option<Regex>,
+
+    /// Make the outermost frame whose name matches this regular expression the leaf of its stack,
+    /// by removing the frames of its callees.
+    ///
+    /// Stacks without a matching frame are kept as they are.
+    ///
+    /// Default is `None`.
+    pub leaf_at: Option<Regex>,
+
+    /// Only keep this many frames of each stack, starting at the root. The samples of the removed
+    /// frames are counted for the innermost frame that is kept.
+    ///
+    /// Default is `None`, which keeps all frames.
+    pub max_depth: Option<usize>,
+}
+
+impl Options {
+    /// Whether the options change the frames of stacks, instead of only keeping or dropping them.
+    fn changes_frames(&self) -> bool {
+        !self.drop_frames.is_empty()
+            || self.root_at.is_some()
+            || self.leaf_at.is_some()
+            || self.max_depth.is_some()
+    }
+}
+
+/// Keep, drop and truncate stacks as configured by `opt`.
+///
+/// If the frames of stacks are changed, stacks may become identical, and are then merged into one
+/// stack, summing up their sample counts. The merged stacks are sorted. Otherwise, the stacks
+/// that are kept stay in their order. Both sample counts of
+/// [differential](FoldedStacks::is_differential) stacks are kept.
+pub fn filter(opt: &Options, stacks: FoldedStacks) -> FoldedStacks {
+    let stacks = stacks.filter(|s| {
+        (opt.keep.is_empty() || opt.keep.iter().any(|re| re.is_match(&s.stack)))
+            && !opt.drop.iter().any(|re| re.is_match(&s.stack))
+    });
+    if !opt.changes_frames() {
+        return stacks;
+    }
+
+    stacks
+        .map_stacks(|stack| {
+            let mut frames: Vec<&str> = stack
+                .split(';')
+                .filter(|frame| !opt.drop_frames.iter().any(|re| re.is_match(frame)))
+                .collect();
+            if let Some(root) = opt
+                .root_at
+                .as_ref()
+                .and_then(|re| frames.iter().rposition(|frame| re.is_match(frame)))
+            {
+                frames.drain(..root);
+            }
+            if let Some(leaf) = opt
+                .leaf_at
+                .as_ref()
+                .and_then(|re| frames.iter().position(|frame| re.is_match(frame)))
+            {
+                frames.truncate(leaf + 1);
+            }
+            if let Some(max_depth) = opt.max_depth {
+                frames.truncate(max_depth);
+            }
+            frames.join(";")
+        })
+        .filter(|s| !s.stack.is_empty())
+        .merge()
+}
+
+/// Filter folded stacks and write the stacks that are left.
+///
+/// See [`filter`] for details.
+pub fn from_stacks<W: Write>(
+    opt: &Options,
+    stacks: &FoldedStacks,
+    mut writer: W,
+) -> io::Result<()> {
+    filter(opt, stacks.clone()).write_to(&mut writer)?;
+    writer.flush()
+}
+
+/// Read folded stacks from `reader`, filter them, and write the stacks that are left.
+///
+/// See [`filter`] for details.
+pub fn from_reader<R, W>(opt: &Options, reader: R, mut writer: W) -> io::Result<()>
+where
+    R: io::BufRead,
+    W: Write,
+{
+    filter(opt, FoldedStacks::from_reader(reader)?).write_to(&mut writer)?;
+    writer.flush()
+}
+
+/// Read folded stacks from files, filter them, and write the stacks that are left.
+///
+/// If `files` is empty, or is just `-`, STDIN will be used as input. Stacks from several files
+/// are combined into one output.
+pub fn from_files<W: Write>(opt: &Options, files: &[PathBuf], mut writer: W) -> io::Result<()> {
+    filter(opt, crate::folded::read_files(files)?).write_to(&mut writer)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filtered(opt: &Options, input: &str) -> String {
+        filter(opt, FoldedStacks::from_str_lossy(input)).to_string()
+    }
+
+    #[test]
+    fn truncate_at_matching_frames() {
+        let input = "main;run;poll;work;poll;leaf 3\nmain;idle 1\n";
+        let opt = Options {
+            root_at: Some(Regex::new("^poll$").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(filtered(&opt, input), "main;idle 1\npoll;leaf 3\n");
+
+        let opt = Options {
+            leaf_at: Some(Regex::new("^poll$").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(filtered(&opt, input), "main;idle 1\nmain;run;poll 3\n");
+    }
+
+    #[test]
+    fn merge_after_dropping_frames() {
+        let input = "main;a;b 1 2\nmain;b 3 4\nmain;c 0 5\n";
+        let opt = Options {
+            drop_frames: vec![Regex::new("^a$").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(filtered(&opt, input), "main;b 4 6\nmain;c 0 5\n");
+    }
+}
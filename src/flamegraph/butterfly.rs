@@ -0,0 +1,104 @@
+use str_stack::StrStack;
+
+use super::merge::{self, Frame, TimedFrame};
+use super::Direction;
+
+/// Split the stacks that contain `function` into the stacks of its callers and its callees.
+///
+/// Both start at `function`: the callers go from it to the root of the stack, and the callees go
+/// from it to the leaf. In recursive stacks, the outermost call of `function` is the one that
+/// splits the stack, so that every sample is counted once on either side. The sample counts of each
+/// stack, including the `metrics` that follow the sample count (see [`merge::parse_line`]), are
+/// kept as they are.
+///
+/// Returns the number of lines that could not be parsed.
+pub(super) fn split<'a, I>(
+    function: &str,
+    lines: I,
+    callers: &mut StrStack,
+    callees: &mut StrStack,
+    metrics: usize,
+) -> usize
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut ignored = 0;
+    let mut stack = String::new();
+    for line in lines {
+        let Some((parsed, _)) = merge::parse_line(line, metrics) else {
+            ignored += 1;
+            continue;
+        };
+        let frames: Vec<&str> = parsed.stack.split(';').collect();
+        let Some(focus) = frames.iter().position(|&f| f == function) else {
+            continue;
+        };
+        let counts = &line[parsed.stack.len()..];
+
+        stack.clear();
+        for (i, func) in frames[..=focus].iter().rev().enumerate() {
+            if i != 0 {
+                stack.push(';');
+            }
+            stack.push_str(func);
+        }
+        stack.push_str(counts);
+        callers.push(&stack);
+
+        stack.clear();
+        stack.push_str(&frames[focus..].join(";"));
+        stack.push_str(counts);
+        callees.push(&stack);
+    }
+    ignored
+}
+
+/// Lay out the merged callers and callees of a function as one graph, with the callers growing
+/// up and the callees growing down from the row of the function.
+///
+/// Frames narrower than `minwidth_time` are left out. The depths of the returned frames are rows
+/// of the graph, counted the way `frame_y` counts depths in the given `direction`, and the second
+/// value is the row of the function.
+pub(super) fn layout<'a>(
+    direction: Direction,
+    callers: Vec<TimedFrame<'a>>,
+    callees: Vec<TimedFrame<'a>>,
+    minwidth_time: f64,
+) -> (Vec<TimedFrame<'a>>, usize) {
+    let wide_enough =
+        |frame: &TimedFrame<'_>| (frame.end_time - frame.start_time).as_f64() >= minwidth_time;
+    // Leave out the roots that merging adds to both sides, and the function itself on the side
+    // of the callers, so that it is drawn once.
+    let callers: Vec<_> = callers
+        .into_iter()
+        .filter(|frame| frame.location.depth > 1 && wide_enough(frame))
+        .collect();
+    let callees: Vec<_> = callees
+        .into_iter()
+        .filter(|frame| frame.location.depth > 0 && wide_enough(frame))
+        .collect();
+
+    let caller_depth = callers.iter().map(|f| f.location.depth).max().unwrap_or(1);
+    let callee_depth = callees.iter().map(|f| f.location.depth).max().unwrap_or(1);
+    let rows = caller_depth + callee_depth - 1;
+    let row = |row_from_top: usize| match direction {
+        Direction::Straight => rows - 1 - row_from_top,
+        Direction::Inverted => row_from_top,
+    };
+
+    let callers = callers.into_iter().map(|frame| TimedFrame {
+        location: Frame {
+            function: frame.location.function,
+            depth: row(caller_depth - frame.location.depth),
+        },
+        ..frame
+    });
+    let callees = callees.into_iter().map(|frame| TimedFrame {
+        location: Frame {
+            function: frame.location.function,
+            depth: row(caller_depth + frame.location.depth - 2),
+        },
+        ..frame
+    });
+    (callers.chain(callees).collect(), row(caller_depth - 1))
+}
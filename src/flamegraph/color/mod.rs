@@ -384,15 +384,15 @@ pub(super) fn color(
             hash ^= *byte as u64;
             hash = hash.wrapping_mul(0x100000001b3);
         }
-        let hash1 = (hash as f64 / std::u64::MAX as f64) as f32;
+        let hash1 = (hash as f64 / u64::MAX as f64) as f32;
 
         // Rotate hash so we get two more distinct numbers
         hash ^= 0;
         hash = hash.wrapping_mul(0x100000001b3);
-        let hash2 = (hash as f64 / std::u64::MAX as f64) as f32;
+        let hash2 = (hash as f64 / u64::MAX as f64) as f32;
         hash ^= 0;
         hash = hash.wrapping_mul(0x100000001b3);
-        let hash3 = (hash as f64 / std::u64::MAX as f64) as f32;
+        let hash3 = (hash as f64 / u64::MAX as f64) as f32;
 
         (hash1, hash2, hash3)
     } else {
@@ -402,8 +402,8 @@ pub(super) fn color(
     rgb_components_for_palette(palette, name, v1, v2, v3)
 }
 
-pub(super) fn color_scale(value: isize, max: usize) -> Color {
-    match value.cmp(&0) {
+pub(super) fn color_scale(value: f64, max: f64) -> Color {
+    match value.partial_cmp(&0.0).unwrap_or(Ordering::Equal) {
         Ordering::Equal => Color {
             r: 250,
             g: 250,
@@ -412,13 +412,13 @@ pub(super) fn color_scale(value: isize, max: usize) -> Color {
         Ordering::Greater => {
             // A positive value indicates _more_ samples,
             // and hence more time spent, so we give it a red hue.
-            let c = 100 + (150 * (max as isize - value) / max as isize) as u8;
+            let c = 100 + (150.0 * (max - value) / max) as u8;
             Color { r: 255, g: c, b: c }
         }
         Ordering::Less => {
             // A negative value indicates _fewer_ samples,
             // or a speed-up, so we give it a blue hue.
-            let c = 100 + (150 * (max as isize + value) / max as isize) as u8;
+            let c = 100 + (150.0 * (max + value) / max) as u8;
             Color { r: c, g: c, b: 255 }
         }
     }
@@ -489,7 +489,7 @@ mod tests {
 
     macro_rules! test_hash {
         ($name:expr, $expected:expr) => {
-            assert!((dbg!(namehash($name.bytes())) - $expected).abs() < std::f32::EPSILON);
+            assert!((dbg!(namehash($name.bytes())) - $expected).abs() < f32::EPSILON);
         };
     }
 
@@ -8,14 +8,18 @@ function init(evt) {
     svg = document.getElementsByTagName("svg")[0];
     frames = document.getElementById("frames");
     known_font_width = get_monospace_width(frames);
-    total_samples = parseInt(frames.attributes.total_samples.value);
+    total_samples = parseFloat(frames.attributes.total_samples.value);
     searching = 0;
 
     // Use GET parameters to restore a flamegraph's state.
     var restore_state = function() {
         var params = get_params();
-        if (params.x && params.y)
-            zoom(find_group(document.querySelector('[*|x="' + params.x + '"][y="' + params.y + '"]')));
+        if (params.x && params.y) {
+            // When the SVG is part of an HTML page, `fg:x` is not a namespaced attribute.
+            var frame = document.querySelector('[*|x="' + params.x + '"][y="' + params.y + '"]') ||
+                document.querySelector('[fg\\:x="' + params.x + '"][y="' + params.y + '"]');
+            zoom(find_group(frame));
+        }
         if (params.s)
             search(params.s);
     };
@@ -273,8 +277,8 @@ function update_text(e) {
 // zoom
 function zoom_reset(e) {
     if (e.tagName == "rect") {
-        e.attributes.x.value = format_percent(100 * parseInt(e.attributes["fg:x"].value) / total_samples);
-        e.attributes.width.value = format_percent(100 * parseInt(e.attributes["fg:w"].value) / total_samples);
+        e.attributes.x.value = format_percent(100 * parseFloat(e.attributes["fg:x"].value) / total_samples);
+        e.attributes.width.value = format_percent(100 * parseFloat(e.attributes["fg:w"].value) / total_samples);
     }
     if (e.childNodes == undefined) return;
     for(var i = 0, c = e.childNodes; i < c.length; i++) {
@@ -286,8 +290,8 @@ function zoom_child(e, x, zoomed_width_samples) {
         var parent_x = parseFloat(find_child(e.parentNode, "rect[x]").attributes.x.value);
         e.attributes.x.value = format_percent(parent_x + (100 * 3 / frames.attributes.width.value));
     } else if (e.tagName == "rect") {
-        e.attributes.x.value = format_percent(100 * (parseInt(e.attributes["fg:x"].value) - x) / zoomed_width_samples);
-        e.attributes.width.value = format_percent(100 * parseInt(e.attributes["fg:w"].value) / zoomed_width_samples);
+        e.attributes.x.value = format_percent(100 * (parseFloat(e.attributes["fg:x"].value) - x) / zoomed_width_samples);
+        e.attributes.width.value = format_percent(100 * parseFloat(e.attributes["fg:w"].value) / zoomed_width_samples);
     }
     if (e.childNodes == undefined) return;
     for(var i = 0, c = e.childNodes; i < c.length; i++) {
@@ -310,23 +314,32 @@ function zoom_parent(e) {
 }
 function zoom(node) {
     var attr = find_child(node, "rect").attributes;
-    var width = parseInt(attr["fg:w"].value);
-    var xmin = parseInt(attr["fg:x"].value);
+    var width = parseFloat(attr["fg:w"].value);
+    var xmin = parseFloat(attr["fg:x"].value);
     var xmax = xmin + width;
     var ymin = parseFloat(attr.y.value);
+    // In a butterfly graph, the callers grow up and the callees grow down from the focused
+    // function, and zooming into one side hides the other.
+    var focus_y = frames.attributes.focus_y ? parseFloat(frames.attributes.focus_y.value) : null;
+    var down = focus_y != null ? ymin > focus_y : inverted;
     unzoombtn.classList.remove("hide");
     var el = frames.children;
     var to_update_text = [];
     for (var i = 0; i < el.length; i++) {
         var e = el[i];
         var a = find_child(e, "rect").attributes;
-        var ex = parseInt(a["fg:x"].value);
-        var ew = parseInt(a["fg:w"].value);
+        var ex = parseFloat(a["fg:x"].value);
+        var ew = parseFloat(a["fg:w"].value);
+        var ey = parseFloat(a.y.value);
+        if (focus_y != null && (ey - focus_y) * (ymin - focus_y) < 0) {
+            e.classList.add("hide");
+            continue;
+        }
         // Is it an ancestor
-        if (!inverted) {
-            var upstack = parseFloat(a.y.value) > ymin;
+        if (!down) {
+            var upstack = ey > ymin;
         } else {
-            var upstack = parseFloat(a.y.value) < ymin;
+            var upstack = ey < ymin;
         }
         if (upstack) {
             // Direct ancestor
@@ -405,12 +418,12 @@ function search(term) {
         if (func == null || rect == null)
             continue;
         // Save max width. Only works as we have a root frame
-        var w = parseInt(rect.attributes["fg:w"].value);
+        var w = parseFloat(rect.attributes["fg:w"].value);
         if (w > maxwidth)
             maxwidth = w;
         if (func.match(re)) {
             // highlight
-            var x = parseInt(rect.attributes["fg:x"].value);
+            var x = parseFloat(rect.attributes["fg:x"].value);
             orig_save(rect, "fill");
             rect.attributes.fill.value = searchcolor;
             // remember matches
@@ -451,7 +464,7 @@ function search(term) {
     // thanks to the sort order. This relies on the tree property
     // where children are always smaller than their parents.
     for (var k in keys) {
-        var x = parseInt(keys[k]);
+        var x = parseFloat(keys[k]);
         var w = matches[keys[k]];
         if (x >= lastx + lastw) {
             count += w;
@@ -0,0 +1,76 @@
+"use strict";
+// The flame graph's own script is defined in the SVG above. The functions here replace some of
+// it to fit a page with a search box, and to keep the state in the URL hash rather than in the
+// query string, since the page may be served from places that don't pass the latter through.
+var searchinput = document.getElementById("search-input");
+function get_params() {
+    var params = {};
+    var paramsarr = window.location.hash.substr(1).split('&');
+    for (var i = 0; i < paramsarr.length; ++i) {
+        var tmp = paramsarr[i].split("=");
+        if (!tmp[0] || !tmp[1]) continue;
+        params[tmp[0]]  = decodeURIComponent(tmp[1]);
+    }
+    return params;
+}
+function parse_params(params) {
+    var uri = "#";
+    for (var key in params) {
+        uri += key + '=' + encodeURIComponent(params[key]) + '&';
+    }
+    if (uri.slice(-1) == "&")
+        uri = uri.substring(0, uri.length - 1);
+    if (uri == '#')
+        uri = window.location.href.split('#')[0];
+    return uri;
+}
+function clear_search() {
+    searchinput.classList.remove("invalid");
+    if (!searching) return;
+    reset_search();
+    searching = 0;
+    searchbtn.classList.remove("show");
+    searchbtn.firstChild.nodeValue = "Search";
+    matchedtxt.classList.add("hide");
+    matchedtxt.firstChild.nodeValue = "";
+}
+// The search button in the graph and ctrl-F go to the search box instead of prompting.
+function search_prompt() {
+    if (searching) {
+        searchinput.value = "";
+        clear_search();
+    } else {
+        searchinput.focus();
+    }
+}
+function search_for(term) {
+    clear_search();
+    if (!term) return;
+    try {
+        search(term);
+    } catch (e) {
+        // Not a valid regular expression.
+        searchinput.classList.add("invalid");
+    }
+}
+document.getElementById("controls").addEventListener("submit", function(e) {
+    e.preventDefault();
+    search_for(searchinput.value);
+});
+document.getElementById("reset").addEventListener("click", function() {
+    searchinput.value = "";
+    clear_search();
+    unzoom();
+    history.replaceState(null, null, parse_params({}));
+});
+// Clicking a function in the self time table searches for its frames.
+var rows = document.querySelectorAll("#self-time tbody tr");
+for (var i = 0; i < rows.length; i++) {
+    rows[i].addEventListener("click", function() {
+        var func = this.cells[0].textContent;
+        searchinput.value = "^" + func.replace(/[.*+?^${}()|[\]\\]/g, "\\$&") + " \\(";
+        search_for(searchinput.value);
+    });
+}
+init();
+searchinput.value = get_params().s || "";
@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+
+use ahash::AHashMap;
+use quick_xml::escape::escape;
+
+use super::merge::{self, TimedFrame};
+use super::{deannotate, format_count, Options};
+use crate::folded::Count;
+
+/// The number of functions listed in the table of self time below the flame graph.
+const TOP_SELF_FUNCTIONS: usize = 20;
+
+/// Find the functions that have the most samples of their own (i.e., not in their children),
+/// along with those samples.
+///
+/// This has to be done before narrow frames are pruned, so that the self samples of their parents
+/// don't include them.
+pub(super) fn top_self_time<'a>(frames: &[TimedFrame<'a>]) -> Vec<(&'a str, Count)> {
+    let (frames, parents) = merge::tree(frames);
+    let mut self_time: Vec<Count> = frames.iter().map(|f| f.end_time - f.start_time).collect();
+    for (i, parent) in parents.iter().enumerate() {
+        if let Some(parent) = *parent {
+            self_time[parent] -= frames[i].end_time - frames[i].start_time;
+        }
+    }
+
+    let mut functions: AHashMap<&'a str, Count> = AHashMap::default();
+    for (frame, samples) in frames.iter().zip(self_time) {
+        if frame.location.depth > 0 && samples > Count::ZERO {
+            *functions
+                .entry(deannotate(frame.location.function))
+                .or_default() += samples;
+        }
+    }
+    let mut functions: Vec<(&str, Count)> = functions.into_iter().collect();
+    functions.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    functions.truncate(TOP_SELF_FUNCTIONS);
+    functions
+}
+
+/// Write the start of the HTML page, up to where the flame graph goes.
+pub(super) fn write_head<W: Write>(writer: &mut W, opt: &Options<'_>) -> io::Result<()> {
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html>")?;
+    writeln!(writer, "<head>")?;
+    writeln!(writer, "<meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>{}</title>", escape(&opt.title))?;
+    writeln!(writer, "<style>")?;
+    writer.write_all(include_str!("html.css").as_bytes())?;
+    if opt.image_width.is_none() {
+        // Let the graph fill the width of the page.
+        writeln!(writer, "#graph > svg {{ width:100%; }}")?;
+    }
+    writeln!(writer, "</style>")?;
+    writeln!(writer, "</head>")?;
+    writeln!(writer, "<body>")?;
+    writeln!(writer, "<form id=\"controls\">")?;
+    writeln!(
+        writer,
+        "<input id=\"search-input\" type=\"search\" placeholder=\"Search (regexp allowed, eg: ^ext4_)\" autocomplete=\"off\">"
+    )?;
+    writeln!(writer, "<button type=\"submit\">Search</button>")?;
+    writeln!(
+        writer,
+        "<button type=\"button\" id=\"reset\">Reset</button>"
+    )?;
+    writeln!(writer, "</form>")?;
+    writeln!(writer, "<div id=\"graph\">")?;
+    Ok(())
+}
+
+/// Write the rest of the HTML page after the flame graph.
+///
+/// If `top_self_time` is `None`, the flame graph could not be drawn, and neither the table of self
+/// time nor the script to make the page interactive are written.
+pub(super) fn write_tail<W: Write>(
+    writer: &mut W,
+    opt: &Options<'_>,
+    top_self_time: Option<&[(&str, Count)]>,
+    total_samples: Count,
+) -> io::Result<()> {
+    writeln!(writer)?;
+    writeln!(writer, "</div>")?;
+
+    if let Some(top_self_time) = top_self_time {
+        let count_name = escape(&opt.count_name);
+        writeln!(writer, "<h2>Top self {}</h2>", count_name)?;
+        writeln!(writer, "<table id=\"self-time\">")?;
+        writeln!(
+            writer,
+            "<thead><tr><th>{}</th><th>Self {}</th><th>%</th></tr></thead>",
+            escape(opt.name_type.trim_end_matches(':')),
+            count_name
+        )?;
+        writeln!(writer, "<tbody>")?;
+        for &(function, samples) in top_self_time {
+            let pct = 100.0 * samples.as_f64() / total_samples.as_f64();
+            writeln!(
+                writer,
+                "<tr><td>{}</td><td>{}</td><td>{:.2}%</td></tr>",
+                escape(function),
+                format_count(opt, samples),
+                pct
+            )?;
+        }
+        writeln!(writer, "</tbody>")?;
+        writeln!(writer, "</table>")?;
+
+        if !opt.no_javascript {
+            writeln!(writer, "<script>")?;
+            writer.write_all(include_str!("html.js").as_bytes())?;
+            writeln!(writer, "</script>")?;
+        }
+    }
+
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")?;
+    Ok(())
+}
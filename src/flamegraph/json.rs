@@ -0,0 +1,117 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use super::merge::{self, TimedFrame};
+use super::Options;
+use crate::folded::{Count, Delta};
+
+/// A frame of the call tree, in the format expected by
+/// [d3-flame-graph](https://github.com/spiermar/d3-flame-graph).
+#[derive(Debug, Serialize)]
+struct Node<'a> {
+    name: &'a str,
+    /// The samples of this frame and all of its children.
+    value: Count,
+    /// The samples of this frame itself.
+    #[serde(rename = "self")]
+    self_value: Count,
+    /// The change of the samples of this frame itself, for differential input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delta: Option<Delta>,
+    /// The change of the samples of this frame and all of its children, for differential input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_delta: Option<Delta>,
+    children: Vec<Node<'a>>,
+}
+
+/// The call tree, before narrow frames are pruned.
+struct Tree<'f, 'a> {
+    frames: Vec<&'f TimedFrame<'a>>,
+    children: Vec<Vec<usize>>,
+    self_time: Vec<Count>,
+    /// The sum of the deltas of each frame and all of its children.
+    total_delta: Vec<Delta>,
+}
+
+/// Write the frames as a nested `{name, value, children}` tree rooted at the `all` frame.
+///
+/// The self samples of each frame are computed before narrow frames are pruned, so that they
+/// don't include the samples of the pruned children.
+pub(super) fn write_tree<W: Write>(
+    opt: &Options<'_>,
+    frames: &[TimedFrame<'_>],
+    minwidth_time: f64,
+    mut writer: W,
+) -> io::Result<()> {
+    let (frames, parents) = merge::tree(frames);
+    if frames.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No stack counts found",
+        ));
+    }
+
+    let mut tree = Tree {
+        children: vec![Vec::new(); frames.len()],
+        self_time: frames.iter().map(|f| f.end_time - f.start_time).collect(),
+        total_delta: frames.iter().map(|f| f.delta.unwrap_or_default()).collect(),
+        frames,
+    };
+    for (i, parent) in parents.iter().enumerate() {
+        if let Some(parent) = *parent {
+            tree.children[parent].push(i);
+            tree.self_time[parent] -= tree.frames[i].end_time - tree.frames[i].start_time;
+        }
+    }
+    // Children come after their parents, so walking backwards visits them first.
+    for (i, parent) in parents.iter().enumerate().rev() {
+        if let Some(parent) = *parent {
+            let delta = tree.total_delta[i];
+            tree.total_delta[parent] += delta;
+        }
+    }
+
+    let root = node(opt, &tree, 0, minwidth_time);
+    if opt.pretty_xml {
+        serde_json::to_writer_pretty(&mut writer, &root)?;
+    } else {
+        serde_json::to_writer(&mut writer, &root)?;
+    }
+    writeln!(writer)?;
+    writer.flush()
+}
+
+fn node<'a>(opt: &Options<'_>, tree: &Tree<'_, 'a>, index: usize, minwidth_time: f64) -> Node<'a> {
+    let frame = tree.frames[index];
+    let scale = |samples: Count| samples.scale(opt.factor);
+    let scale_delta = |delta: Delta| {
+        let delta = if opt.negate_differentials {
+            -delta
+        } else {
+            delta
+        };
+        delta.scale(opt.factor)
+    };
+
+    let name = if frame.location.depth == 0 {
+        "all"
+    } else {
+        frame.location.function
+    };
+    Node {
+        name,
+        value: scale(frame.end_time - frame.start_time),
+        self_value: scale(tree.self_time[index]),
+        delta: frame.delta.map(scale_delta),
+        total_delta: frame.delta.map(|_| scale_delta(tree.total_delta[index])),
+        children: tree.children[index]
+            .iter()
+            .filter(|&&c| {
+                let child = tree.frames[c];
+                (child.end_time - child.start_time).as_f64() >= minwidth_time
+            })
+            .map(|&c| node(opt, tree, c, minwidth_time))
+            .collect(),
+    }
+}
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::io;
 use std::iter;
 
-use log::warn;
+use crate::folded::{Count, Delta, FoldedLine, MetricsLine};
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub(super) struct Frame<'a> {
@@ -13,15 +13,36 @@ pub(super) struct Frame<'a> {
 #[derive(Debug, PartialEq)]
 pub(super) struct TimedFrame<'a> {
     pub(super) location: Frame<'a>,
-    pub(super) start_time: usize,
-    pub(super) end_time: usize,
-    pub(super) delta: Option<isize>,
+    pub(super) start_time: Count,
+    pub(super) end_time: Count,
+    pub(super) delta: Option<Delta>,
+    /// The sums of the metrics that are shown along with the width of the frame, if any.
+    pub(super) metrics: Vec<Count>,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub(super) struct FrameTime {
-    pub(super) start_time: usize,
-    pub(super) delta: Option<isize>,
+    pub(super) start_time: Count,
+    pub(super) delta: Option<Delta>,
+    pub(super) start_metrics: Vec<Count>,
+}
+
+/// Parse a line of input whose last `metrics` sample counts are metrics that are shown along
+/// with the width of frames, and whose sample count before them is the width.
+///
+/// Lines without such metrics may be differential, and are parsed as [`FoldedLine`]s.
+pub(super) fn parse_line(line: &str, metrics: usize) -> Option<(FoldedLine<'_>, Vec<Count>)> {
+    if metrics == 0 {
+        return FoldedLine::parse(line).map(|parsed| (parsed, Vec::new()));
+    }
+    let MetricsLine { stack, mut counts } = MetricsLine::parse(line, metrics + 1)?;
+    let count = counts.remove(0);
+    let parsed = FoldedLine {
+        stack,
+        count,
+        before: None,
+    };
+    Some((parsed, counts))
 }
 
 fn flow<'a, LI, TI>(
@@ -29,8 +50,9 @@ fn flow<'a, LI, TI>(
     frames: &mut Vec<TimedFrame<'a>>,
     last: LI,
     this: TI,
-    time: usize,
-    delta: Option<isize>,
+    time: Count,
+    delta: Option<Delta>,
+    metrics: &[Count],
 ) where
     LI: IntoIterator<Item = &'a str>,
     TI: IntoIterator<Item = &'a str>,
@@ -70,6 +92,11 @@ fn flow<'a, LI, TI>(
             start_time: frame_time.start_time,
             end_time: time,
             delta: frame_time.delta,
+            metrics: metrics
+                .iter()
+                .zip(frame_time.start_metrics)
+                .map(|(&end, start)| end - start)
+                .collect(),
         };
         frames.push(frame);
     }
@@ -84,7 +111,7 @@ fn flow<'a, LI, TI>(
 
         let is_last = this.peek().is_none();
         let delta = match delta {
-            Some(_) if !is_last => Some(0),
+            Some(_) if !is_last => Some(Delta::ZERO),
             d => d,
         };
         let frame_time = FrameTime {
@@ -92,6 +119,7 @@ fn flow<'a, LI, TI>(
             // For some reason the Perl version does a `+=` for `delta`, but I can't figure out why.
             // See https://github.com/brendangregg/FlameGraph/blob/1b1c6deede9c33c5134c920bdb7a44cc5528e9a7/flamegraph.pl#L588
             delta,
+            start_metrics: metrics.to_vec(),
         };
 
         //eprintln!("stored tmp for time {}: {:?}", time, key);
@@ -106,21 +134,27 @@ fn flow<'a, LI, TI>(
     }
 }
 
+/// Merge sorted lines into frames.
+///
+/// The last `metrics` sample counts of each line are summed up for each frame, as its
+/// [`metrics`](TimedFrame::metrics), and the sample count before them sets the width of frames
+/// (see [`parse_line`]).
 pub(super) fn frames<'a, I>(
     lines: I,
     suppress_sort_check: bool,
-) -> quick_xml::Result<(Vec<TimedFrame<'a>>, usize, usize, usize)>
+    metrics: usize,
+) -> quick_xml::Result<(Vec<TimedFrame<'a>>, Count, usize, Count)>
 where
     I: IntoIterator<Item = &'a str>,
 {
-    let mut time = 0;
+    let mut time = Count::ZERO;
     let mut ignored = 0;
     let mut last = "";
     let mut tmp = Default::default();
     let mut frames = Default::default();
     let mut delta = None;
-    let mut delta_max = 1;
-    let mut stripped_fractional_samples = false;
+    let mut delta_max = Count::ZERO;
+    let mut totals = vec![Count::ZERO; metrics];
     let mut prev_line = None;
     for line in lines {
         let mut line = line.trim();
@@ -140,25 +174,17 @@ where
         // Usually there will only be one samples column at the end of a line,
         // but for differentials there will be two. When there are two we compute the
         // delta between them and use the second one.
-        let nsamples =
-            if let Some(samples) = parse_nsamples(&mut line, &mut stripped_fractional_samples) {
-                // See if there's also a differential column present
-                if let Some(original_samples) =
-                    parse_nsamples(&mut line, &mut stripped_fractional_samples)
-                {
-                    delta = Some(samples as isize - original_samples as isize);
-                    delta_max = std::cmp::max(delta.unwrap().unsigned_abs(), delta_max);
-                }
-                samples
-            } else {
-                ignored += 1;
-                continue;
-            };
-
-        if line.is_empty() {
+        let Some((parsed, counts)) = parse_line(line, metrics) else {
             ignored += 1;
             continue;
+        };
+        if let Some(original_samples) = parsed.before {
+            let d = parsed.count.delta(original_samples);
+            delta = Some(d);
+            delta_max = std::cmp::max(d.magnitude(), delta_max);
         }
+        let nsamples = parsed.count;
+        line = parsed.stack;
         let stack = line;
 
         // inject empty first-level stack frame to capture "all"
@@ -166,7 +192,7 @@ where
         if last.is_empty() {
             // need to special-case this, because otherwise iter("") + "".split(';') == ["", ""]
             //eprintln!("flow(_, {}, {})", stack, time);
-            flow(&mut tmp, &mut frames, None, this, time, delta);
+            flow(&mut tmp, &mut frames, None, this, time, delta, &totals);
         } else {
             //eprintln!("flow({}, {}, {})", last, stack, time);
             flow(
@@ -176,11 +202,15 @@ where
                 this,
                 time,
                 delta,
+                &totals,
             );
         }
 
         last = stack;
         time += nsamples;
+        for (total, count) in totals.iter_mut().zip(counts) {
+            *total += count;
+        }
         prev_line = Some(line);
     }
 
@@ -193,70 +223,40 @@ where
             None,
             time,
             delta,
+            &totals,
         );
     }
 
     Ok((frames, time, ignored, delta_max))
 }
 
-// Parse and remove the number of samples from the end of a line.
-fn parse_nsamples(line: &mut &str, stripped_fractional_samples: &mut bool) -> Option<usize> {
-    if let Some((samplesi, doti)) = rfind_samples(line) {
-        let mut samples = &line[samplesi..];
-        // Strip fractional part (if any);
-        // foobar 1.klwdjlakdj
-        //
-        // The Perl version keeps the fractional part but this can be problematic
-        // because of cumulative floating point errors. Instead we recommend to
-        // use the --factor option. See https://github.com/brendangregg/FlameGraph/pull/18
-        //
-        // Warn if we're stripping a non-zero fractional part, but only the first time.
-        if !*stripped_fractional_samples
-            && doti < samples.len() - 1
-            && !samples[doti + 1..].chars().all(|c| c == '0')
-        {
-            *stripped_fractional_samples = true;
-            warn!(
-                "The input data has fractional sample counts that will be truncated to integers. \
-                 If you need to retain the extra precision you can scale up the sample data and \
-                 use the --factor option to scale it back down."
-            );
-        }
-        samples = &samples[..doti];
-        let nsamples = samples.parse::<usize>().ok()?;
-        // remove nsamples part we just parsed from line
-        *line = line[..samplesi].trim_end();
-        Some(nsamples)
-    } else {
-        None
-    }
-}
+/// Arrange frames into a call tree.
+///
+/// The frames are returned in an order in which every frame comes after its parent, along with the
+/// index of the parent of each frame (which is `None` only for the root frame). Empty frames are
+/// left out, since they can't contain anything.
+pub(super) fn tree<'a, 'f, I>(frames: I) -> (Vec<&'f TimedFrame<'a>>, Vec<Option<usize>>)
+where
+    I: IntoIterator<Item = &'f TimedFrame<'a>>,
+{
+    // Frames at the same depth don't overlap, so this puts parents before their children.
+    let mut sorted: Vec<&TimedFrame<'_>> = frames
+        .into_iter()
+        .filter(|frame| frame.end_time > frame.start_time)
+        .collect();
+    sorted.sort_unstable_by_key(|frame| (frame.start_time, frame.location.depth));
 
-// Tries to find a sample count at the end of a line.
-//
-// On success, the first value of the returned tuple will be the index to the sample count.
-// If the sample count is fractional, the second value will be the offset of the dot within
-// the sample count.
-// If the sample count is not fractional, the second value returned is the offset
-// to the last digit in the sample count.
-//
-// If no sample count is found, `None` will be returned.
-pub(super) fn rfind_samples(line: &str) -> Option<(usize, usize)> {
-    let samplesi = line.rfind(' ')? + 1;
-    let samples = &line[samplesi..];
-    if let Some(doti) = samples.find('.') {
-        if samples[..doti]
-            .chars()
-            .chain(samples[doti + 1..].chars())
-            .all(|c| c.is_ascii_digit())
-        {
-            Some((samplesi, doti))
-        } else {
-            None
+    let mut parents = Vec::with_capacity(sorted.len());
+    let mut path: Vec<usize> = Vec::new();
+    for (i, frame) in sorted.iter().enumerate() {
+        while let Some(&parent) = path.last() {
+            if sorted[parent].location.depth < frame.location.depth {
+                break;
+            }
+            path.pop();
         }
-    } else if !samples.chars().all(|c| c.is_ascii_digit()) {
-        None
-    } else {
-        Some((samplesi, line.len() - samplesi))
+        parents.push(path.last().copied());
+        path.push(i);
     }
+    (sorted, parents)
 }
@@ -7,22 +7,27 @@ macro_rules! args {
 #[cfg(feature = "nameattr")]
 mod attrs;
 
+mod butterfly;
 pub mod color;
+mod html;
+mod json;
 mod merge;
+#[cfg(feature = "png")]
+mod png;
 mod rand;
 mod svg;
+mod term;
 
-use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use std::io::{self, BufReader};
 use std::iter;
 use std::path::PathBuf;
 use std::str::FromStr;
 
 use log::{error, warn};
-use num_format::Locale;
 use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::Writer;
+use regex::Regex;
 use str_stack::StrStack;
 
 #[cfg(feature = "nameattr")]
@@ -34,6 +39,8 @@ pub use self::attrs::FuncFrameAttrsMap;
 pub use self::color::Palette;
 use self::color::{Color, SearchColor, StrokeColor};
 use self::svg::{Dimension, StyleOptions};
+use crate::folded::{self, Count, Delta, MetricsLine};
+use crate::input;
 
 const XPAD: usize = 10; // pad left and right
 const FRAMEPAD: usize = 1; // vertical padding for frames
@@ -212,19 +219,47 @@ pub struct Options<'a> {
 
     /// Factor to scale sample counts by in the flame graph.
     ///
-    /// This option can be useful if the sample data has fractional sample counts since the fractional
-    /// parts are stripped off when creating the flame graph. To work around this you can scale up the
-    /// sample counts to be integers, then scale them back down in the graph with the `factor` option.
-    ///
-    /// For example, if you have `23.4` as a sample count you can upscale it to `234`, then set `factor`
-    /// to `0.1`.
+    /// Fractional sample counts are kept as they are, so this is only needed to show the counts in
+    /// another unit than the one they were collected in. For example, if the sample counts are
+    /// microseconds, a `factor` of `0.001` shows them as milliseconds.
     ///
     /// [Default value](defaults::FACTOR).
     pub factor: f64,
 
+    /// How sample counts are formatted in tooltips and tables.
+    ///
+    /// Defaults to [`CountFormat::Auto`], which shows as many decimal places as the counts have.
+    pub count_format: CountFormat,
+
+    /// The metric of [multi-metric](folded::parse_metrics_header) input that sets the widths of
+    /// frames, and is named in tooltips instead of `count_name`.
+    ///
+    /// The other metrics, and the `ratios` between metrics, are shown in the tooltips of frames.
+    ///
+    /// Defaults to `None`, which is the first metric of the input.
+    pub metric: Option<String>,
+
+    /// Ratios between the metrics of multi-metric input to show in the tooltips of frames, such as
+    /// the instructions per cycle.
+    ///
+    /// Default is empty.
+    pub ratios: Vec<Ratio>,
+
     /// Pretty print XML with newlines and indentation.
+    ///
+    /// This also pretty prints JSON output.
     pub pretty_xml: bool,
 
+    /// The format to write the flame graph in.
+    ///
+    /// Defaults to an SVG image.
+    pub format: OutputFormat,
+
+    /// The colors the terminal can show, when writing the flame graph for a terminal.
+    ///
+    /// Defaults to 24-bit colors.
+    pub term_colors: TermColors,
+
     /// Don't sort the input lines.
     ///
     /// If you know for sure that your folded stack lines are sorted you can set this flag to get
@@ -241,6 +276,19 @@ pub struct Options<'a> {
     /// option will be ignored.
     pub reverse_stack_order: bool,
 
+    /// Generate a "butterfly" graph of the function with this name.
+    ///
+    /// The callers of the function are merged into an inverted graph above it, and its callees
+    /// into a normal graph below it, so that both show how the samples of the function are spread
+    /// across its call sites. Only stacks that contain the function are shown. In recursive stacks,
+    /// the outermost call of the function is the one that is focused on.
+    ///
+    /// The `reverse_stack_order`, `flame_chart` and `base` options are ignored in this mode, and
+    /// the graph can't be written in the JSON format.
+    ///
+    /// Defaults to `None`, which generates a normal flame graph.
+    pub butterfly: Option<String>,
+
     /// Don't include static JavaScript in flame graph.
     /// This is only meant to be used in tests.
     #[doc(hidden)]
@@ -261,6 +309,25 @@ pub struct Options<'a> {
 
     /// Base symbols
     pub base: Vec<String>,
+
+    /// Re-root every stack at a frame whose name matches this regular expression, and leave out
+    /// the stacks that have no such frame.
+    ///
+    /// The frames above the matching frame are removed, so that all occurrences of the matching
+    /// functions are merged into one subtree each, no matter where they were called from. Unlike
+    /// `base`, the expression matches any part of a frame name unless it is anchored with `^` and
+    /// `$`. Which match a stack is re-rooted at is set by `reroot_match`.
+    ///
+    /// Note that stack lines must always be sorted after re-rooting, so the `no_sort` option will
+    /// be ignored.
+    ///
+    /// Defaults to `None`, which leaves the stacks as they are.
+    pub reroot: Option<String>,
+
+    /// Which of the frames that match `reroot` to re-root a stack at.
+    ///
+    /// Defaults to the first match, which is the one closest to the root of the stack.
+    pub reroot_match: RerootMatch,
 }
 
 impl<'a> Options<'a> {
@@ -309,6 +376,9 @@ impl<'a> Default for Options<'a> {
             count_name: defaults::COUNT_NAME.to_string(),
             name_type: defaults::NAME_TYPE.to_string(),
             factor: defaults::FACTOR,
+            count_format: Default::default(),
+            metric: Default::default(),
+            ratios: Default::default(),
             image_width: Default::default(),
             notes: Default::default(),
             subtitle: Default::default(),
@@ -320,12 +390,17 @@ impl<'a> Default for Options<'a> {
             direction: Default::default(),
             negate_differentials: Default::default(),
             pretty_xml: Default::default(),
+            format: Default::default(),
+            term_colors: Default::default(),
             no_sort: Default::default(),
             reverse_stack_order: Default::default(),
+            butterfly: Default::default(),
             no_javascript: Default::default(),
             color_diffusion: Default::default(),
             flame_chart: Default::default(),
             base: Default::default(),
+            reroot: Default::default(),
+            reroot_match: Default::default(),
 
             #[cfg(feature = "nameattr")]
             func_frameattrs: Default::default(),
@@ -359,11 +434,311 @@ pub enum TextTruncateDirection {
     Right,
 }
 
+/// The format of the flame graph output.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// An interactive SVG image.
+    #[default]
+    Svg,
+
+    /// A JSON call tree of nested `{"name", "value", "children"}` objects, as used by
+    /// [d3-flame-graph](https://github.com/spiermar/d3-flame-graph).
+    ///
+    /// Besides the total samples of a frame in `value`, each object has the samples of the frame
+    /// itself in `self`. For differential input, `delta` and `total_delta` hold the change in
+    /// samples of the frame itself and of the frame including its children, respectively.
+    Json,
+
+    /// A self-contained HTML page with the interactive SVG image, a search box and a table of the
+    /// functions with the most samples of their own.
+    ///
+    /// Unlike the SVG image, the page keeps its zoom and search state in the hash part of its
+    /// URL. It works offline, and in viewers that strip scripts from SVG images.
+    Html,
+
+    /// Text with ANSI escape codes that draws the flame graph in a terminal, with one line per
+    /// stack depth and one column per character cell.
+    ///
+    /// [`Options::image_width`] is the width in columns. If it is `None`, the `COLUMNS`
+    /// environment variable is used, or 80 columns if that is not set either. Frames narrower
+    /// than one column are left out, and [`Options::term_colors`] picks the color escape codes.
+    Term,
+
+    /// A PNG image with the same frames, colors and text as the SVG image, but without the
+    /// interactive parts. [`Options::image_width`] is the width of the image in pixels.
+    ///
+    /// Text is drawn in an embedded monospace font (DejaVu Sans Mono) rather than
+    /// [`Options::font_type`]. Only available with the `png` feature.
+    #[cfg(feature = "png")]
+    Png,
+}
+
+impl OutputFormat {
+    /// The valid set of output formats (via `FromStr`).
+    #[cfg(not(feature = "png"))]
+    pub const VARIANTS: &'static [&'static str] = &["svg", "json", "html", "term"];
+
+    /// The valid set of output formats (via `FromStr`).
+    #[cfg(feature = "png")]
+    pub const VARIANTS: &'static [&'static str] = &["svg", "json", "html", "term", "png"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "svg" => Ok(OutputFormat::Svg),
+            "json" => Ok(OutputFormat::Json),
+            "html" => Ok(OutputFormat::Html),
+            "term" => Ok(OutputFormat::Term),
+            #[cfg(feature = "png")]
+            "png" => Ok(OutputFormat::Png),
+            unknown => Err(format!("unknown output format: {}", unknown)),
+        }
+    }
+}
+
+/// The colors a terminal can show, for [`OutputFormat::Term`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum TermColors {
+    /// 24-bit RGB colors.
+    #[default]
+    TrueColor,
+
+    /// The 256 colors of the xterm palette. Frame colors are rounded to the nearest color of its
+    /// 6x6x6 color cube.
+    Ansi256,
+}
+
+impl TermColors {
+    /// The valid set of terminal colors (via `FromStr`).
+    pub const VARIANTS: &'static [&'static str] = &["truecolor", "256"];
+}
+
+impl FromStr for TermColors {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "truecolor" | "24bit" => Ok(TermColors::TrueColor),
+            "256" => Ok(TermColors::Ansi256),
+            unknown => Err(format!("unknown terminal colors: {}", unknown)),
+        }
+    }
+}
+
+/// Which frame a stack is re-rooted at when several of its frames match
+/// [`Options::reroot`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum RerootMatch {
+    /// The matching frame closest to the root of the stack, so that recursive and nested calls of
+    /// the matching functions stay in the subtree of the outermost one.
+    #[default]
+    First,
+
+    /// The matching frame closest to the leaf of the stack, so that each subtree only contains
+    /// what runs under the innermost matching function.
+    Last,
+}
+
+impl RerootMatch {
+    /// The valid set of re-root matches (via `FromStr`).
+    pub const VARIANTS: &'static [&'static str] = &["first", "last"];
+}
+
+impl FromStr for RerootMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(RerootMatch::First),
+            "last" => Ok(RerootMatch::Last),
+            unknown => Err(format!("unknown re-root match: {}", unknown)),
+        }
+    }
+}
+
+/// How sample counts are formatted in a flame graph.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum CountFormat {
+    /// Whole numbers as they are, and fractional counts with as many decimal places as they have,
+    /// up to nine.
+    #[default]
+    Auto,
+
+    /// Counts rounded to whole numbers.
+    Integer,
+
+    /// Counts rounded to this many decimal places, which are always shown.
+    Decimal(usize),
+}
+
+impl CountFormat {
+    /// The valid set of count formats (via `FromStr`).
+    ///
+    /// `decimal` shows two decimal places, and another number of decimal places can be given as
+    /// in `decimal:3`.
+    pub const VARIANTS: &'static [&'static str] = &["auto", "integer", "decimal"];
+
+    /// Format `count` for display, with thousands separators.
+    fn format(self, count: Count) -> String {
+        let formatted = match self {
+            CountFormat::Auto => count.to_string(),
+            // The rounding here can differ from the Perl version when the fractional part is
+            // `0.5`. The Perl version does `sprintf "%.0f", ($etime - $stime) * $factor;`, but
+            // this can format in strange ways as shown in these examples:
+            //     `sprintf "%.0f", 1.5` produces "2"
+            //     `sprintf "%.0f", 2.5` produces "2"
+            //     `sprintf "%.0f", 3.5` produces "4"
+            CountFormat::Integer => format!("{:.0}", count),
+            CountFormat::Decimal(places) => format!("{:.*}", places, count),
+        };
+        folded::group_thousands(&formatted)
+    }
+}
+
+impl FromStr for CountFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "auto" => Ok(CountFormat::Auto),
+            None if s == "integer" => Ok(CountFormat::Integer),
+            None if s == "decimal" => Ok(CountFormat::Decimal(2)),
+            Some(("decimal", places)) => places
+                .parse()
+                .map(CountFormat::Decimal)
+                .map_err(|_| format!("invalid number of decimal places: {}", places)),
+            _ => Err(format!("unknown count format: {}", s)),
+        }
+    }
+}
+
+/// A ratio between two metrics of [multi-metric](folded::parse_metrics_header) input, shown in
+/// the tooltips of frames.
+///
+/// Ratios are parsed from `NAME=METRIC/METRIC`, as in `IPC=instructions/cycles`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ratio {
+    /// The name of the ratio in tooltips.
+    pub name: String,
+
+    /// The metric that is divided.
+    pub numerator: String,
+
+    /// The metric that it is divided by.
+    pub denominator: String,
+}
+
+impl FromStr for Ratio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, metrics) = s.split_once('=').unwrap_or_default();
+        let (numerator, denominator) = metrics.split_once('/').unwrap_or_default();
+        if name.is_empty() || numerator.is_empty() || denominator.is_empty() {
+            return Err(format!(
+                "invalid ratio: {} (expected NAME=METRIC/METRIC)",
+                s
+            ));
+        }
+        Ok(Ratio {
+            name: name.to_string(),
+            numerator: numerator.to_string(),
+            denominator: denominator.to_string(),
+        })
+    }
+}
+
+/// The metrics of multi-metric input, as shown in tooltips.
+struct Metrics<'a> {
+    /// The names of the metrics, in the order of their sample counts in the input.
+    names: Vec<&'a str>,
+
+    /// The index of the metric that sets the widths of frames.
+    width: usize,
+
+    /// The name of each ratio, with the indices of its numerator and denominator.
+    ratios: Vec<(String, usize, usize)>,
+}
+
+impl<'a> Metrics<'a> {
+    /// Look up the metric and the ratios of `opt` among the metrics of the input.
+    fn new(opt: &Options<'_>, names: Vec<&'a str>) -> Result<Self, String> {
+        let index = |name: &str| {
+            names
+                .iter()
+                .position(|&n| n == name)
+                .ok_or_else(|| format!("Unknown metric: {}", name))
+        };
+        let width = match &opt.metric {
+            Some(metric) => index(metric)?,
+            None => 0,
+        };
+        let ratios = opt
+            .ratios
+            .iter()
+            .map(|ratio| {
+                let numerator = index(&ratio.numerator)?;
+                let denominator = index(&ratio.denominator)?;
+                Ok((ratio.name.clone(), numerator, denominator))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Metrics {
+            names,
+            width,
+            ratios,
+        })
+    }
+
+    /// Push `line` onto `lines` with the sample count of the width metric first, followed by
+    /// those of the other metrics, as [`merge::parse_line`] expects them.
+    ///
+    /// Lines without a sample count for every metric are pushed as they are, to be ignored later
+    /// on.
+    fn reorder(&self, line: &str, lines: &mut StrStack) {
+        let Some(parsed) = MetricsLine::parse(line, self.names.len()) else {
+            lines.push(line);
+            return;
+        };
+        let mut reordered = format!("{} {}", parsed.stack, parsed.counts[self.width]);
+        for (i, count) in parsed.counts.iter().enumerate() {
+            if i != self.width {
+                reordered.push(' ');
+                reordered.push_str(&count.to_string());
+            }
+        }
+        lines.push(&reordered);
+    }
+
+    /// The other metrics and the ratios of a frame for its tooltip, given its width in `samples`
+    /// and the sums of its other metrics.
+    fn tooltip(&self, opt: &Options<'_>, samples: Count, others: &[Count]) -> String {
+        let mut counts = others.to_vec();
+        counts.insert(self.width, samples);
+        let mut tooltip = String::new();
+        for (i, (name, &count)) in self.names.iter().zip(&counts).enumerate() {
+            if i != self.width {
+                tooltip.push_str(&format!(", {} {}", opt.count_format.format(count), name));
+            }
+        }
+        for (name, numerator, denominator) in &self.ratios {
+            if counts[*denominator] != Count::ZERO {
+                let ratio = counts[*numerator].as_f64() / counts[*denominator].as_f64();
+                tooltip.push_str(&format!(", {} {:.2}", name, ratio));
+            }
+        }
+        tooltip
+    }
+}
+
 struct Rectangle {
-    x1_samples: usize,
+    x1_samples: Count,
     x1_pct: f64,
     y1: usize,
-    x2_samples: usize,
+    x2_samples: Count,
     x2_pct: f64,
     y2: usize,
 }
@@ -382,29 +757,98 @@ impl Rectangle {
 /// This function expects each folded stack to contain the following whitespace-separated fields:
 ///
 ///  - A semicolon-separated list of frame names (e.g., `main;foo;bar;baz`).
-///  - A sample count for the given stack.
+///  - A sample count for the given stack, which may be fractional (e.g., `12.5` milliseconds).
 ///  - An optional second sample count.
 ///
 /// If two sample counts are provided, a [differential flame graph] is produced. In this mode, the
 /// flame graph uses the difference between the two sample counts to show how the sample counts for
 /// each stack has changed between the first and second profiling.
 ///
-/// The resulting flame graph will be written out to `writer` in SVG format.
+/// The resulting flame graph will be written out to `writer` in the format chosen by
+/// [`Options::format`], which is SVG by default.
 ///
 /// [differential flame graph]: http://www.brendangregg.com/blog/2014-11-09/differential-flame-graphs.html
 #[allow(clippy::cognitive_complexity)]
-pub fn from_lines<'a, I, W>(opt: &mut Options<'_>, lines: I, writer: W) -> quick_xml::Result<()>
+pub fn from_lines<'a, I, W>(opt: &mut Options<'_>, lines: I, mut writer: W) -> quick_xml::Result<()>
 where
     I: IntoIterator<Item = &'a str>,
     W: Write,
 {
+    let reroot = match opt.reroot.as_deref().map(Regex::new).transpose() {
+        Ok(reroot) => reroot,
+        Err(e) => {
+            error!("Invalid re-root expression: {}", e);
+            return Err(quick_xml::Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                e,
+            )));
+        }
+    };
+    let reroot_match = opt.reroot_match;
+
+    // Multi-metric input starts with a header line that names its metrics.
+    let mut lines = lines.into_iter().map(|line| line.trim()).peekable();
+    let mut names = None;
+    while let Some(line) = lines.next_if(|line| folded::is_comment(line)) {
+        names = names.or_else(|| folded::parse_metrics_header(line));
+    }
+    let metrics = match names.map(|names| Metrics::new(opt, names)).transpose() {
+        Ok(metrics) => metrics,
+        Err(e) => {
+            error!("{}", e);
+            return Err(quick_xml::Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                e,
+            )));
+        }
+    };
+    if metrics.is_none() && (opt.metric.is_some() || !opt.ratios.is_empty()) {
+        warn!("The input has no metrics, so the metric and ratio options are being ignored.");
+    }
+    let mut reordered = StrStack::new();
+    if let Some(metrics) = &metrics {
+        for line in lines.by_ref() {
+            metrics.reorder(line, &mut reordered);
+        }
+    }
+    // The number of metrics that follow the sample count of each line.
+    let nmetrics = metrics
+        .as_ref()
+        .map_or(0, |metrics| metrics.names.len() - 1);
+
     let mut reversed = StrStack::new();
     let lines = lines
-        .into_iter()
-        .map(|line| line.trim())
-        .filter(|line| !(line.is_empty() || line.starts_with("# ")));
+        .chain(reordered.iter())
+        .filter(|line| !folded::is_comment(line))
+        .filter_map(|line| match &reroot {
+            Some(reroot) => reroot_line(line, reroot, reroot_match, nmetrics),
+            None => Some(line),
+        });
+
+    let mut callers = StrStack::new();
+    let mut callees = StrStack::new();
+    let mut butterfly_focus = None;
+    let mut top_self_time = Vec::new();
+    let (mut frames, time, ignored, delta_max) = if let Some(function) = opt.butterfly.as_deref() {
+        let ignored = butterfly::split(function, lines, &mut callers, &mut callees, nmetrics);
+        let mut callers: Vec<&str> = callers.iter().collect();
+        callers.sort_unstable();
+        let mut callees: Vec<&str> = callees.iter().collect();
+        callees.sort_unstable();
+        let (callers, time, _, delta_max) = merge::frames(callers, false, nmetrics)?;
+        let (callees, _, _, _) = merge::frames(callees, false, nmetrics)?;
+        if time == Count::ZERO {
+            warn!("No stacks contain the function {}", function);
+        }
 
-    let (mut frames, time, ignored, delta_max) = if opt.reverse_stack_order {
+        if opt.format == OutputFormat::Html {
+            top_self_time = html::top_self_time(&callees);
+        }
+        let minwidth_time = opt.min_width * time.as_f64() / 100.0;
+        let (frames, focus) = butterfly::layout(opt.direction, callers, callees, minwidth_time);
+        butterfly_focus = Some(focus);
+        (frames, time, ignored, delta_max)
+    } else if opt.reverse_stack_order {
         if opt.no_sort {
             warn!(
                 "Input lines are always sorted when `reverse_stack_order` is `true`. \
@@ -415,34 +859,39 @@ where
         let mut stack = String::new();
         for line in lines {
             stack.clear();
-            let samples_idx = merge::rfind_samples(line)
-                .map(|(i, _)| i)
-                .unwrap_or_else(|| line.len());
-            let samples_idx = merge::rfind_samples(&line[..samples_idx - 1])
-                .map(|(i, _)| i)
-                .unwrap_or(samples_idx);
-            for (i, func) in line[..samples_idx].trim().split(';').rev().enumerate() {
+            // Lines without sample counts are kept as they are, to be ignored later on.
+            let Some((parsed, _)) = merge::parse_line(line, nmetrics) else {
+                reversed.push(line);
+                continue;
+            };
+            for (i, func) in parsed.stack.split(';').rev().enumerate() {
                 if i != 0 {
                     stack.push(';');
                 }
                 stack.push_str(func);
             }
-            stack.push(' ');
-            stack.push_str(&line[samples_idx..]);
+            // Keep the sample counts as they are, so they are only parsed once.
+            stack.push_str(&line[parsed.stack.len()..]);
             reversed.push(&stack);
         }
         let mut reversed: Vec<&str> = reversed.iter().collect();
         reversed.sort_unstable();
-        merge::frames(reversed, false)?
+        merge::frames(reversed, false, nmetrics)?
     } else if opt.flame_chart {
         // In flame chart mode, just reverse the data so time moves from left to right.
         let mut lines: Vec<&str> = lines.into_iter().collect();
         lines.reverse();
-        merge::frames(lines, true)?
-    } else if opt.no_sort {
+        merge::frames(lines, true, nmetrics)?
+    } else if opt.no_sort && reroot.is_none() {
         // Lines don't need sorting.
-        merge::frames(lines, false)?
+        merge::frames(lines, false, nmetrics)?
     } else {
+        if opt.no_sort {
+            warn!(
+                "Input lines are always sorted when `reroot` is set. \
+                 The `no_sort` option is being ignored."
+            );
+        }
         // Sort lines by default.
         let mut lines: Vec<&str> = if opt.base.is_empty() {
             lines.into_iter().collect()
@@ -467,25 +916,50 @@ where
                 .collect()
         };
         lines.sort_unstable();
-        merge::frames(lines, false)?
+        merge::frames(lines, false, nmetrics)?
     };
 
     if ignored != 0 {
         warn!("Ignored {} lines with invalid format", ignored);
     }
 
-    let mut buffer = StrStack::new();
+    if time == Count::ZERO && !matches!(opt.format, OutputFormat::Svg | OutputFormat::Html) {
+        error!("No stack counts found");
+        return Err(quick_xml::Error::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No stack counts found",
+        )));
+    }
+    match opt.format {
+        OutputFormat::Json if opt.butterfly.is_some() => {
+            error!("Butterfly graphs can't be written as JSON");
+            return Err(quick_xml::Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Butterfly graphs can't be written as JSON",
+            )));
+        }
+        OutputFormat::Json => {
+            let minwidth_time = opt.min_width / (100.0 / time.as_f64());
+            return json::write_tree(opt, &frames, minwidth_time, writer)
+                .map_err(quick_xml::Error::Io);
+        }
+        OutputFormat::Term => {
+            return term::write_graph(opt, &frames, time, delta_max, writer)
+                .map_err(quick_xml::Error::Io);
+        }
+        _ => {}
+    }
 
-    // let's start writing the svg!
-    let mut svg = if opt.pretty_xml {
-        Writer::new_with_indent(writer, b' ', 4)
-    } else {
-        Writer::new(writer)
-    };
+    if opt.format == OutputFormat::Html {
+        html::write_head(&mut writer, opt)?;
+    }
+
+    let mut buffer = StrStack::new();
 
-    if time == 0 {
+    if time == Count::ZERO {
         error!("No stack counts found");
         // emit an error message SVG, for tools automating flamegraph use
+        let mut svg = svg_writer(opt, writer);
         let imageheight = opt.font_size * 5;
         svg::write_header(&mut svg, imageheight, opt)?;
         svg::write_str(
@@ -500,6 +974,9 @@ where
         )?;
         svg.write_event(Event::End(BytesEnd::new("svg")))?;
         svg.write_event(Event::Eof)?;
+        if opt.format == OutputFormat::Html {
+            html::write_tail(&mut svg.into_inner(), opt, None, Count::ZERO)?;
+        }
         return Err(quick_xml::Error::Io(io::Error::new(
             io::ErrorKind::InvalidData,
             "No stack counts found",
@@ -508,13 +985,17 @@ where
 
     let image_width = opt.image_width.unwrap_or(DEFAULT_IMAGE_WIDTH) as f64;
     let timemax = time;
-    let widthpertime_pct = 100.0 / timemax as f64;
+    let widthpertime_pct = 100.0 / timemax.as_f64();
     let minwidth_time = opt.min_width / widthpertime_pct;
 
+    if opt.format == OutputFormat::Html && opt.butterfly.is_none() {
+        top_self_time = html::top_self_time(&frames);
+    }
+
     // prune blocks that are too narrow
     let mut depthmax = 0;
     frames.retain(|frame| {
-        if ((frame.end_time - frame.start_time) as f64) < minwidth_time {
+        if (frame.end_time - frame.start_time).as_f64() < minwidth_time {
             false
         } else {
             depthmax = std::cmp::max(depthmax, frame.location.depth);
@@ -522,8 +1003,16 @@ where
         }
     });
 
-    // draw canvas, and embed interactive JavaScript program
     let imageheight = ((depthmax + 1) * opt.frame_height) + opt.ypad1() + opt.ypad2();
+
+    #[cfg(feature = "png")]
+    if opt.format == OutputFormat::Png {
+        return png::write_image(opt, &frames, timemax, delta_max, imageheight, writer)
+            .map_err(quick_xml::Error::Io);
+    }
+
+    // draw canvas, and embed interactive JavaScript program
+    let mut svg = svg_writer(opt, writer);
     svg::write_header(&mut svg, imageheight, opt)?;
 
     let (bgcolor1, bgcolor2) = color::bgcolor_for(opt.bgcolors, opt.colors);
@@ -557,32 +1046,32 @@ where
     // create frames container
     let container_x = format!("{}", XPAD);
     let container_width = format!("{}", image_width as usize - XPAD - XPAD);
-    svg.write_event(Event::Start(BytesStart::new("svg").with_attributes(vec![
+    let total_samples = format!("{}", timemax);
+    let mut container = BytesStart::new("svg").with_attributes(vec![
         ("id", "frames"),
-        ("x", &container_x),
+        ("x", &*container_x),
         ("width", &container_width),
-        ("total_samples", &format!("{}", timemax)),
-    ])))?;
+        ("total_samples", &total_samples),
+    ]);
+    if let Some(focus) = butterfly_focus {
+        // Zooming needs to know which frames are callers and which are callees.
+        let (focus_y, _) = frame_y(opt, focus, imageheight);
+        container.push_attribute(("focus_y", &*format!("{}", focus_y)));
+    }
+    svg.write_event(Event::Start(container))?;
+
+    // Tooltips name the metric that sets the widths of frames, if there are several.
+    let count_name = match &metrics {
+        Some(metrics) => metrics.names[metrics.width].to_string(),
+        None => opt.count_name.clone(),
+    };
 
     // draw frames
-    let mut samples_txt_buffer = num_format::Buffer::default();
     for frame in frames {
-        let x1_pct = frame.start_time as f64 * widthpertime_pct;
-        let x2_pct = frame.end_time as f64 * widthpertime_pct;
-
-        let (y1, y2) = match opt.direction {
-            Direction::Straight => {
-                let y1 = imageheight - opt.ypad2() - (frame.location.depth + 1) * opt.frame_height
-                    + FRAMEPAD;
-                let y2 = imageheight - opt.ypad2() - frame.location.depth * opt.frame_height;
-                (y1, y2)
-            }
-            Direction::Inverted => {
-                let y1 = opt.ypad1() + frame.location.depth * opt.frame_height;
-                let y2 = opt.ypad1() + (frame.location.depth + 1) * opt.frame_height - FRAMEPAD;
-                (y1, y2)
-            }
-        };
+        let x1_pct = frame.start_time.as_f64() * widthpertime_pct;
+        let x2_pct = frame.end_time.as_f64() * widthpertime_pct;
+
+        let (y1, y2) = frame_y(opt, frame.location.depth, imageheight);
 
         let rect = Rectangle {
             x1_pct,
@@ -593,44 +1082,43 @@ where
             y2,
         };
 
-        // The rounding here can differ from the Perl version when the fractional part is `0.5`.
-        // The Perl version does `my $samples = sprintf "%.0f", ($etime - $stime) * $factor;`,
-        // but this can format in strange ways as shown in these examples:
-        //     `sprintf "%.0f", 1.5` produces "2"
-        //     `sprintf "%.0f", 2.5` produces "2"
-        //     `sprintf "%.0f", 3.5` produces "4"
-        let samples = ((frame.end_time - frame.start_time) as f64 * opt.factor).round() as usize;
-
-        // add thousands separators to `samples`
-        let _ = samples_txt_buffer.write_formatted(&samples, &Locale::en);
-        let samples_txt = samples_txt_buffer.as_str();
+        let samples = frame.end_time - frame.start_time;
+        let samples_txt = format_count(opt, samples);
+        let metrics_txt = match &metrics {
+            Some(metrics) => metrics.tooltip(opt, samples, &frame.metrics),
+            None => String::new(),
+        };
 
         let info = if frame.location.function.is_empty() && frame.location.depth == 0 {
-            write!(buffer, "all ({} {}, 100%)", samples_txt, opt.count_name)
+            write!(
+                buffer,
+                "all ({} {}, 100%{})",
+                samples_txt, count_name, metrics_txt
+            )
         } else {
-            let pct = (100 * samples) as f64 / (timemax as f64 * opt.factor);
+            let pct = 100.0 * samples.as_f64() / timemax.as_f64();
             let function = deannotate(frame.location.function);
             match frame.delta {
                 None => write!(
                     buffer,
-                    "{} ({} {}, {:.2}%)",
-                    function, samples_txt, opt.count_name, pct
+                    "{} ({} {}, {:.2}%{})",
+                    function, samples_txt, count_name, pct, metrics_txt
                 ),
                 // Special case delta == 0 so we don't format percentage with a + sign.
-                Some(delta) if delta == 0 => write!(
+                Some(Delta::ZERO) => write!(
                     buffer,
                     "{} ({} {}, {:.2}%; 0.00%)",
-                    function, samples_txt, opt.count_name, pct,
+                    function, samples_txt, count_name, pct,
                 ),
                 Some(mut delta) => {
                     if opt.negate_differentials {
                         delta = -delta;
                     }
-                    let delta_pct = (100 * delta) as f64 / (timemax as f64 * opt.factor);
+                    let delta_pct = 100.0 * delta.as_f64() / timemax.as_f64();
                     write!(
                         buffer,
                         "{} ({} {}, {:.2}%; {:+.2}%)",
-                        function, samples_txt, opt.count_name, pct, delta_pct
+                        function, samples_txt, count_name, pct, delta_pct
                     )
                 }
             }
@@ -649,44 +1137,10 @@ where
         svg.write_event(Event::Text(BytesText::new(title)))?;
         svg.write_event(Event::End(BytesEnd::new("title")))?;
 
-        // select the color of the rectangle
-        let color = if frame.location.function == "--" {
-            color::VDGREY
-        } else if frame.location.function == "-" {
-            color::DGREY
-        } else if opt.color_diffusion {
-            // We want to visually highlight high priority regions for
-            // optimization: wider frames are redder. Typically when optimizing,
-            // a frame that is 50% of width is high priority, so it seems wrong
-            // to give it half the saturation of 100%. So we use sqrt to make
-            // the red dropoff less linear.
-            color::color_scale((((x2_pct - x1_pct) / 100.0).sqrt() * 2000.0) as isize, 2000)
-        } else if let Some(mut delta) = frame.delta {
-            if opt.negate_differentials {
-                delta = -delta;
-            }
-            color::color_scale(delta, delta_max)
-        } else if let Some(ref mut palette_map) = opt.palette_map {
-            let colors = opt.colors;
-            let hash = opt.hash;
-            let deterministic = opt.deterministic;
-            palette_map.find_color_for(frame.location.function, |name| {
-                color::color(colors, hash, deterministic, name, &mut thread_rng)
-            })
-        } else {
-            color::color(
-                opt.colors,
-                opt.hash,
-                opt.deterministic,
-                frame.location.function,
-                &mut thread_rng,
-            )
-        };
+        let color = frame_color(opt, &frame, x2_pct - x1_pct, delta_max, &mut thread_rng);
         filled_rectangle(&mut svg, &mut buffer, &rect, color, &mut cache_rect)?;
 
-        let fitchars = (rect.width_pct()
-            / (100.0 * opt.font_size as f64 * opt.font_width / image_width))
-            .trunc() as usize;
+        let fitchars = fit_chars(opt, rect.width_pct(), image_width);
         let text: svg::TextArgument<'_> = if fitchars >= 3 {
             // room for one char plus two dots
             let f = deannotate(frame.location.function);
@@ -734,10 +1188,120 @@ where
     svg.write_event(Event::End(BytesEnd::new("svg")))?;
     svg.write_event(Event::Eof)?;
 
-    svg.into_inner().flush()?;
+    let mut writer = svg.into_inner();
+    if opt.format == OutputFormat::Html {
+        html::write_tail(&mut writer, opt, Some(&top_self_time), timemax)?;
+    }
+    writer.flush()?;
     Ok(())
 }
 
+/// Cut off the frames of `line` above the frame that `reroot` matches, or return `None` if no
+/// frame matches.
+///
+/// Lines without sample counts are kept as they are, to be ignored later on.
+fn reroot_line<'a>(
+    line: &'a str,
+    reroot: &Regex,
+    reroot_match: RerootMatch,
+    metrics: usize,
+) -> Option<&'a str> {
+    let Some((parsed, _)) = merge::parse_line(line, metrics) else {
+        return Some(line);
+    };
+    let mut start = 0;
+    let mut root = None;
+    for frame in parsed.stack.split(';') {
+        if reroot.is_match(frame) {
+            root = Some(start);
+            if reroot_match == RerootMatch::First {
+                break;
+            }
+        }
+        start += frame.len() + 1;
+    }
+    root.map(|root| &line[root..])
+}
+
+/// Create the writer for the SVG image.
+fn svg_writer<W: Write>(opt: &Options<'_>, writer: W) -> Writer<W> {
+    if opt.pretty_xml {
+        Writer::new_with_indent(writer, b' ', 4)
+    } else {
+        Writer::new(writer)
+    }
+}
+
+/// The top and bottom of the frames at the given depth.
+fn frame_y(opt: &Options<'_>, depth: usize, imageheight: usize) -> (usize, usize) {
+    match opt.direction {
+        Direction::Straight => {
+            let y1 = imageheight - opt.ypad2() - (depth + 1) * opt.frame_height + FRAMEPAD;
+            let y2 = imageheight - opt.ypad2() - depth * opt.frame_height;
+            (y1, y2)
+        }
+        Direction::Inverted => {
+            let y1 = opt.ypad1() + depth * opt.frame_height;
+            let y2 = opt.ypad1() + (depth + 1) * opt.frame_height - FRAMEPAD;
+            (y1, y2)
+        }
+    }
+}
+
+/// The number of characters that fit in a frame that takes up `width_pct` percent of the width of
+/// the flame graph.
+fn fit_chars(opt: &Options<'_>, width_pct: f64, image_width: f64) -> usize {
+    (width_pct / (100.0 * opt.font_size as f64 * opt.font_width / image_width)).trunc() as usize
+}
+
+/// Format a sample count for display, scaled by [`Options::factor`] and formatted as set by
+/// [`Options::count_format`].
+fn format_count(opt: &Options<'_>, count: Count) -> String {
+    opt.count_format.format(count.scale(opt.factor))
+}
+
+/// Select the color of a frame that takes up `width_pct` percent of the width of the flame graph.
+fn frame_color(
+    opt: &mut Options<'_>,
+    frame: &merge::TimedFrame<'_>,
+    width_pct: f64,
+    delta_max: Count,
+    rng: &mut impl FnMut() -> f32,
+) -> Color {
+    if frame.location.function == "--" {
+        color::VDGREY
+    } else if frame.location.function == "-" {
+        color::DGREY
+    } else if opt.color_diffusion {
+        // We want to visually highlight high priority regions for
+        // optimization: wider frames are redder. Typically when optimizing,
+        // a frame that is 50% of width is high priority, so it seems wrong
+        // to give it half the saturation of 100%. So we use sqrt to make
+        // the red dropoff less linear.
+        color::color_scale(((width_pct / 100.0).sqrt() * 2000.0).trunc(), 2000.0)
+    } else if let Some(mut delta) = frame.delta {
+        if opt.negate_differentials {
+            delta = -delta;
+        }
+        color::color_scale(delta.as_f64(), delta_max.as_f64())
+    } else if let Some(ref mut palette_map) = opt.palette_map {
+        let colors = opt.colors;
+        let hash = opt.hash;
+        let deterministic = opt.deterministic;
+        palette_map.find_color_for(frame.location.function, |name| {
+            color::color(colors, hash, deterministic, name, &mut *rng)
+        })
+    } else {
+        color::color(
+            opt.colors,
+            opt.hash,
+            opt.deterministic,
+            frame.location.function,
+            &mut *rng,
+        )
+    }
+}
+
 #[cfg(feature = "nameattr")]
 fn write_container_start<'a, W: Write>(
     opt: &'a Options<'a>,
@@ -809,7 +1373,8 @@ fn write_container_attributes(event: &mut Event<'_>, frame_attributes: &FrameAtt
 ///
 /// See [`from_lines`] for the expected format of each line.
 ///
-/// The resulting flame graph will be written out to `writer` in SVG format.
+/// The resulting flame graph will be written out to `writer` in the format chosen by
+/// [`Options::format`].
 pub fn from_reader<R, W>(opt: &mut Options<'_>, reader: R, writer: W) -> quick_xml::Result<()>
 where
     R: Read,
@@ -822,7 +1387,8 @@ where
 ///
 /// See [`from_lines`] for the expected format of each line.
 ///
-/// The resulting flame graph will be written out to `writer` in SVG format.
+/// The resulting flame graph will be written out to `writer` in the format chosen by
+/// [`Options::format`].
 pub fn from_readers<R, W>(opt: &mut Options<'_>, readers: R, writer: W) -> quick_xml::Result<()>
 where
     R: IntoIterator,
@@ -841,32 +1407,31 @@ where
 /// Produce a flame graph from files that contain folded stack lines
 /// and write the result to provided `writer`.
 ///
-/// If files is empty, STDIN will be used as input.
+/// If files is empty, STDIN will be used as input. With the `compression` feature, files that are
+/// compressed with gzip, zstd or xz are decompressed on the fly.
 pub fn from_files<W: Write>(
     opt: &mut Options<'_>,
     files: &[PathBuf],
     writer: W,
 ) -> quick_xml::Result<()> {
     if files.is_empty() || files.len() == 1 && files[0].to_str() == Some("-") {
-        let stdin = io::stdin();
-        let r = BufReader::with_capacity(128 * 1024, stdin.lock());
+        let r = input::stdin().map_err(quick_xml::Error::Io)?;
         from_reader(opt, r, writer)
     } else if files.len() == 1 {
-        let r = File::open(&files[0]).map_err(quick_xml::Error::Io)?;
+        let r = input::open(&files[0]).map_err(quick_xml::Error::Io)?;
         from_reader(opt, r, writer)
     } else {
-        let stdin = io::stdin();
         let mut stdin_added = false;
         let mut readers: Vec<Box<dyn Read>> = Vec::with_capacity(files.len());
         for infile in files.iter() {
             if infile.to_str() == Some("-") {
                 if !stdin_added {
-                    let r = BufReader::with_capacity(128 * 1024, stdin.lock());
+                    let r = input::stdin().map_err(quick_xml::Error::Io)?;
                     readers.push(Box::new(r));
                     stdin_added = true;
                 }
             } else {
-                let r = File::open(infile).map_err(quick_xml::Error::Io)?;
+                let r = input::open(infile).map_err(quick_xml::Error::Io)?;
                 readers.push(Box::new(r));
             }
         }
@@ -898,8 +1463,8 @@ fn filled_rectangle<W: Write>(
     let width = write!(buffer, "{:.4}%", rect.width_pct());
     let height = write_usize(buffer, rect.height());
     let color = write!(buffer, "rgb({},{},{})", color.r, color.g, color.b);
-    let x_samples = write_usize(buffer, rect.x1_samples);
-    let width_samples = write_usize(buffer, rect.x2_samples - rect.x1_samples);
+    let x_samples = write!(buffer, "{}", rect.x1_samples);
+    let width_samples = write!(buffer, "{}", rect.x2_samples - rect.x1_samples);
 
     if let Event::Empty(bytes_start) = cache_rect {
         // clear the state
@@ -925,7 +1490,8 @@ fn write_usize(buffer: &mut StrStack, value: usize) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use super::{Direction, Options};
+    use super::{reroot_line, Direction, Options, Ratio, RerootMatch};
+    use regex::Regex;
 
     // If there's a subtitle, we need to adjust the top height:
     #[test]
@@ -956,4 +1522,45 @@ mod tests {
         assert!(inverted.ypad1() > regular.ypad1());
         assert!(inverted.ypad2() < regular.ypad2());
     }
+
+    #[test]
+    fn reroot_at_first_or_last_match() {
+        let poll = Regex::new("poll$").unwrap();
+        let line = "main;a::poll;b;c::poll;d 5";
+        assert_eq!(
+            reroot_line(line, &poll, RerootMatch::First, 0),
+            Some("a::poll;b;c::poll;d 5")
+        );
+        assert_eq!(
+            reroot_line(line, &poll, RerootMatch::Last, 0),
+            Some("c::poll;d 5")
+        );
+        assert_eq!(
+            reroot_line("main;b 1 2", &poll, RerootMatch::First, 0),
+            None
+        );
+        assert_eq!(
+            reroot_line("bad line", &poll, RerootMatch::First, 0),
+            Some("bad line")
+        );
+        assert_eq!(
+            reroot_line("main;a::poll 1 2", &poll, RerootMatch::First, 1),
+            Some("a::poll 1 2")
+        );
+    }
+
+    #[test]
+    fn parse_ratios() {
+        assert_eq!(
+            "IPC=instructions/cycles".parse(),
+            Ok(Ratio {
+                name: "IPC".to_string(),
+                numerator: "instructions".to_string(),
+                denominator: "cycles".to_string(),
+            })
+        );
+        assert!("IPC".parse::<Ratio>().is_err());
+        assert!("IPC=instructions".parse::<Ratio>().is_err());
+        assert!("=instructions/cycles".parse::<Ratio>().is_err());
+    }
 }
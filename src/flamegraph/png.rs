@@ -0,0 +1,229 @@
+use std::io::{self, Write};
+
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use tiny_skia::{
+    GradientStop, LinearGradient, Paint, PathBuilder, Pixmap, Point, PremultipliedColorU8, Rect,
+    Shader, SpreadMode, Stroke, Transform,
+};
+
+use super::color::{self, Color, StrokeColor};
+use super::merge::TimedFrame;
+use super::{deannotate, fit_chars, frame_color, frame_y, rand, Options, XPAD};
+use crate::folded::Count;
+
+/// The font all text is drawn in.
+static FONT: &[u8] = include_bytes!("DejaVuSansMono.ttf");
+
+const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+
+/// The color of the subtitle, as set by the style sheet of the SVG image.
+const SUBTITLE_COLOR: Color = Color {
+    r: 160,
+    g: 160,
+    b: 160,
+};
+
+/// Draw the frames as a PNG image that is `imageheight` pixels high.
+///
+/// This follows the layout of the SVG image, but leaves out the parts that only make sense in an
+/// interactive image (the details line, the search and reset zoom buttons).
+pub(super) fn write_image<W: Write>(
+    opt: &mut Options<'_>,
+    frames: &[TimedFrame<'_>],
+    timemax: Count,
+    delta_max: Count,
+    imageheight: usize,
+    mut writer: W,
+) -> io::Result<()> {
+    let image_width = opt.image_width.unwrap_or(super::DEFAULT_IMAGE_WIDTH);
+    let mut canvas = Canvas::new(image_width, imageheight)?;
+
+    let (bgcolor1, bgcolor2) = color::bgcolor_for(opt.bgcolors, opt.colors);
+    let bgcolor1 = color::parse_hex_color(&bgcolor1).unwrap_or_default();
+    let bgcolor2 = color::parse_hex_color(&bgcolor2).unwrap_or_default();
+    canvas.fill_background(bgcolor1, bgcolor2);
+
+    let center = image_width as f32 / 2.0;
+    let titlesize = (opt.font_size + 5) as f32;
+    let y = (opt.font_size * 2) as f32;
+    canvas.draw_text_centered(&opt.title, center, y, titlesize, opt.uicolor);
+    if let Some(ref subtitle) = opt.subtitle {
+        let y = (opt.font_size * 4) as f32;
+        canvas.draw_text_centered(subtitle, center, y, opt.font_size as f32, SUBTITLE_COLOR);
+    }
+
+    // The frames are drawn in a container that leaves a margin on both sides.
+    let container_width = (image_width - XPAD - XPAD) as f64;
+    let x_of = |pct: f64| (XPAD as f64 + pct * container_width / 100.0) as f32;
+    let stroke_color = match opt.stroke_color {
+        StrokeColor::Color(c) => Some(c),
+        StrokeColor::None => None,
+    };
+    let widthpertime_pct = 100.0 / timemax.as_f64();
+    let mut thread_rng = rand::thread_rng();
+    for frame in frames {
+        let x1_pct = frame.start_time.as_f64() * widthpertime_pct;
+        let x2_pct = frame.end_time.as_f64() * widthpertime_pct;
+        let (y1, y2) = frame_y(opt, frame.location.depth, imageheight);
+
+        let color = frame_color(opt, frame, x2_pct - x1_pct, delta_max, &mut thread_rng);
+        let (x1, x2) = (x_of(x1_pct), x_of(x2_pct));
+        canvas.fill_rect(
+            x1,
+            y1 as f32,
+            x2 - x1,
+            (y2 - y1) as f32,
+            color,
+            stroke_color,
+        );
+
+        let fitchars = fit_chars(opt, x2_pct - x1_pct, image_width as f64);
+        if fitchars >= 3 {
+            // room for one char plus two dots
+            let f = deannotate(frame.location.function);
+            let text = if f.len() < fitchars {
+                f.to_string()
+            } else {
+                let mut text: String = f.chars().take(fitchars - 2).collect();
+                text.push_str("..");
+                text
+            };
+            let x = x_of(x1_pct + 100.0 * 3.0 / image_width as f64);
+            let y = 3.0 + (y1 + y2) as f32 / 2.0;
+            canvas.draw_text(&text, x, y, opt.font_size as f32, BLACK);
+        }
+    }
+
+    let png = canvas
+        .pixmap
+        .encode_png()
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    writer.write_all(&png)?;
+    writer.flush()
+}
+
+struct Canvas {
+    pixmap: Pixmap,
+    font: FontRef<'static>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> io::Result<Self> {
+        let pixmap = u32::try_from(width)
+            .ok()
+            .zip(u32::try_from(height).ok())
+            .and_then(|(width, height)| Pixmap::new(width, height))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid image size: {}x{}", width, height),
+                )
+            })?;
+        let font = FontRef::try_from_slice(FONT).expect("the embedded font is valid");
+        Ok(Canvas { pixmap, font })
+    }
+
+    /// Fill the image with a vertical gradient between the two colors.
+    fn fill_background(&mut self, top: Color, bottom: Color) {
+        let (width, height) = (self.pixmap.width() as f32, self.pixmap.height() as f32);
+        let shader = LinearGradient::new(
+            Point::from_xy(0.0, 0.0),
+            Point::from_xy(0.0, height),
+            vec![
+                GradientStop::new(0.05, skia_color(top)),
+                GradientStop::new(0.95, skia_color(bottom)),
+            ],
+            SpreadMode::Pad,
+            Transform::identity(),
+        )
+        .unwrap_or(Shader::SolidColor(skia_color(top)));
+        let paint = Paint {
+            shader,
+            ..Paint::default()
+        };
+        if let Some(rect) = Rect::from_xywh(0.0, 0.0, width, height) {
+            self.pixmap
+                .fill_rect(rect, &paint, Transform::identity(), None);
+        }
+    }
+
+    fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, fill: Color, stroke: Option<Color>) {
+        let Some(rect) = Rect::from_xywh(x, y, w, h) else {
+            return;
+        };
+        let mut paint = Paint::default();
+        paint.set_color(skia_color(fill));
+        self.pixmap
+            .fill_rect(rect, &paint, Transform::identity(), None);
+
+        if let Some(stroke) = stroke {
+            paint.set_color(skia_color(stroke));
+            let path = PathBuilder::from_rect(rect);
+            let stroke = Stroke {
+                width: 1.0,
+                ..Stroke::default()
+            };
+            self.pixmap
+                .stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+        }
+    }
+
+    /// Draw `text` centered on `x`, with its baseline at `y`.
+    fn draw_text_centered(&mut self, text: &str, x: f32, y: f32, size: f32, color: Color) {
+        let font = self.font.as_scaled(self.scale(size));
+        let width: f32 = text.chars().map(|c| font.h_advance(font.glyph_id(c))).sum();
+        self.draw_text(text, x - width / 2.0, y, size, color);
+    }
+
+    /// Draw `text` starting at `x`, with its baseline at `y`.
+    fn draw_text(&mut self, text: &str, mut x: f32, y: f32, size: f32, color: Color) {
+        let font = self.font.as_scaled(self.scale(size));
+        let (width, height) = (self.pixmap.width(), self.pixmap.height());
+        let pixels = self.pixmap.pixels_mut();
+        for c in text.chars() {
+            let glyph = font.scaled_glyph(c);
+            let advance = font.h_advance(glyph.id);
+            let glyph = ab_glyph::Glyph {
+                position: ab_glyph::point(x, y),
+                ..glyph
+            };
+            if let Some(outline) = font.outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, coverage| {
+                    let px = bounds.min.x as i64 + i64::from(gx);
+                    let py = bounds.min.y as i64 + i64::from(gy);
+                    if px < 0 || py < 0 || px >= i64::from(width) || py >= i64::from(height) {
+                        return;
+                    }
+                    let pixel = &mut pixels[py as usize * width as usize + px as usize];
+                    *pixel = blend(*pixel, color, coverage.min(1.0));
+                });
+            }
+            x += advance;
+        }
+    }
+
+    /// The glyph scale for a font size in pixels per em, as in CSS.
+    fn scale(&self, size: f32) -> PxScale {
+        let units_per_em = self.font.units_per_em().unwrap_or(2048.0);
+        PxScale::from(size * self.font.height_unscaled() / units_per_em)
+    }
+}
+
+fn skia_color(color: Color) -> tiny_skia::Color {
+    tiny_skia::Color::from_rgba8(color.r, color.g, color.b, 255)
+}
+
+/// Draw `color` with the given coverage over an opaque pixel.
+fn blend(pixel: PremultipliedColorU8, color: Color, coverage: f32) -> PremultipliedColorU8 {
+    let mix = |under: u8, over: u8| {
+        (f32::from(under) * (1.0 - coverage) + f32::from(over) * coverage).round() as u8
+    };
+    PremultipliedColorU8::from_rgba(
+        mix(pixel.red(), color.r),
+        mix(pixel.green(), color.g),
+        mix(pixel.blue(), color.b),
+        255,
+    )
+    .unwrap_or(pixel)
+}
@@ -7,7 +7,7 @@ use quick_xml::events::{BytesCData, BytesDecl, BytesEnd, BytesStart, BytesText,
 use quick_xml::Writer;
 use str_stack::StrStack;
 
-use super::{Direction, Options, TextTruncateDirection};
+use super::{Direction, Options, OutputFormat, TextTruncateDirection};
 
 /// The generic font families should not have quotes around them in the CSS.
 const GENERIC_FONT_FAMILIES: &[&str] = &["cursive", "fantasy", "monospace", "serif", "sans-serif"];
@@ -63,19 +63,30 @@ pub(super) fn write_header<W>(
 where
     W: Write,
 {
-    svg.write_event(Event::Decl(BytesDecl::new("1.0", None, Some("no"))))?;
-    svg.write_event(Event::DocType(BytesText::from_escaped(r#"svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd""#)))?;
+    // An SVG that is part of an HTML page is not a document of its own, and the page's script
+    // initializes it once it has been set up.
+    let standalone = opt.format != OutputFormat::Html;
+    if standalone {
+        svg.write_event(Event::Decl(BytesDecl::new("1.0", None, Some("no"))))?;
+        svg.write_event(Event::DocType(BytesText::from_escaped(r#"svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd""#)))?;
+    }
     let imagewidth = opt.image_width.unwrap_or(super::DEFAULT_IMAGE_WIDTH);
-    svg.write_event(Event::Start(BytesStart::new("svg").with_attributes(vec![
-        ("version", "1.1"),
-        ("width", &*format!("{}", imagewidth)),
-        ("height", &*format!("{}", imageheight)),
-        ("onload", "init(evt)"),
-        ("viewBox", &*format!("0 0 {} {}", imagewidth, imageheight)),
+    let width = format!("{}", imagewidth);
+    let height = format!("{}", imageheight);
+    let view_box = format!("0 0 {} {}", imagewidth, imageheight);
+    let mut attributes = vec![("version", "1.1"), ("width", &*width), ("height", &*height)];
+    if standalone {
+        attributes.push(("onload", "init(evt)"));
+    }
+    attributes.extend([
+        ("viewBox", &*view_box),
         ("xmlns", "http://www.w3.org/2000/svg"),
         ("xmlns:xlink", "http://www.w3.org/1999/xlink"),
         ("xmlns:fg", "http://github.com/jonhoo/inferno"),
-    ])))?;
+    ]);
+    svg.write_event(Event::Start(
+        BytesStart::new("svg").with_attributes(attributes),
+    ))?;
     svg.write_event(Event::Comment(BytesText::new(
         "Flame graph stack visualization. \
          See https://github.com/brendangregg/FlameGraph for latest version, \
@@ -120,7 +131,7 @@ where
     };
 
     let titlesize = &opt.font_size + 5;
-    svg.write_event(Event::Text(BytesText::from_escaped(&format!(
+    svg.write_event(Event::Text(BytesText::from_escaped(format!(
         "
 text {{ font-family:{}; font-size:{}px }}
 #title {{ text-anchor:middle; font-size:{}px; }}
@@ -128,7 +139,7 @@ text {{ font-family:{}; font-size:{}px }}
         font_type, &opt.font_size, titlesize,
     ))))?;
     if let Some(strokecolor) = &style_options.strokecolor {
-        svg.write_event(Event::Text(BytesText::from_escaped(&format!(
+        svg.write_event(Event::Text(BytesText::from_escaped(format!(
             "#frames > g > rect {{ stroke:{}; stroke-width:1; }}\n",
             strokecolor
         ))))?;
@@ -141,7 +152,7 @@ text {{ font-family:{}; font-size:{}px }}
     svg.write_event(Event::Start(
         BytesStart::new("script").with_attributes(iter::once(("type", "text/ecmascript"))),
     ))?;
-    svg.write_event(Event::CData(BytesCData::new(&format!(
+    svg.write_event(Event::CData(BytesCData::new(format!(
         "
         var nametype = {};
         var fontsize = {};
@@ -0,0 +1,171 @@
+use std::io::{self, Write};
+
+use super::color::Color;
+use super::merge::TimedFrame;
+use super::{deannotate, frame_color, rand, Direction, Options, TermColors, TextTruncateDirection};
+use crate::folded::Count;
+
+/// The width of the flame graph if neither the options nor the environment give one.
+const DEFAULT_COLUMNS: usize = 80;
+
+const RESET: &str = "\x1b[0m";
+
+/// Draw the frames as lines of colored character cells, one line per stack depth.
+pub(super) fn write_graph<W: Write>(
+    opt: &mut Options<'_>,
+    frames: &[TimedFrame<'_>],
+    time: Count,
+    delta_max: Count,
+    mut writer: W,
+) -> io::Result<()> {
+    let columns = opt
+        .image_width
+        .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+        .unwrap_or(DEFAULT_COLUMNS)
+        .max(1);
+    // Frames start and end at the column closest to their start and end time, so that adjacent
+    // frames never overlap.
+    let column = |t: Count| (t.as_f64() * columns as f64 / time.as_f64()).round() as usize;
+
+    let mut rows: Vec<Vec<(usize, usize, &TimedFrame<'_>)>> = Vec::new();
+    for frame in frames {
+        let (x1, x2) = (column(frame.start_time), column(frame.end_time));
+        if x2 <= x1 {
+            continue;
+        }
+        let depth = frame.location.depth;
+        if rows.len() <= depth {
+            rows.resize_with(depth + 1, Vec::new);
+        }
+        rows[depth].push((x1, x2, frame));
+    }
+    // Children are never wider than their parents, so only trailing rows can be empty.
+    while rows.last().is_some_and(Vec::is_empty) {
+        rows.pop();
+    }
+    if opt.direction == Direction::Straight {
+        rows.reverse();
+    }
+
+    let title = truncate(&opt.title, columns, opt.text_truncate_direction);
+    let pad = columns.saturating_sub(title.chars().count()) / 2;
+    writeln!(writer, "{:pad$}\x1b[1m{}{}", "", title, RESET, pad = pad)?;
+
+    let mut thread_rng = rand::thread_rng();
+    for row in &mut rows {
+        row.sort_unstable_by_key(|&(x1, _, _)| x1);
+        let mut cursor = 0;
+        for &(x1, x2, frame) in row.iter() {
+            write!(writer, "{:1$}", "", x1 - cursor)?;
+
+            let width_pct = 100.0 * (frame.end_time - frame.start_time).as_f64() / time.as_f64();
+            let color = frame_color(opt, frame, width_pct, delta_max, &mut thread_rng);
+            write_colors(&mut writer, color, opt.term_colors)?;
+
+            let name = if frame.location.function.is_empty() && frame.location.depth == 0 {
+                "all"
+            } else {
+                deannotate(frame.location.function)
+            };
+            let label = if x2 - x1 >= 3 {
+                // room for one char plus two dots
+                truncate(name, x2 - x1, opt.text_truncate_direction)
+            } else {
+                String::new()
+            };
+            write!(writer, "{:1$}{2}", label, x2 - x1, RESET)?;
+            cursor = x2;
+        }
+        writeln!(writer)?;
+    }
+
+    writer.flush()
+}
+
+/// Set the background to `color`, and the foreground to black or white, whichever is easier to
+/// read on it.
+fn write_colors<W: Write>(writer: &mut W, color: Color, colors: TermColors) -> io::Result<()> {
+    let luma = 299 * u32::from(color.r) + 587 * u32::from(color.g) + 114 * u32::from(color.b);
+    let foreground = if luma < 128_000 { 97 } else { 30 };
+    match colors {
+        TermColors::TrueColor => write!(
+            writer,
+            "\x1b[{};48;2;{};{};{}m",
+            foreground, color.r, color.g, color.b
+        ),
+        TermColors::Ansi256 => write!(writer, "\x1b[{};48;5;{}m", foreground, ansi256(color)),
+    }
+}
+
+/// The index of the color closest to `color` in the 6x6x6 color cube of the xterm palette.
+fn ansi256(color: Color) -> u8 {
+    // The levels of the cube are 0, 95, 135, 175, 215 and 255.
+    let level = |v: u8| match v {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (v - 35) / 40,
+    };
+    16 + 36 * level(color.r) + 6 * level(color.g) + level(color.b)
+}
+
+/// Shorten `text` with `..` so that it is less than `width` characters long.
+fn truncate(text: &str, width: usize, direction: TextTruncateDirection) -> String {
+    let len = text.chars().count();
+    if len < width {
+        return text.to_string();
+    }
+    let keep = width.saturating_sub(3);
+    match direction {
+        TextTruncateDirection::Left => {
+            let mut truncated = String::from("..");
+            truncated.extend(text.chars().skip(len - keep));
+            truncated
+        }
+        TextTruncateDirection::Right => {
+            let mut truncated: String = text.chars().take(keep).collect();
+            truncated.push_str("..");
+            truncated
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ansi256_colors() {
+        assert_eq!(ansi256(Color { r: 0, g: 0, b: 0 }), 16);
+        assert_eq!(
+            ansi256(Color {
+                r: 255,
+                g: 255,
+                b: 255
+            }),
+            231
+        );
+        assert_eq!(ansi256(Color { r: 255, g: 0, b: 0 }), 196);
+        assert_eq!(
+            ansi256(Color {
+                r: 100,
+                g: 140,
+                b: 180
+            }),
+            67
+        );
+    }
+
+    #[test]
+    fn truncate_labels() {
+        assert_eq!(truncate("main", 5, TextTruncateDirection::Left), "main");
+        assert_eq!(truncate("main", 4, TextTruncateDirection::Left), "..n");
+        assert_eq!(
+            truncate("function", 6, TextTruncateDirection::Right),
+            "fun.."
+        );
+        assert_eq!(
+            truncate("function", 6, TextTruncateDirection::Left),
+            "..ion"
+        );
+    }
+}
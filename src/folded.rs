@@ -0,0 +1,872 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use log::warn;
+use num_format::{Buffer, Locale};
+use serde::{Serialize, Serializer};
+
+use crate::input;
+
+/// A single line of folded stack input, borrowed from the line it was parsed from.
+///
+/// A folded stack line consists of the following whitespace-separated fields:
+///
+///  - A semicolon-separated list of frame names (e.g., `main;foo;bar;baz`), which may itself
+///    contain spaces.
+///  - An optional sample count from a first profile, as written by
+///    [`differential`](crate::differential).
+///  - A sample count.
+///
+/// Sample counts may be fractional weights, such as milliseconds; see [`Count`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FoldedLine<'a> {
+    /// The semicolon-separated frames of the stack.
+    pub stack: &'a str,
+
+    /// The sample count of the stack (the second count, if there are two).
+    pub count: Count,
+
+    /// The first sample count of the stack, if there are two.
+    pub before: Option<Count>,
+}
+
+impl<'a> FoldedLine<'a> {
+    /// Parse a folded stack line.
+    ///
+    /// Returns `None` if the line has no sample count or no stack. Comment lines (see
+    /// [`is_comment`]) are not treated specially, so they should be skipped before calling this.
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let line = line.trim();
+        let (rest, count) = split_count(line)?;
+        let (stack, before) = match split_count(rest) {
+            // A stack of a single, numeric frame has no first count.
+            Some((stack, before)) if !stack.is_empty() => (stack, Some(before)),
+            _ => (rest, None),
+        };
+        if stack.is_empty() {
+            return None;
+        }
+        Some(FoldedLine {
+            stack,
+            count,
+            before,
+        })
+    }
+}
+
+/// Whether a line of folded stack input carries no stack.
+///
+/// This is the case for blank lines and comments, which start with `#` followed by whitespace.
+pub fn is_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line == "#" || line.starts_with("# ") || line.starts_with("#\t")
+}
+
+// Split a sample count off the end of a line, returning the rest of the line and the count.
+fn split_count(line: &str) -> Option<(&str, Count)> {
+    let (rest, samples) = match line.rfind([' ', '\t']) {
+        Some(i) => (line[..i].trim_end(), &line[i + 1..]),
+        None => ("", line),
+    };
+    Some((rest, samples.parse().ok()?))
+}
+
+/// The start of the header line of multi-metric folded stacks.
+const METRICS_HEADER: &str = "# metrics:";
+
+/// The names of the metrics of multi-metric folded stacks, if `line` is their header line.
+///
+/// Multi-metric folded stacks carry one sample count for each of several metrics, such as the
+/// cycles, instructions and cache misses of a stack as counted by different `perf` events. They
+/// start with a header line that names the metrics in the order of the counts:
+///
+/// ```text
+/// # metrics: cycles instructions cache-misses
+/// main;compute 1200 2400 3
+/// main;wait 300 150 0
+/// ```
+///
+/// The header line is a [comment](is_comment), so that it is skipped by tools that only read
+/// the stacks. See [`MetricsLine`] for the lines that follow it.
+pub fn parse_metrics_header(line: &str) -> Option<Vec<&str>> {
+    let names: Vec<&str> = line
+        .trim()
+        .strip_prefix(METRICS_HEADER)?
+        .split_whitespace()
+        .collect();
+    if names.is_empty() {
+        None
+    } else {
+        Some(names)
+    }
+}
+
+/// Write the header line of multi-metric folded stacks with the given metrics.
+///
+/// See [`parse_metrics_header`] for the format. Returns an error of kind `InvalidInput`, without
+/// writing anything, if a name is empty or contains whitespace, since it could not be read back.
+pub fn write_metrics_header<W, S>(mut writer: W, names: &[S]) -> io::Result<()>
+where
+    W: Write,
+    S: AsRef<str>,
+{
+    if let Some(name) = names
+        .iter()
+        .map(AsRef::as_ref)
+        .find(|name| name.is_empty() || name.contains(char::is_whitespace))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid metric name '{}'", name),
+        ));
+    }
+    writer.write_all(METRICS_HEADER.as_bytes())?;
+    for name in names {
+        write!(writer, " {}", name.as_ref())?;
+    }
+    writeln!(writer)
+}
+
+/// A single line of multi-metric folded stack input, borrowed from the line it was parsed from.
+///
+/// The line consists of a semicolon-separated list of frame names followed by one sample count
+/// for each metric named by the [header line](parse_metrics_header).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetricsLine<'a> {
+    /// The semicolon-separated frames of the stack.
+    pub stack: &'a str,
+
+    /// The sample counts of the stack, in the order of the metrics.
+    pub counts: Vec<Count>,
+}
+
+impl<'a> MetricsLine<'a> {
+    /// Parse a multi-metric folded stack line with `metrics` sample counts.
+    ///
+    /// Returns `None` if the line has fewer sample counts or no stack.
+    pub fn parse(line: &'a str, metrics: usize) -> Option<Self> {
+        let mut stack = line.trim();
+        let mut counts = vec![Count::ZERO; metrics];
+        for count in counts.iter_mut().rev() {
+            (stack, *count) = split_count(stack)?;
+        }
+        if stack.is_empty() {
+            return None;
+        }
+        Some(MetricsLine { stack, counts })
+    }
+}
+
+/// The number of fractional digits that a [`Count`] keeps.
+const DECIMALS: usize = 9;
+
+/// The number of units of a [`Count`] in a count of one.
+const SCALE: u128 = 1_000_000_000;
+
+/// The sample count, or weight, of a stack.
+///
+/// Counts are either whole numbers, such as numbers of samples or bytes up to `u64::MAX`, or
+/// decimals, such as `12.5` milliseconds. They are stored as fixed-point numbers with nine
+/// fractional digits, so that adding them up has none of the cumulative rounding errors of
+/// floating-point numbers (see <https://github.com/brendangregg/FlameGraph/pull/18>). More
+/// fractional digits are rounded when a count is parsed.
+///
+/// Counts are displayed the way they are written in folded stacks: whole numbers without a
+/// fractional part, and decimals without trailing zeros. With a precision, as in `{:.2}`, they
+/// are rounded to that many fractional digits instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Count(u128);
+
+impl Count {
+    /// A count of zero.
+    pub const ZERO: Count = Count(0);
+
+    /// A count of one.
+    pub const ONE: Count = Count(SCALE);
+
+    /// Convert a floating-point number to a count, rounded to nine fractional digits.
+    ///
+    /// Returns `None` if `value` is negative, not finite, or too large.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        let units = (value * SCALE as f64).round();
+        if units.is_finite() && units >= 0.0 && units < u128::MAX as f64 {
+            Some(Count(units as u128))
+        } else {
+            None
+        }
+    }
+
+    /// The count as a floating-point number.
+    pub fn as_f64(self) -> f64 {
+        // Convert the whole part on its own, so that whole counts up to 2^53 stay exact.
+        (self.0 / SCALE) as f64 + (self.0 % SCALE) as f64 / SCALE as f64
+    }
+
+    /// Whether the count has no fractional part.
+    pub fn is_integer(self) -> bool {
+        self.0 % SCALE == 0
+    }
+
+    /// The count rounded to the nearest whole number, or `u64::MAX` if it is larger.
+    pub fn round(self) -> u64 {
+        u64::try_from((self.0 + SCALE / 2) / SCALE).unwrap_or(u64::MAX)
+    }
+
+    /// Scale the count by `factor`, rounded to nine fractional digits. Scaling by a negative
+    /// factor gives zero.
+    pub fn scale(self, factor: f64) -> Count {
+        if factor == 1.0 {
+            self
+        } else {
+            Count::from_f64(self.as_f64() * factor).unwrap_or_default()
+        }
+    }
+
+    /// The difference between this count and `before`.
+    pub fn delta(self, before: Count) -> Delta {
+        Delta(self.0 as i128 - before.0 as i128)
+    }
+}
+
+impl From<u64> for Count {
+    fn from(count: u64) -> Self {
+        Count(count as u128 * SCALE)
+    }
+}
+
+impl From<u32> for Count {
+    fn from(count: u32) -> Self {
+        Count::from(u64::from(count))
+    }
+}
+
+impl From<usize> for Count {
+    fn from(count: usize) -> Self {
+        Count::from(count as u64)
+    }
+}
+
+impl FromStr for Count {
+    type Err = String;
+
+    /// Parse a count of decimal digits, which may have a fractional part, such as `42`, `12.5`
+    /// or `20.`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if whole.is_empty()
+            || !whole.bytes().all(|b| b.is_ascii_digit())
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(format!("invalid count: {}", s));
+        }
+        let whole: u64 = whole
+            .parse()
+            .map_err(|_| format!("count too large: {}", s))?;
+
+        let digits = &fraction.as_bytes()[..fraction.len().min(DECIMALS)];
+        let mut units = digits
+            .iter()
+            .fold(0, |units, &b| units * 10 + u128::from(b - b'0'));
+        units *= 10u128.pow((DECIMALS - digits.len()) as u32);
+        if fraction.len() > DECIMALS && fraction.as_bytes()[DECIMALS] >= b'5' {
+            units += 1;
+        }
+        Ok(Count(u128::from(whole) * SCALE + units))
+    }
+}
+
+impl fmt::Display for Count {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut units = self.0;
+        if let Some(precision) = f.precision().filter(|&p| p < DECIMALS) {
+            let step = 10u128.pow((DECIMALS - precision) as u32);
+            units = (units + step / 2) / step * step;
+        }
+        let (whole, fraction) = (units / SCALE, units % SCALE);
+        match f.precision() {
+            Some(0) => write!(f, "{}", whole),
+            Some(precision) if precision <= DECIMALS => {
+                let fraction = format!("{:09}", fraction);
+                write!(f, "{}.{}", whole, &fraction[..precision])
+            }
+            Some(precision) => write!(f, "{}.{:0<width$}", whole, fraction, width = precision),
+            None if fraction == 0 => write!(f, "{}", whole),
+            None => {
+                let fraction = format!("{:09}", fraction);
+                write!(f, "{}.{}", whole, fraction.trim_end_matches('0'))
+            }
+        }
+    }
+}
+
+impl Serialize for Count {
+    /// Counts are serialized as integers if they are whole numbers, or as floating-point numbers
+    /// otherwise.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match u64::try_from(self.0 / SCALE) {
+            Ok(whole) if self.is_integer() => serializer.serialize_u64(whole),
+            _ => serializer.serialize_f64(self.as_f64()),
+        }
+    }
+}
+
+impl Add for Count {
+    type Output = Count;
+
+    fn add(self, rhs: Count) -> Count {
+        Count(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Count {
+    fn add_assign(&mut self, rhs: Count) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Count {
+    type Output = Count;
+
+    fn sub(self, rhs: Count) -> Count {
+        Count(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Count {
+    fn sub_assign(&mut self, rhs: Count) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Sum for Count {
+    fn sum<I: Iterator<Item = Count>>(iter: I) -> Count {
+        iter.fold(Count::ZERO, Add::add)
+    }
+}
+
+/// The signed difference between two [`Count`]s, such as the change in the sample count of a
+/// stack from a first profile to a second one.
+///
+/// Deltas are displayed like counts, with a leading `-` if they are negative. With the `+` flag,
+/// as in `{:+}`, positive deltas have a leading `+`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Delta(i128);
+
+impl Delta {
+    /// A delta of zero.
+    pub const ZERO: Delta = Delta(0);
+
+    /// The delta as a floating-point number.
+    pub fn as_f64(self) -> f64 {
+        let magnitude = self.magnitude().as_f64();
+        if self.is_negative() {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// The absolute value of the delta.
+    pub fn magnitude(self) -> Count {
+        Count(self.0.unsigned_abs())
+    }
+
+    /// Whether the delta is negative.
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// Scale the delta by `factor`, rounded to nine fractional digits.
+    pub fn scale(self, factor: f64) -> Delta {
+        if factor == 1.0 {
+            self
+        } else {
+            Delta((self.0 as f64 * factor).round() as i128)
+        }
+    }
+}
+
+impl fmt::Display for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_negative() {
+            f.write_str("-")?;
+        } else if f.sign_plus() && self.0 > 0 {
+            f.write_str("+")?;
+        }
+        match f.precision() {
+            Some(precision) => write!(f, "{:.*}", precision, self.magnitude()),
+            None => write!(f, "{}", self.magnitude()),
+        }
+    }
+}
+
+impl Serialize for Delta {
+    /// Deltas are serialized as integers if they are whole numbers, or as floating-point numbers
+    /// otherwise.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match i64::try_from(self.0 / SCALE as i128) {
+            Ok(whole) if self.magnitude().is_integer() => serializer.serialize_i64(whole),
+            _ => serializer.serialize_f64(self.as_f64()),
+        }
+    }
+}
+
+impl Neg for Delta {
+    type Output = Delta;
+
+    fn neg(self) -> Delta {
+        Delta(-self.0)
+    }
+}
+
+impl Add for Delta {
+    type Output = Delta;
+
+    fn add(self, rhs: Delta) -> Delta {
+        Delta(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Delta {
+    fn add_assign(&mut self, rhs: Delta) {
+        self.0 += rhs.0;
+    }
+}
+
+/// Insert thousands separators into the whole part of a formatted count or delta, so that
+/// `-1234567.5` becomes `-1,234,567.5`. The whole part is formatted by `num_format`, and the
+/// sign and fractional part are kept as they are.
+pub(crate) fn group_thousands(number: &str) -> String {
+    let start = number.find(|c: char| c.is_ascii_digit()).unwrap_or(0);
+    let end = number.find('.').unwrap_or(number.len());
+    let (sign, whole, fraction) = (&number[..start], &number[start..end], &number[end..]);
+    let Ok(whole) = whole.parse::<u128>() else {
+        return number.to_string();
+    };
+    let mut buffer = Buffer::default();
+    buffer.write_formatted(&whole, &Locale::en);
+    format!("{}{}{}", sign, buffer.as_str(), fraction)
+}
+
+/// A stack and its sample counts, as stored in [`FoldedStacks`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FoldedStack {
+    /// The semicolon-separated frames of the stack.
+    pub stack: String,
+
+    /// The sample count of the stack (the second count, if there are two).
+    pub count: Count,
+
+    /// The first sample count of the stack, if there are two.
+    pub before: Option<Count>,
+}
+
+impl FoldedStack {
+    /// A stack with a single sample count.
+    pub fn new<S: Into<String>, C: Into<Count>>(stack: S, count: C) -> Self {
+        FoldedStack {
+            stack: stack.into(),
+            count: count.into(),
+            before: None,
+        }
+    }
+
+    /// The frames of the stack, root first.
+    pub fn frames(&self) -> std::str::Split<'_, char> {
+        self.stack.split(';')
+    }
+}
+
+impl<'a> From<FoldedLine<'a>> for FoldedStack {
+    fn from(line: FoldedLine<'a>) -> Self {
+        FoldedStack {
+            stack: line.stack.to_string(),
+            count: line.count,
+            before: line.before,
+        }
+    }
+}
+
+impl fmt::Display for FoldedStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.before {
+            Some(before) => write!(f, "{} {} {}", self.stack, before, self.count),
+            None => write!(f, "{} {}", self.stack, self.count),
+        }
+    }
+}
+
+/// A list of folded stacks, as produced by the [collapsers](crate::collapse) and consumed by
+/// [`flamegraph`](crate::flamegraph).
+///
+/// Stacks are kept in the order they were read or added in. The same stack may appear more than
+/// once; use [`merge`](FoldedStacks::merge) to sum up the counts of identical stacks.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FoldedStacks {
+    stacks: Vec<FoldedStack>,
+}
+
+impl FoldedStacks {
+    /// An empty list of stacks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read folded stack lines from `reader`.
+    ///
+    /// Blank lines and comments are skipped. Lines that cannot be parsed are skipped with a
+    /// warning. Of [multi-metric](parse_metrics_header) stacks, only the counts of the first
+    /// metric are kept, with a warning.
+    pub fn from_reader<R: BufRead>(mut reader: R) -> io::Result<Self> {
+        let mut stacks = Self::new();
+        let mut metrics = None;
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let l = String::from_utf8_lossy(&line);
+            if is_comment(&l) {
+                if let Some(names) = parse_metrics_header(&l) {
+                    warn!(
+                        "Only using the first metric of multi-metric stacks: {}",
+                        names[0]
+                    );
+                    metrics = Some(names.len());
+                }
+                continue;
+            }
+            let parsed = match metrics {
+                Some(metrics) => MetricsLine::parse(&l, metrics).map(|parsed| FoldedStack {
+                    stack: parsed.stack.to_string(),
+                    count: parsed.counts[0],
+                    before: None,
+                }),
+                None => FoldedLine::parse(&l).map(FoldedStack::from),
+            };
+            match parsed {
+                Some(stack) => stacks.push(stack),
+                None => warn!("Unable to parse line: {}", l.trim_end()),
+            }
+        }
+        Ok(stacks)
+    }
+
+    /// Parse folded stack lines from a string.
+    ///
+    /// See [`from_reader`](FoldedStacks::from_reader) for how invalid lines are handled.
+    pub fn from_str_lossy(s: &str) -> Self {
+        Self::from_reader(s.as_bytes()).expect("reading from a byte slice cannot fail")
+    }
+
+    /// Add a stack to the end of the list.
+    pub fn push(&mut self, stack: FoldedStack) {
+        self.stacks.push(stack);
+    }
+
+    /// The number of stacks.
+    pub fn len(&self) -> usize {
+        self.stacks.len()
+    }
+
+    /// Whether there are no stacks.
+    pub fn is_empty(&self) -> bool {
+        self.stacks.is_empty()
+    }
+
+    /// Iterate over the stacks.
+    pub fn iter(&self) -> std::slice::Iter<'_, FoldedStack> {
+        self.stacks.iter()
+    }
+
+    /// Iterate mutably over the stacks.
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, FoldedStack> {
+        self.stacks.iter_mut()
+    }
+
+    /// Whether any stack has two sample counts.
+    pub fn is_differential(&self) -> bool {
+        self.stacks.iter().any(|s| s.before.is_some())
+    }
+
+    /// The sum of the sample counts of all stacks.
+    pub fn total(&self) -> Count {
+        self.stacks.iter().map(|s| s.count).sum()
+    }
+
+    /// The sum of the first sample counts of all stacks, counting stacks with only one sample
+    /// count as zero.
+    pub fn total_before(&self) -> Count {
+        self.stacks.iter().filter_map(|s| s.before).sum()
+    }
+
+    /// Replace every stack by the result of `f`, keeping its counts.
+    pub fn map_stacks<F>(mut self, mut f: F) -> Self
+    where
+        F: FnMut(&str) -> String,
+    {
+        for s in &mut self.stacks {
+            s.stack = f(&s.stack);
+        }
+        self
+    }
+
+    /// Keep only the stacks for which `f` returns `true`.
+    pub fn filter<F>(mut self, mut f: F) -> Self
+    where
+        F: FnMut(&FoldedStack) -> bool,
+    {
+        self.stacks.retain(|s| f(s));
+        self
+    }
+
+    /// Sum up the counts of identical stacks, and sort the stacks.
+    ///
+    /// If any of the merged stacks has two sample counts, so does the result; stacks with only
+    /// one count add nothing to its first count.
+    pub fn merge(self) -> Self {
+        let mut merged: BTreeMap<String, (Count, Option<Count>)> = BTreeMap::new();
+        for s in self.stacks {
+            let (count, before) = merged.entry(s.stack).or_default();
+            *count += s.count;
+            if let Some(b) = s.before {
+                *before = Some(before.unwrap_or_default() + b);
+            }
+        }
+        merged
+            .into_iter()
+            .map(|(stack, (count, before))| FoldedStack {
+                stack,
+                count,
+                before,
+            })
+            .collect()
+    }
+
+    /// Write the stacks to `writer` in the folded format, one per line.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for s in &self.stacks {
+            writeln!(writer, "{}", s)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FoldedStacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for s in &self.stacks {
+            writeln!(f, "{}", s)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromIterator<FoldedStack> for FoldedStacks {
+    fn from_iter<I: IntoIterator<Item = FoldedStack>>(iter: I) -> Self {
+        FoldedStacks {
+            stacks: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<FoldedStack> for FoldedStacks {
+    fn extend<I: IntoIterator<Item = FoldedStack>>(&mut self, iter: I) {
+        self.stacks.extend(iter);
+    }
+}
+
+impl IntoIterator for FoldedStacks {
+    type Item = FoldedStack;
+    type IntoIter = std::vec::IntoIter<FoldedStack>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.stacks.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a FoldedStacks {
+    type Item = &'a FoldedStack;
+    type IntoIter = std::slice::Iter<'a, FoldedStack>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.stacks.iter()
+    }
+}
+
+/// Read the folded stacks of all `files`, one after another.
+///
+/// If `files` is empty, or is just `-`, STDIN will be used as input. Compressed files are
+/// decompressed with the `compression` feature.
+pub(crate) fn read_files(files: &[PathBuf]) -> io::Result<FoldedStacks> {
+    if files.is_empty() || files.len() == 1 && files[0].to_str() == Some("-") {
+        return FoldedStacks::from_reader(input::stdin()?);
+    }
+
+    let mut stacks = FoldedStacks::new();
+    for file in files {
+        stacks.extend(FoldedStacks::from_reader(input::open(file)?)?);
+    }
+    Ok(stacks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(s: &str) -> Count {
+        s.parse().unwrap()
+    }
+
+    fn line<'a>(stack: &'a str, count: &str, before: Option<&str>) -> Option<FoldedLine<'a>> {
+        Some(FoldedLine {
+            stack,
+            count: count.parse().unwrap(),
+            before: before.map(|b| b.parse().unwrap()),
+        })
+    }
+
+    #[test]
+    fn parse_line() {
+        assert_eq!(FoldedLine::parse("a;b;c 42"), line("a;b;c", "42", None));
+        assert_eq!(
+            FoldedLine::parse("a;b c 1 2\n"),
+            line("a;b c", "2", Some("1"))
+        );
+        assert_eq!(FoldedLine::parse("  a;b\t3  "), line("a;b", "3", None));
+        assert_eq!(FoldedLine::parse("a;b 3.000"), line("a;b", "3", None));
+        assert_eq!(FoldedLine::parse("a;b 3.5"), line("a;b", "3.5", None));
+        assert_eq!(
+            FoldedLine::parse("a;b 0.25 20."),
+            line("a;b", "20", Some("0.25"))
+        );
+        assert_eq!(FoldedLine::parse(""), None);
+        assert_eq!(FoldedLine::parse("42"), None);
+        assert_eq!(FoldedLine::parse("  42"), None);
+        assert_eq!(FoldedLine::parse("5 3"), line("5", "3", None));
+        assert_eq!(FoldedLine::parse("no;count"), None);
+        assert_eq!(FoldedLine::parse("a;b 1x"), None);
+        assert_eq!(FoldedLine::parse("a;b -1"), None);
+        assert_eq!(FoldedLine::parse("a;b .5"), None);
+    }
+
+    #[test]
+    fn parse_and_display_counts() {
+        assert_eq!(count("12.5").to_string(), "12.5");
+        assert_eq!(count("12.500").to_string(), "12.5");
+        assert_eq!(count("7.").to_string(), "7");
+        assert_eq!(count("0.0000000014").to_string(), "0.000000001");
+        assert_eq!(count("0.0000000015").to_string(), "0.000000002");
+        assert_eq!(
+            count("18446744073709551615").to_string(),
+            "18446744073709551615"
+        );
+        assert!("18446744073709551616".parse::<Count>().is_err());
+
+        assert_eq!(format!("{:.2}", count("12.345")), "12.35");
+        assert_eq!(format!("{:.0}", count("12.5")), "13");
+        assert_eq!(format!("{:.12}", count("1.5")), "1.500000000000");
+
+        let sum: Count = ["0.1", "0.2", "0.3"].iter().map(|c| count(c)).sum();
+        assert_eq!(sum, count("0.6"));
+        assert_eq!(
+            Count::from(u64::MAX) + Count::ONE,
+            count("18446744073709551615") + count("1")
+        );
+        assert_eq!(Count::from_f64(2.25), Some(count("2.25")));
+        assert_eq!(Count::from_f64(-1.0), None);
+        assert_eq!(count("2.5").round(), 3);
+
+        let delta = count("1.5").delta(count("3"));
+        assert_eq!(delta.to_string(), "-1.5");
+        assert_eq!(format!("{:+}", -delta), "+1.5");
+        assert_eq!(format!("{:+}", Delta::ZERO), "0");
+        assert_eq!(
+            group_thousands(&format!("{:.1}", count("1234567.25"))),
+            "1,234,567.3"
+        );
+        assert_eq!(group_thousands("-123"), "-123");
+    }
+
+    #[test]
+    fn comments() {
+        assert!(is_comment(""));
+        assert!(is_comment("  \n"));
+        assert!(is_comment("#"));
+        assert!(is_comment("# a;b 1"));
+        assert!(!is_comment("#a;b 1"));
+        assert!(!is_comment("a;b 1"));
+    }
+
+    #[test]
+    fn parse_metrics() {
+        assert_eq!(
+            parse_metrics_header("# metrics: cycles  instructions\n"),
+            Some(vec!["cycles", "instructions"])
+        );
+        assert_eq!(parse_metrics_header("# metrics:"), None);
+        assert_eq!(parse_metrics_header("# comment"), None);
+        let mut header = Vec::new();
+        write_metrics_header(&mut header, &["cycles", "instructions"]).unwrap();
+        assert_eq!(header, b"# metrics: cycles instructions\n");
+        for names in [&["cycles", ""], &["cycles", "cache misses"]] {
+            let error = write_metrics_header(io::sink(), names).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+
+        assert_eq!(
+            MetricsLine::parse("a;b c 1 2.5 0\n", 3),
+            Some(MetricsLine {
+                stack: "a;b c",
+                counts: vec![count("1"), count("2.5"), Count::ZERO],
+            })
+        );
+        assert_eq!(MetricsLine::parse("a;b 1 2", 3), None);
+        assert_eq!(MetricsLine::parse("1 2", 2), None);
+
+        let stacks = FoldedStacks::from_str_lossy("# metrics: cycles ipc\na;b 3 1.5\na 2 1\n");
+        assert_eq!(stacks.to_string(), "a;b 3\na 2\n");
+    }
+
+    #[test]
+    fn parse_transform_write() {
+        let stacks = FoldedStacks::from_str_lossy(
+            "# comment\nmain;b 2\nmain;0x1f 1\n\nbad line\nmain;0x2e 3\nother 4.5\n",
+        );
+        assert_eq!(stacks.len(), 4);
+        assert_eq!(stacks.total(), count("10.5"));
+        assert!(!stacks.is_differential());
+
+        let stacks = stacks
+            .map_stacks(|s| s.replace("0x1f", "0x").replace("0x2e", "0x"))
+            .filter(|s| s.frames().next() == Some("main"))
+            .merge();
+        assert_eq!(stacks.to_string(), "main;0x 4\nmain;b 2\n");
+    }
+
+    #[test]
+    fn merge_differential() {
+        let stacks: FoldedStacks = vec![
+            FoldedStack {
+                stack: "a".into(),
+                count: Count::ONE,
+                before: Some(count("2")),
+            },
+            FoldedStack::new("a", 3u64),
+            FoldedStack::new("b", 5u64),
+        ]
+        .into_iter()
+        .collect();
+        assert!(stacks.is_differential());
+        assert_eq!(stacks.total_before(), count("2"));
+
+        let mut out = Vec::new();
+        stacks.merge().write_to(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a 2 4\nb 5\n");
+    }
+}
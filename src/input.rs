@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+#[cfg(feature = "compression")]
+use log::debug;
+
+use crate::collapse::common::CAPACITY_READER;
+
+#[cfg(feature = "compression")]
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+#[cfg(feature = "compression")]
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+#[cfg(feature = "compression")]
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Opens the file at `path` for reading, and decompresses it if it is compressed.
+///
+/// See [`decompress`] for details.
+pub(crate) fn open<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn BufRead>> {
+    decompress(BufReader::with_capacity(CAPACITY_READER, File::open(path)?))
+}
+
+/// Opens STDIN for reading, and decompresses it if it is compressed.
+///
+/// See [`decompress`] for details.
+pub(crate) fn stdin() -> io::Result<Box<dyn BufRead>> {
+    decompress(BufReader::with_capacity(
+        CAPACITY_READER,
+        io::stdin().lock(),
+    ))
+}
+
+/// Returns a reader of the decompressed contents of `reader` if they are compressed with gzip,
+/// zstd or xz, or of the contents as they are otherwise.
+///
+/// The compression format is detected by the magic bytes at the start of the contents, as
+/// returned by the first call to `fill_buf`. Concatenated streams, as written by `pigz` or
+/// `zstd -T0`, are decompressed one after another. Without the `compression` feature, the
+/// contents are always read as they are.
+#[cfg(feature = "compression")]
+pub(crate) fn decompress<'a, R>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>>
+where
+    R: BufRead + 'a,
+{
+    let magic = reader.fill_buf()?;
+    let decoder: Box<dyn io::Read + 'a> = if magic.starts_with(GZIP_MAGIC) {
+        debug!("Decompressing gzip input");
+        Box::new(libflate::gzip::MultiDecoder::new(reader)?)
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        debug!("Decompressing zstd input");
+        Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)
+    } else if magic.starts_with(XZ_MAGIC) {
+        debug!("Decompressing xz input");
+        Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader))
+    } else {
+        return Ok(Box::new(reader));
+    };
+    Ok(Box::new(BufReader::with_capacity(CAPACITY_READER, decoder)))
+}
+
+/// Returns a reader of the decompressed contents of `reader` if they are compressed with gzip,
+/// zstd or xz, or of the contents as they are otherwise.
+///
+/// The compression format is detected by the magic bytes at the start of the contents, as
+/// returned by the first call to `fill_buf`. Concatenated streams, as written by `pigz` or
+/// `zstd -T0`, are decompressed one after another. Without the `compression` feature, the
+/// contents are always read as they are.
+#[cfg(not(feature = "compression"))]
+pub(crate) fn decompress<'a, R>(reader: R) -> io::Result<Box<dyn BufRead + 'a>>
+where
+    R: BufRead + 'a,
+{
+    Ok(Box::new(reader))
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+
+    fn decompressed(input: &[u8]) -> String {
+        let mut output = String::new();
+        decompress(input)
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn decompress_by_magic_bytes() {
+        let text = "main;run 1\nmain;idle 2\n";
+
+        let mut encoder = libflate::gzip::Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(text.as_bytes()).unwrap();
+        let gzip = encoder.finish().into_result().unwrap();
+        assert_eq!(decompressed(&gzip), text);
+
+        let zstd = zstd::encode_all(text.as_bytes(), 0).unwrap();
+        assert_eq!(decompressed(&zstd), text);
+
+        let mut xz = Vec::new();
+        xz2::read::XzEncoder::new(text.as_bytes(), 6)
+            .read_to_end(&mut xz)
+            .unwrap();
+        assert_eq!(decompressed(&xz), text);
+
+        assert_eq!(decompressed(text.as_bytes()), text);
+        assert_eq!(decompressed(b""), "");
+    }
+
+    #[test]
+    fn decompress_concatenated_streams() {
+        let mut zstd = zstd::encode_all(&b"main;run 1\n"[..], 0).unwrap();
+        zstd.extend(zstd::encode_all(&b"main;idle 2\n"[..], 0).unwrap());
+        assert_eq!(decompressed(&zstd), "main;run 1\nmain;idle 2\n");
+    }
+}
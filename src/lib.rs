@@ -82,6 +82,105 @@
 //! $ inferno-collapse-vtune result.csv > stacks.folded
 //! ```
 //!
+//! ### callgrind (Valgrind)
+//!
+//! ```console
+//! $ valgrind --tool=callgrind --callgrind-out-file=callgrind.out target/release/mybin
+//! $ inferno-collapse-callgrind --event Ir callgrind.out > stacks.folded
+//! ```
+//!
+//! ### jstack (Java)
+//!
+//! ```console
+//! $ for i in $(seq 60); do jstack $pid; sleep 1; done > dumps.txt
+//! $ inferno-collapse-jstack --include-state dumps.txt > stacks.folded
+//! ```
+//!
+//! Use `inferno-flamegraph --colors java` to tell the thread state frames apart.
+//!
+//! ### bpftrace and BCC
+//!
+//! ```console
+//! $ sudo bpftrace -e 'profile:hz:99 { @[kstack, ustack, comm] = count(); }' > stacks.txt
+//! $ inferno-collapse-bpftrace --kernel stacks.txt > stacks.folded
+//! ```
+//!
+//! The multi-line output of BCC's `profile` and `offcputime` is handled too; pass them `-d` so
+//! that kernel and user stacks can be told apart.
+//!
+//! ### Firefox Profiler (samply)
+//!
+//! ```console
+//! $ samply record --save-only -o profile.json target/release/mybin
+//! $ inferno-collapse-gecko --include-thread profile.json > stacks.folded
+//! ```
+//!
+//! Use `--thread REGEX` to only include the threads whose name matches.
+//!
+//! ### gdb / eu-stack
+//!
+//! A "poor man's profiler" can be built from repeated backtraces of a running process:
+//!
+//! ```console
+//! $ for i in $(seq 60); do
+//!     gdb -p $pid -batch -ex "thread apply all bt" 2>/dev/null; sleep 1
+//!   done > stacks.txt
+//! $ inferno-collapse-gdb stacks.txt > stacks.folded
+//! ```
+//!
+//! Output of `eu-stack -p $pid` is handled the same way. Pass `--threads` to give each thread
+//! its own root frame.
+//!
+//! ### pprof (Go and others)
+//!
+//! ```console
+//! $ curl -o cpu.pprof http://localhost:6060/debug/pprof/profile?seconds=30
+//! $ inferno-collapse-pprof cpu.pprof > stacks.folded
+//! ```
+//!
+//! Profiles with several sample types (e.g. heap profiles) can be collapsed on any of them with
+//! `--sample-index`.
+//!
+//! ### Chrome and Node.js
+//!
+//! ```console
+//! $ node --cpu-prof --cpu-prof-name=out.cpuprofile target/mybin.js
+//! $ inferno-collapse-chrome out.cpuprofile > stacks.folded
+//! ```
+//!
+//! Traces saved from the Performance panel of Chrome DevTools work too.
+//!
+//! ### speedscope (rbspy, py-spy, samply, ...)
+//!
+//! ```console
+//! $ py-spy record --format speedscope -o profile.json -- python app.py
+//! $ inferno-collapse-speedscope profile.json > stacks.folded
+//! ```
+//!
+//! ### Mangled names
+//!
+//! If a profiler leaves C++, Rust or Swift function names mangled (`_ZN...`, `_R...`, `$s...`),
+//! every collapser can demangle them with `--demangle`. A comma-separated list of options can also
+//! strip Rust hashes, the arguments of templates and generics, and the numbers of closures, which
+//! merges the frames that only differ in those:
+//!
+//! ```console
+//! $ perf script | inferno-collapse-perf --demangle=strip-hash,strip-generics > stacks.folded
+//! ```
+//!
+//! Swift names are only demangled with the `swift-demangle` feature, which builds Swift's C++
+//! demangler.
+//!
+//! ### Compressed input
+//!
+//! With the `compression` feature, the collapsers, `inferno-flamegraph` and the other tools that
+//! read folded stacks decompress input files and STDIN that are compressed with gzip, zstd or xz.
+//! The format is detected from the first bytes of the input, so no `zcat` is needed:
+//!
+//! ```console
+//! $ inferno-collapse-guess perf.txt.zst | inferno-flamegraph > profile.svg
+//! ```
+//!
 //! ## Producing a flame graph
 //!
 //! Once you have a folded stack file, you're ready to produce the flame graph SVG image. To do so,
@@ -94,6 +193,105 @@
 //!
 //! And then open `profile.svg` in your viewer of choice.
 //!
+//! To embed the flame graph in a web page with [d3-flame-graph] instead, have `inferno-flamegraph`
+//! write the call tree as JSON:
+//!
+//! ```console
+//! $ cat stacks.folded | inferno-flamegraph --format json > profile.json
+//! ```
+//!
+//! Some viewers strip the scripts from SVG images, which breaks zooming and searching. For those,
+//! `--format html` writes a self-contained HTML page with the flame graph, a search box and a
+//! table of the functions with the most samples of their own.
+//!
+//! On a remote machine, `--format term` draws the flame graph right in the terminal with colored
+//! text, sized to the width of the terminal:
+//!
+//! ```console
+//! $ cat stacks.folded | inferno-flamegraph --format term
+//! ```
+//!
+//! For places that don't show SVG images, such as many chat systems, `--format png` draws the
+//! flame graph as a PNG image instead. This needs the `png` cargo feature, which is off by
+//! default.
+//!
+//! Folded stacks can also be opened in [speedscope]. `inferno-to-speedscope` converts them into a
+//! speedscope file, and with `--flamechart` keeps the stacks in input order so that speedscope
+//! shows them as a timeline:
+//!
+//! ```console
+//! $ inferno-to-speedscope stacks.folded > profile.speedscope.json
+//! ```
+//!
+//! To upload folded stacks to tools and storage backends that read [pprof] profiles, convert them
+//! with `inferno-to-pprof`:
+//!
+//! ```console
+//! $ inferno-to-pprof --sample-type cpu --countname nanoseconds stacks.folded > profile.pb.gz
+//! ```
+//!
+//! To see what runs under a group of functions no matter where they were called from, re-root
+//! the stacks at the frames that match a regular expression. Every stack without such a frame is
+//! left out, and the frames above the match are cut off, so all occurrences are merged.
+//! `--reroot-match last` re-roots at the innermost match instead of the outermost one:
+//!
+//! ```console
+//! $ cat stacks.folded | inferno-flamegraph --reroot '^tokio::runtime::task::.*::poll$' > tasks.svg
+//! ```
+//!
+//! To see where a function that is called from many places, such as `memcpy`, gets its samples
+//! from and spends them, draw a butterfly graph of it. Its callers are merged into an inverted
+//! graph above it, and its callees into a normal graph below it:
+//!
+//! ```console
+//! $ cat stacks.folded | inferno-flamegraph --butterfly memcpy > memcpy.svg
+//! ```
+//!
+//! When a table is all you need, `inferno-top` lists the functions with the most samples, either
+//! where they are the leaf frame (`--sort self`) or anywhere on the stack (`--sort total`). The
+//! table can also be written as CSV or JSON with `--format`:
+//!
+//! ```console
+//! $ inferno-top --sort total -n 10 stacks.folded
+//! ```
+//!
+//! To clean up folded stacks before drawing them, `inferno-filter` keeps or drops whole stacks
+//! that match regular expressions, drops single frames, truncates stacks at matching frames, and
+//! limits their depth. Stacks that become identical are merged, and both counts of differential
+//! input are kept:
+//!
+//! ```console
+//! $ inferno-filter --drop '^swapper;' --drop-frames '^\[unknown\]$' stacks.folded \
+//!     | inferno-flamegraph > flamegraph.svg
+//! ```
+//!
+//! Sample counts don't have to be whole numbers of samples. Weights such as milliseconds
+//! (`main;work 12.5`) or byte counts larger than 2^32 are kept exactly, and the tooltips show as
+//! many decimal places as the counts have. `--count-format integer` rounds the counts in the
+//! tooltips instead, and `--count-format decimal:3` always shows three decimal places:
+//!
+//! ```console
+//! $ cat wall-time.folded | inferno-flamegraph --countname ms --count-format decimal:1 > wall.svg
+//! ```
+//!
+//! When `perf record` samples several events at once, as with `-e cycles,instructions`,
+//! `inferno-collapse-perf --all-events` counts all of them in one pass and writes one column of
+//! counts per event, below a `# metrics:` header line. `inferno-flamegraph` then sizes the frames
+//! by one of the events (`--metric`), and shows the others and ratios between them in the
+//! tooltips:
+//!
+//! ```console
+//! $ perf script | inferno-collapse-perf --all-events \
+//!     | inferno-flamegraph --metric cycles --ratio IPC=instructions/cycles > ipc.svg
+//! ```
+//!
+//! To get one folded stack file per event type instead, again in one pass, give
+//! `inferno-collapse-perf` a file name template:
+//!
+//! ```console
+//! $ perf script | inferno-collapse-perf --split-events 'out.{event}.folded'
+//! ```
+//!
 //! ## Differential flame graphs
 //!
 //! You can debug CPU performance regressions with the help of differential flame graphs.
@@ -137,6 +335,9 @@
 //!   [DTrace examples]: http://www.brendangregg.com/FlameGraphs/cpuflamegraphs.html#DTrace
 //!   [NodeJS's ustack helper]: http://dtrace.org/blogs/dap/2012/01/05/where-does-your-node-program-spend-its-time/
 //!   [a series of live coding sessions]: https://www.youtube.com/watch?v=jTpK-bNZiA4&list=PLqbS7AVVErFimAvMW-kIJUwxpPvcPBCsz
+//!   [d3-flame-graph]: https://github.com/spiermar/d3-flame-graph
+//!   [speedscope]: https://www.speedscope.app
+//!   [pprof]: https://github.com/google/pprof
 //!   [differential flame graphs]: http://www.brendangregg.com/blog/2014-11-09/differential-flame-graphs.html
 //!   [sample]: https://gist.github.com/loderunner/36724cc9ee8db66db305#profiling-with-sample
 //!   [VTune]: https://software.intel.com/en-us/vtune-amplifier-help-command-line-interface
@@ -161,6 +362,36 @@ pub mod collapse;
 ///   [crate-level documentation]: ../index.html
 pub mod differential;
 
+/// Conversion of folded stacks to the file formats of other profiling tools.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../index.html
+pub mod export;
+
+/// Filtering of folded stacks by regular expressions on their frames.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../index.html
+pub mod filter;
+
+/// A model of folded stacks, with a parser for the folded stack format.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../index.html
+pub mod folded;
+
+mod input;
+
+/// Textual reports of the functions with the most samples in folded stacks.
+///
+/// See the [crate-level documentation] for details.
+///
+///   [crate-level documentation]: ../index.html
+pub mod report;
+
 /// Tools for producing flame graphs from folded stack traces.
 ///
 /// See the [crate-level documentation] for details.
@@ -0,0 +1,442 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use ahash::{AHashMap, AHashSet};
+use serde::Serialize;
+
+use crate::folded::{group_thousands, Count, Delta, FoldedStacks};
+
+/// The label of sample counts if none is given.
+const DEFAULT_COUNT_NAME: &str = "samples";
+
+/// `report` configuration options.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Options {
+    /// Which count to sort the functions by, from highest to lowest.
+    ///
+    /// Default is [`SortBy::Exclusive`].
+    pub sort_by: SortBy,
+
+    /// Only report this many functions.
+    ///
+    /// Default is `None`, which reports all functions.
+    pub limit: Option<usize>,
+
+    /// The format of the report.
+    ///
+    /// Default is [`ReportFormat::Table`].
+    pub format: ReportFormat,
+
+    /// What the sample counts are, for the summary above the table.
+    ///
+    /// Default is `samples`.
+    pub count_name: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            sort_by: SortBy::default(),
+            limit: None,
+            format: ReportFormat::default(),
+            count_name: DEFAULT_COUNT_NAME.to_string(),
+        }
+    }
+}
+
+/// Which count to sort the functions of a report by.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum SortBy {
+    /// The samples in which the function is the leaf frame (its "self" samples).
+    #[default]
+    Exclusive,
+
+    /// The samples in which the function is anywhere on the stack (its "total" samples).
+    Inclusive,
+}
+
+impl SortBy {
+    /// The valid set of sort orders (via `FromStr`).
+    pub const VARIANTS: &'static [&'static str] = &["self", "total"];
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "self" => Ok(SortBy::Exclusive),
+            "total" => Ok(SortBy::Inclusive),
+            unknown => Err(format!("unknown sort order: {}", unknown)),
+        }
+    }
+}
+
+/// The format of a report.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[non_exhaustive]
+pub enum ReportFormat {
+    /// An aligned table with thousands separators, for people to read.
+    #[default]
+    Table,
+
+    /// Comma-separated values with a header row.
+    Csv,
+
+    /// A JSON array with one object per function.
+    Json,
+}
+
+impl ReportFormat {
+    /// The valid set of report formats (via `FromStr`).
+    pub const VARIANTS: &'static [&'static str] = &["table", "csv", "json"];
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(ReportFormat::Table),
+            "csv" => Ok(ReportFormat::Csv),
+            "json" => Ok(ReportFormat::Json),
+            unknown => Err(format!("unknown report format: {}", unknown)),
+        }
+    }
+}
+
+/// The sample counts of one function.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct FunctionCounts {
+    /// The name of the function.
+    pub function: String,
+
+    /// The samples in which the function is the leaf frame.
+    #[serde(rename = "self")]
+    pub self_count: Count,
+
+    /// The samples in which the function is anywhere on the stack. Samples in which the function
+    /// recurses are only counted once.
+    pub total: Count,
+
+    /// The self samples in the first profile, for differential input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub self_before: Option<Count>,
+
+    /// The total samples in the first profile, for differential input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_before: Option<Count>,
+}
+
+impl FunctionCounts {
+    /// The change in self samples, for differential input.
+    pub fn self_delta(&self) -> Option<Delta> {
+        self.self_before.map(|before| self.self_count.delta(before))
+    }
+
+    /// The change in total samples, for differential input.
+    pub fn total_delta(&self) -> Option<Delta> {
+        self.total_before.map(|before| self.total.delta(before))
+    }
+
+    fn sort_key(&self, sort_by: SortBy) -> Count {
+        match sort_by {
+            SortBy::Exclusive => self.self_count,
+            SortBy::Inclusive => self.total,
+        }
+    }
+}
+
+/// Count the self and total samples of every function in the stacks.
+///
+/// For [differential](FoldedStacks::is_differential) stacks, the counts of the first profile are
+/// in [`FunctionCounts::self_before`] and [`FunctionCounts::total_before`]. The functions are
+/// returned in no particular order.
+pub fn function_counts(stacks: &FoldedStacks) -> Vec<FunctionCounts> {
+    let differential = stacks.is_differential();
+    let mut counts: AHashMap<&str, FunctionCounts> = AHashMap::default();
+    let mut seen = AHashSet::default();
+    for stack in stacks {
+        let before = stack.before.unwrap_or_default();
+        seen.clear();
+        let mut leaf = None;
+        for function in stack.frames() {
+            leaf = Some(function);
+            // Recursive functions are on the stack more than once, but the sample only counts
+            // once towards their total.
+            if seen.insert(function) {
+                let counts = counts.entry(function).or_insert_with(|| FunctionCounts {
+                    function: function.to_string(),
+                    ..FunctionCounts::default()
+                });
+                counts.total += stack.count;
+                if differential {
+                    *counts.total_before.get_or_insert(Count::ZERO) += before;
+                }
+            }
+        }
+        if let Some(leaf) = leaf {
+            let counts = counts.get_mut(leaf).expect("leaf was counted above");
+            counts.self_count += stack.count;
+            if differential {
+                *counts.self_before.get_or_insert(Count::ZERO) += before;
+            }
+        }
+    }
+
+    let mut counts: Vec<FunctionCounts> = counts.into_values().collect();
+    if differential {
+        for counts in &mut counts {
+            counts.self_before.get_or_insert(Count::ZERO);
+        }
+    }
+    counts
+}
+
+/// Write a report of the functions with the most samples in the stacks.
+///
+/// The functions are sorted by [`Options::sort_by`], and their counts are shown as numbers and as
+/// percentages of all samples. For [differential](FoldedStacks::is_differential) stacks, the
+/// percentages are of the samples of the second profile, and the report also shows how the
+/// counts of each function changed since the first profile.
+pub fn from_stacks<W: Write>(
+    opt: &Options,
+    stacks: &FoldedStacks,
+    mut writer: W,
+) -> io::Result<()> {
+    let mut counts = function_counts(stacks);
+    counts.sort_unstable_by(|a, b| {
+        b.sort_key(opt.sort_by)
+            .cmp(&a.sort_key(opt.sort_by))
+            .then_with(|| a.function.cmp(&b.function))
+    });
+    if let Some(limit) = opt.limit {
+        counts.truncate(limit);
+    }
+
+    let report = Report {
+        counts: &counts,
+        total: stacks.total(),
+        total_before: stacks.is_differential().then(|| stacks.total_before()),
+    };
+    match opt.format {
+        ReportFormat::Table => report.write_table(opt, &mut writer)?,
+        ReportFormat::Csv => report.write_csv(&mut writer)?,
+        ReportFormat::Json => report.write_json(&mut writer)?,
+    }
+    writer.flush()
+}
+
+/// Read folded stacks from `reader` and write a report of the functions with the most samples.
+///
+/// See [`from_stacks`] for details.
+pub fn from_reader<R, W>(opt: &Options, reader: R, writer: W) -> io::Result<()>
+where
+    R: io::BufRead,
+    W: Write,
+{
+    from_stacks(opt, &FoldedStacks::from_reader(reader)?, writer)
+}
+
+/// Read folded stacks from files and write a report of the functions with the most samples.
+///
+/// If `files` is empty, or is just `-`, STDIN will be used as input. Stacks from several files
+/// are combined into one report.
+pub fn from_files<W: Write>(opt: &Options, files: &[PathBuf], writer: W) -> io::Result<()> {
+    from_stacks(opt, &crate::folded::read_files(files)?, writer)
+}
+
+struct Report<'a> {
+    counts: &'a [FunctionCounts],
+    total: Count,
+    total_before: Option<Count>,
+}
+
+#[derive(Serialize)]
+struct JsonRow<'a> {
+    #[serde(flatten)]
+    counts: &'a FunctionCounts,
+    self_pct: f64,
+    total_pct: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    self_delta: Option<Delta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_delta: Option<Delta>,
+}
+
+impl<'a> Report<'a> {
+    fn pct(&self, count: Count) -> f64 {
+        if self.total == Count::ZERO {
+            0.0
+        } else {
+            100.0 * count.as_f64() / self.total.as_f64()
+        }
+    }
+
+    fn write_table<W: Write>(&self, opt: &Options, writer: &mut W) -> io::Result<()> {
+        let formatted = |n: Count| group_thousands(&n.to_string());
+        let delta = |delta: Delta| group_thousands(&format!("{:+}", delta));
+
+        match self.total_before {
+            None => writeln!(
+                writer,
+                "Total: {} {}",
+                formatted(self.total),
+                opt.count_name
+            )?,
+            Some(before) => writeln!(
+                writer,
+                "Total: {} {} (before: {} {}, {})",
+                formatted(self.total),
+                opt.count_name,
+                formatted(before),
+                opt.count_name,
+                delta(self.total.delta(before))
+            )?,
+        }
+        writeln!(writer)?;
+
+        let differential = self.total_before.is_some();
+        let header: &[&str] = if differential {
+            &[
+                "Self",
+                "Self%",
+                "\u{394}Self",
+                "Total",
+                "Total%",
+                "\u{394}Total",
+            ]
+        } else {
+            &["Self", "Self%", "Total", "Total%"]
+        };
+        let rows: Vec<Vec<String>> = self
+            .counts
+            .iter()
+            .map(|counts| {
+                let mut row = vec![
+                    formatted(counts.self_count),
+                    format!("{:.2}%", self.pct(counts.self_count)),
+                ];
+                if let Some(d) = counts.self_delta() {
+                    row.push(delta(d));
+                }
+                row.push(formatted(counts.total));
+                row.push(format!("{:.2}%", self.pct(counts.total)));
+                if let Some(d) = counts.total_delta() {
+                    row.push(delta(d));
+                }
+                row
+            })
+            .collect();
+
+        let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let mut line = String::new();
+        let mut write_line = |cells: &[&str], function: &str| -> io::Result<()> {
+            line.clear();
+            for (cell, &width) in cells.iter().zip(&widths) {
+                let pad = width - cell.chars().count();
+                let _ = write!(line, "{:pad$}{}  ", "", cell, pad = pad);
+            }
+            line.push_str(function);
+            writeln!(writer, "{}", line)
+        };
+        write_line(header, "Function")?;
+        for (row, counts) in rows.iter().zip(self.counts) {
+            let cells: Vec<&str> = row.iter().map(String::as_str).collect();
+            write_line(&cells, &counts.function)?;
+        }
+        Ok(())
+    }
+
+    fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let differential = self.total_before.is_some();
+        write!(writer, "function,self,self_pct,total,total_pct")?;
+        if differential {
+            write!(writer, ",self_before,total_before,self_delta,total_delta")?;
+        }
+        writeln!(writer)?;
+
+        for counts in self.counts {
+            write!(
+                writer,
+                "{},{},{:.2},{},{:.2}",
+                csv_field(&counts.function),
+                counts.self_count,
+                self.pct(counts.self_count),
+                counts.total,
+                self.pct(counts.total)
+            )?;
+            if differential {
+                write!(
+                    writer,
+                    ",{},{},{},{}",
+                    counts.self_before.unwrap_or_default(),
+                    counts.total_before.unwrap_or_default(),
+                    counts.self_delta().unwrap_or_default(),
+                    counts.total_delta().unwrap_or_default()
+                )?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let rows: Vec<JsonRow<'_>> = self
+            .counts
+            .iter()
+            .map(|counts| JsonRow {
+                counts,
+                self_pct: self.pct(counts.self_count),
+                total_pct: self.pct(counts.total),
+                self_delta: counts.self_delta(),
+                total_delta: counts.total_delta(),
+            })
+            .collect();
+        serde_json::to_writer_pretty(&mut *writer, &rows)?;
+        writeln!(writer)
+    }
+}
+
+/// Quote a CSV field if it contains characters that would otherwise end it.
+fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_recursion_once() {
+        let stacks = FoldedStacks::from_str_lossy("main;a;b;a 3\nmain;a 2\nmain 1\n");
+        let mut counts = function_counts(&stacks);
+        counts.sort_unstable_by(|a, b| a.function.cmp(&b.function));
+        let counts: Vec<String> = counts
+            .iter()
+            .map(|c| format!("{} {} {}", c.function, c.self_count, c.total))
+            .collect();
+        assert_eq!(counts, vec!["a 5 5", "b 0 3", "main 1 6"]);
+    }
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(csv_field("main"), "main");
+        assert_eq!(csv_field("f<a, b>"), "\"f<a, b>\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::process::{Command, Stdio};
+
+use assert_cmd::cargo::CommandCargoExt;
+use inferno::collapse::bpftrace::{Folder, Options};
+
+fn test_collapse_bpftrace(
+    test_file: &str,
+    expected_file: &str,
+    options: Options,
+) -> io::Result<()> {
+    for &n in &[1, 2] {
+        let mut options = options.clone();
+        options.nthreads = n;
+        common::test_collapse(Folder::from(options), test_file, expected_file, false)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn collapse_bpftrace_kstack_ustack() {
+    let test_file = "./tests/data/collapse-bpftrace/bpftrace-kstack-ustack.txt";
+    let result_file = "./tests/data/collapse-bpftrace/results/bpftrace-kstack-ustack.txt";
+    test_collapse_bpftrace(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_bpftrace_kstack_ustack_annotate_kernel() {
+    let test_file = "./tests/data/collapse-bpftrace/bpftrace-kstack-ustack.txt";
+    let result_file =
+        "./tests/data/collapse-bpftrace/results/bpftrace-kstack-ustack-annotate-kernel.txt";
+    let mut options = Options::default();
+    options.annotate_kernel = true;
+    test_collapse_bpftrace(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_bpftrace_perf_mode_annotate_kernel() {
+    let test_file = "./tests/data/collapse-bpftrace/bpftrace-perf-mode.txt";
+    let result_file =
+        "./tests/data/collapse-bpftrace/results/bpftrace-perf-mode-annotate-kernel.txt";
+    let mut options = Options::default();
+    options.annotate_kernel = true;
+    test_collapse_bpftrace(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_bpftrace_bcc_profile() {
+    let test_file = "./tests/data/collapse-bpftrace/bcc-profile.txt";
+    let result_file = "./tests/data/collapse-bpftrace/results/bcc-profile.txt";
+    test_collapse_bpftrace(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_bpftrace_bcc_profile_annotate_kernel() {
+    let test_file = "./tests/data/collapse-bpftrace/bcc-profile.txt";
+    let result_file = "./tests/data/collapse-bpftrace/results/bcc-profile-annotate-kernel.txt";
+    let mut options = Options::default();
+    options.annotate_kernel = true;
+    test_collapse_bpftrace(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_bpftrace_cli() {
+    let input_file = "./tests/data/collapse-bpftrace/bpftrace-kstack-ustack.txt";
+    let expected_file = "./tests/data/collapse-bpftrace/results/bpftrace-kstack-ustack.txt";
+
+    // Test with file passed in
+    let output = Command::cargo_bin("inferno-collapse-bpftrace")
+        .unwrap()
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with STDIN
+    let mut child = Command::cargo_bin("inferno-collapse-bpftrace")
+        .unwrap()
+        .arg("--kernel")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    let mut input = BufReader::new(File::open(input_file).unwrap());
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    io::copy(&mut input, stdin).unwrap();
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let expected_file =
+        "./tests/data/collapse-bpftrace/results/bpftrace-kstack-ustack-annotate-kernel.txt";
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::process::{Command, Stdio};
+
+use assert_cmd::prelude::CommandCargoExt;
+use inferno::collapse::callgrind::{Event, Folder, Options};
+use inferno::collapse::Collapse;
+use log::Level;
+
+fn test_collapse_callgrind(
+    test_file: &str,
+    expected_file: &str,
+    options: Options,
+) -> io::Result<()> {
+    common::test_collapse(Folder::from(options), test_file, expected_file, false)
+}
+
+#[test]
+fn collapse_callgrind_default() {
+    let test_file = "./tests/data/collapse-callgrind/callgrind.out.1234";
+    let result_file = "./tests/data/collapse-callgrind/results/callgrind-default.txt";
+    test_collapse_callgrind(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_callgrind_ir() {
+    let test_file = "./tests/data/collapse-callgrind/callgrind.out.1234";
+    let result_file = "./tests/data/collapse-callgrind/results/callgrind-default.txt";
+    let mut options = Options::default();
+    options.event = Event::Named("Ir".to_string());
+    test_collapse_callgrind(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_callgrind_dw() {
+    let test_file = "./tests/data/collapse-callgrind/callgrind.out.1234";
+    let result_file = "./tests/data/collapse-callgrind/results/callgrind-dw.txt";
+    let mut options = Options::default();
+    options.event = Event::Named("Dw".to_string());
+    test_collapse_callgrind(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_callgrind_recursive() {
+    let test_file = "./tests/data/collapse-callgrind/callgrind.out.recursive";
+    let result_file = "./tests/data/collapse-callgrind/results/callgrind-recursive.txt";
+    test_collapse_callgrind(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_callgrind_pruned() {
+    let test_file = "./tests/data/collapse-callgrind/callgrind.out.pruned";
+    let result_file = "./tests/data/collapse-callgrind/results/callgrind-pruned.txt";
+    test_collapse_callgrind(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_callgrind_should_warn_about_unreached_functions() {
+    common::test_collapse_logs(
+        Folder::default(),
+        "./tests/data/collapse-callgrind/callgrind.out.recursive",
+        |captured_logs| {
+            let nwarnings = captured_logs
+                .iter()
+                .filter(|log| {
+                    log.body == "1 function(s) with a cost were not called from any root, and were shown as roots"
+                        && log.level == Level::Warn
+                })
+                .count();
+            assert_eq!(nwarnings, 1);
+        },
+    );
+}
+
+#[test]
+fn collapse_callgrind_should_reject_missing_event() {
+    let input =
+        BufReader::new(File::open("./tests/data/collapse-callgrind/callgrind.out.1234").unwrap());
+    let mut options = Options::default();
+    options.event = Event::Named("cycles".to_string());
+    let error = Folder::from(options)
+        .collapse(input, io::sink())
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn collapse_callgrind_cli() {
+    let input_file = "./tests/data/collapse-callgrind/callgrind.out.1234";
+    let expected_file = "./tests/data/collapse-callgrind/results/callgrind-default.txt";
+
+    // Test with file passed in
+    let output = Command::cargo_bin("inferno-collapse-callgrind")
+        .unwrap()
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with STDIN
+    let mut child = Command::cargo_bin("inferno-collapse-callgrind")
+        .unwrap()
+        .arg("--event")
+        .arg("Dw")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    let mut input = BufReader::new(File::open(input_file).unwrap());
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    io::copy(&mut input, stdin).unwrap();
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let expected_file = "./tests/data/collapse-callgrind/results/callgrind-dw.txt";
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with an event that is not in the profile
+    let output = Command::cargo_bin("inferno-collapse-callgrind")
+        .unwrap()
+        .arg("--event")
+        .arg("cycles")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    assert!(!output.status.success());
+}
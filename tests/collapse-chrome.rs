@@ -0,0 +1,100 @@
+mod common;
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::process::{Command, Stdio};
+
+use assert_cmd::prelude::CommandCargoExt;
+use inferno::collapse::chrome::{Folder, Options, Weight};
+use inferno::collapse::Collapse;
+
+fn test_collapse_chrome(test_file: &str, expected_file: &str, options: Options) -> io::Result<()> {
+    common::test_collapse(Folder::from(options), test_file, expected_file, false)
+}
+
+#[test]
+fn collapse_chrome_cpuprofile_default() {
+    let test_file = "./tests/data/collapse-chrome/node.cpuprofile";
+    let result_file = "./tests/data/collapse-chrome/results/node-default.txt";
+    test_collapse_chrome(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_chrome_cpuprofile_time() {
+    let test_file = "./tests/data/collapse-chrome/node.cpuprofile";
+    let result_file = "./tests/data/collapse-chrome/results/node-time.txt";
+    let mut options = Options::default();
+    options.weight = Weight::Time;
+    test_collapse_chrome(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_chrome_cpuprofile_source() {
+    let test_file = "./tests/data/collapse-chrome/node.cpuprofile";
+    let result_file = "./tests/data/collapse-chrome/results/node-source.txt";
+    let mut options = Options::default();
+    options.include_source = true;
+    test_collapse_chrome(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_chrome_trace_default() {
+    let test_file = "./tests/data/collapse-chrome/trace.json";
+    let result_file = "./tests/data/collapse-chrome/results/trace-default.txt";
+    test_collapse_chrome(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_chrome_trace_array() {
+    let test_file = "./tests/data/collapse-chrome/trace-array.json";
+    let result_file = "./tests/data/collapse-chrome/results/trace-default.txt";
+    test_collapse_chrome(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_chrome_trace_time() {
+    let test_file = "./tests/data/collapse-chrome/trace.json";
+    let result_file = "./tests/data/collapse-chrome/results/trace-time.txt";
+    let mut options = Options::default();
+    options.weight = Weight::Time;
+    test_collapse_chrome(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_chrome_should_reject_invalid_json() {
+    let error = Folder::default()
+        .collapse(&b"{\"nodes\": [{\"id\": 1]}"[..], io::sink())
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn collapse_chrome_cli() {
+    let input_file = "./tests/data/collapse-chrome/node.cpuprofile";
+    let expected_file = "./tests/data/collapse-chrome/results/node-default.txt";
+
+    // Test with file passed in
+    let output = Command::cargo_bin("inferno-collapse-chrome")
+        .unwrap()
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with STDIN
+    let mut child = Command::cargo_bin("inferno-collapse-chrome")
+        .unwrap()
+        .arg("--time")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    let mut input = BufReader::new(File::open(input_file).unwrap());
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    io::copy(&mut input, stdin).unwrap();
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let expected_file = "./tests/data/collapse-chrome/results/node-time.txt";
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::process::{Command, Stdio};
+
+use assert_cmd::prelude::CommandCargoExt;
+use inferno::collapse::gdb::{Folder, Options};
+
+fn test_collapse_gdb(test_file: &str, expected_file: &str, options: Options) -> io::Result<()> {
+    common::test_collapse(Folder::from(options), test_file, expected_file, false)
+}
+
+#[test]
+fn collapse_gdb_default() {
+    let test_file = "./tests/data/collapse-gdb/gdb-thread-apply-all-bt.txt";
+    let result_file = "./tests/data/collapse-gdb/results/gdb-default.txt";
+    test_collapse_gdb(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_gdb_all_options() {
+    let test_file = "./tests/data/collapse-gdb/gdb-thread-apply-all-bt.txt";
+    let result_file = "./tests/data/collapse-gdb/results/gdb-all-options.txt";
+    let mut options = Options::default();
+    options.include_args = true;
+    options.include_source = true;
+    options.include_thread = true;
+    test_collapse_gdb(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_eu_stack_default() {
+    let test_file = "./tests/data/collapse-gdb/eu-stack.txt";
+    let result_file = "./tests/data/collapse-gdb/results/eu-stack-default.txt";
+    test_collapse_gdb(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_eu_stack_source_and_threads() {
+    let test_file = "./tests/data/collapse-gdb/eu-stack.txt";
+    let result_file = "./tests/data/collapse-gdb/results/eu-stack-source-threads.txt";
+    let mut options = Options::default();
+    options.include_source = true;
+    options.include_thread = true;
+    test_collapse_gdb(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_gdb_cli() {
+    let input_file = "./tests/data/collapse-gdb/gdb-thread-apply-all-bt.txt";
+    let expected_file = "./tests/data/collapse-gdb/results/gdb-default.txt";
+
+    // Test with file passed in
+    let output = Command::cargo_bin("inferno-collapse-gdb")
+        .unwrap()
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with STDIN
+    let mut child = Command::cargo_bin("inferno-collapse-gdb")
+        .unwrap()
+        .args(["--args", "--source", "--threads"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    let mut input = BufReader::new(File::open(input_file).unwrap());
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    io::copy(&mut input, stdin).unwrap();
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let expected_file = "./tests/data/collapse-gdb/results/gdb-all-options.txt";
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::process::{Command, Stdio};
+
+use assert_cmd::prelude::CommandCargoExt;
+use inferno::collapse::gecko::{Folder, Options};
+use inferno::collapse::Collapse;
+use regex::Regex;
+
+fn test_collapse_gecko(test_file: &str, expected_file: &str, options: Options) -> io::Result<()> {
+    common::test_collapse(Folder::from(options), test_file, expected_file, false)
+}
+
+#[test]
+fn collapse_gecko_default() {
+    let test_file = "./tests/data/collapse-gecko/samply.json";
+    let result_file = "./tests/data/collapse-gecko/results/samply-default.txt";
+    test_collapse_gecko(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_gecko_include_process_and_thread() {
+    let test_file = "./tests/data/collapse-gecko/samply.json";
+    let result_file = "./tests/data/collapse-gecko/results/samply-include-process-thread.txt";
+    let mut options = Options::default();
+    options.include_process = true;
+    options.include_thread = true;
+    test_collapse_gecko(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_gecko_workers() {
+    let test_file = "./tests/data/collapse-gecko/samply.json";
+    let result_file = "./tests/data/collapse-gecko/results/samply-workers.txt";
+    let mut options = Options::default();
+    options.thread_name = Some(Regex::new("^Worker").unwrap());
+    options.include_thread = true;
+    test_collapse_gecko(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_gecko_per_thread_strings_and_weights() {
+    let test_file = "./tests/data/collapse-gecko/per-thread-strings.json";
+    let result_file = "./tests/data/collapse-gecko/results/per-thread-strings.txt";
+    let mut options = Options::default();
+    options.include_process = true;
+    options.include_thread = true;
+    test_collapse_gecko(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_gecko_should_error_on_unknown_stack() {
+    let input = r#"{"threads": [{
+        "name": "main",
+        "samples": {"stack": [0, 1], "weight": null},
+        "stackTable": {"frame": [0], "prefix": [null]},
+        "frameTable": {"func": [0]},
+        "funcTable": {"name": [0]},
+        "stringArray": ["main"]
+    }]}"#;
+    let error = Folder::default()
+        .collapse(input.as_bytes(), io::sink())
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("unknown stack 1"));
+}
+
+#[test]
+fn collapse_gecko_cli() {
+    let input_file = "./tests/data/collapse-gecko/samply.json";
+    let expected_file = "./tests/data/collapse-gecko/results/samply-default.txt";
+
+    // Test with file passed in
+    let output = Command::cargo_bin("inferno-collapse-gecko")
+        .unwrap()
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with STDIN
+    let mut child = Command::cargo_bin("inferno-collapse-gecko")
+        .unwrap()
+        .args(["--thread", "^Worker", "--include-thread"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    let mut input = BufReader::new(File::open(input_file).unwrap());
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    io::copy(&mut input, stdin).unwrap();
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let expected_file = "./tests/data/collapse-gecko/results/samply-workers.txt";
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}
@@ -92,6 +92,41 @@ fn collapse_guess_vsprof() {
     test_collapse_guess(test_file, result_file, false).unwrap()
 }
 
+#[test]
+fn collapse_guess_speedscope() {
+    let test_file = "./tests/data/collapse-speedscope/example.speedscope.json";
+    let result_file = "./tests/data/collapse-speedscope/results/example-default.txt";
+    test_collapse_guess(test_file, result_file, false).unwrap()
+}
+
+#[test]
+fn collapse_guess_gdb() {
+    let test_file = "./tests/data/collapse-gdb/gdb-thread-apply-all-bt.txt";
+    let result_file = "./tests/data/collapse-gdb/results/gdb-default.txt";
+    test_collapse_guess(test_file, result_file, false).unwrap()
+}
+
+#[test]
+fn collapse_guess_eu_stack() {
+    let test_file = "./tests/data/collapse-gdb/eu-stack.txt";
+    let result_file = "./tests/data/collapse-gdb/results/eu-stack-default.txt";
+    test_collapse_guess(test_file, result_file, false).unwrap()
+}
+
+#[test]
+fn collapse_guess_pprof() {
+    let test_file = "./tests/data/collapse-pprof/cpu.pprof";
+    let result_file = "./tests/data/collapse-pprof/results/cpu-default.txt";
+    test_collapse_guess(test_file, result_file, false).unwrap()
+}
+
+#[test]
+fn collapse_guess_pprof_uncompressed() {
+    let test_file = "./tests/data/collapse-pprof/heap.pb";
+    let result_file = "./tests/data/collapse-pprof/results/heap-default.txt";
+    test_collapse_guess(test_file, result_file, false).unwrap()
+}
+
 #[test]
 fn collapse_guess_unknown_format_should_log_error() {
     test_collapse_guess_logs(
@@ -162,3 +197,24 @@ fn collapse_guess_cli() {
     let expected = BufReader::new(File::open(expected_file).unwrap());
     common::compare_results(Cursor::new(output.stdout), expected, expected_file, true);
 }
+
+#[cfg(feature = "compression")]
+#[test]
+fn collapse_guess_compressed_stdin() {
+    // gzip input must not be mistaken for a compressed pprof profile.
+    let input_file = "./tests/data/collapse-perf/compressed/cpp-stacks-std-function.txt.gz";
+    let expected_file = "./tests/data/collapse-perf/results/cpp-stacks-std-function-collapsed.txt";
+
+    let mut child = Command::cargo_bin("inferno-collapse-guess")
+        .unwrap()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    let mut input = BufReader::new(File::open(input_file).unwrap());
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    io::copy(&mut input, stdin).unwrap();
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, true);
+}
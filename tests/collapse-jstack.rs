@@ -0,0 +1,89 @@
+mod common;
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::process::{Command, Stdio};
+
+use assert_cmd::prelude::CommandCargoExt;
+use inferno::collapse::jstack::{Folder, Options, ThreadState};
+use regex::Regex;
+
+fn test_collapse_jstack(test_file: &str, expected_file: &str, options: Options) -> io::Result<()> {
+    common::test_collapse(Folder::from(options), test_file, expected_file, false)
+}
+
+#[test]
+fn collapse_jstack_default() {
+    let test_file = "./tests/data/collapse-jstack/jstack.txt";
+    let result_file = "./tests/data/collapse-jstack/results/jstack-default.txt";
+    test_collapse_jstack(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_jstack_include_state() {
+    let test_file = "./tests/data/collapse-jstack/jstack.txt";
+    let result_file = "./tests/data/collapse-jstack/results/jstack-include-state.txt";
+    let mut options = Options::default();
+    options.include_state = true;
+    test_collapse_jstack(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_jstack_runnable() {
+    let test_file = "./tests/data/collapse-jstack/jstack.txt";
+    let result_file = "./tests/data/collapse-jstack/results/jstack-runnable.txt";
+    let mut options = Options::default();
+    options.thread_states = vec![ThreadState::Runnable];
+    test_collapse_jstack(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_jstack_workers_waiting() {
+    let test_file = "./tests/data/collapse-jstack/jstack.txt";
+    let result_file = "./tests/data/collapse-jstack/results/jstack-workers-waiting.txt";
+    let mut options = Options::default();
+    options.thread_states = vec![ThreadState::Blocked, ThreadState::TimedWaiting];
+    options.thread_name = Some(Regex::new("^worker-").unwrap());
+    options.include_state = true;
+    test_collapse_jstack(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_jstack_cli() {
+    let input_file = "./tests/data/collapse-jstack/jstack.txt";
+    let expected_file = "./tests/data/collapse-jstack/results/jstack-default.txt";
+
+    // Test with file passed in
+    let output = Command::cargo_bin("inferno-collapse-jstack")
+        .unwrap()
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with STDIN
+    let mut child = Command::cargo_bin("inferno-collapse-jstack")
+        .unwrap()
+        .args(["--state", "blocked", "--state", "timed_waiting"])
+        .args(["--thread", "^worker-", "--include-state"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    let mut input = BufReader::new(File::open(input_file).unwrap());
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    io::copy(&mut input, stdin).unwrap();
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let expected_file = "./tests/data/collapse-jstack/results/jstack-workers-waiting.txt";
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with an invalid thread state
+    let output = Command::cargo_bin("inferno-collapse-jstack")
+        .unwrap()
+        .args(["--state", "sleeping", input_file])
+        .output()
+        .expect("failed to execute process");
+    assert!(!output.status.success());
+}
@@ -59,6 +59,15 @@ fn options_from_vec(opt_vec: Vec<&str>) -> Options {
                 options.annotate_jit = true;
                 options.annotate_kernel = true;
             }
+            "events" => options.all_events = true,
+            "demangle" => options.demangle = Some(Default::default()),
+            "simplified" => {
+                options.demangle = Some(
+                    "strip-hash,strip-generics,simplify-closures"
+                        .parse()
+                        .unwrap(),
+                )
+            }
             opt => panic!("invalid option: {}", opt),
         }
     }
@@ -234,7 +243,12 @@ collapse_perf_tests! {
     collapse_perf_go_stacks,
     collapse_perf_java_inline,
     collapse_perf_versioned_vmlinux__kernel,
-    collapse_perf_sourcepawn_jitdump__jit
+    collapse_perf_sourcepawn_jitdump__jit,
+    collapse_perf_mangled__demangle,
+    collapse_perf_mangled__simplified,
+    collapse_perf_mixed_events,
+    collapse_perf_mixed_events__events,
+    collapse_perf_unnamed_events__events
 }
 
 #[test]
@@ -248,6 +262,24 @@ fn collapse_perf_example_perf_stacks() {
     .unwrap();
 }
 
+#[cfg(feature = "compression")]
+#[test]
+fn collapse_perf_compressed_input() {
+    // The zstd file holds two concatenated frames.
+    for extension in ["xz", "zst"] {
+        test_collapse_perf(
+            &format!(
+                "./tests/data/collapse-perf/compressed/cpp-stacks-std-function.txt.{}",
+                extension
+            ),
+            "./tests/data/collapse-perf/results/cpp-stacks-std-function-collapsed.txt",
+            Default::default(),
+            true,
+        )
+        .unwrap();
+    }
+}
+
 #[test]
 fn collapse_perf_should_warn_about_empty_input_lines() {
     test_collapse_perf_logs(
@@ -318,3 +350,78 @@ fn collapse_perf_cli() {
     let expected = BufReader::new(File::open(expected_file).unwrap());
     common::compare_results(Cursor::new(output.stdout), expected, expected_file, true);
 }
+
+#[test]
+fn collapse_perf_demangle_cli() {
+    let input_file = "./tests/data/collapse-perf/mangled.txt";
+    let expected_file = "./tests/data/collapse-perf/results/mangled-collapsed-simplified.txt";
+
+    let output = Command::cargo_bin("inferno-collapse-perf")
+        .unwrap()
+        .arg("--demangle=strip-hash,strip-generics,simplify-closures")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}
+
+#[test]
+fn collapse_perf_split_events() {
+    let input_file = "./tests/data/collapse-perf/mixed-events.txt";
+    for &n in &[1, 2] {
+        let dir = std::env::temp_dir().join(format!("inferno-split-{}", rand::random::<u64>()));
+        std::fs::create_dir(&dir).unwrap();
+        let template = dir.join("out.{event}.folded");
+
+        let mut options = Options::default();
+        options.nthreads = n;
+        Folder::from(options)
+            .collapse_file_per_event(Some(input_file), template.to_str().unwrap())
+            .unwrap();
+
+        for event in ["cache-misses", "cycles", "instructions"] {
+            let expected_file = format!(
+                "./tests/data/collapse-perf/results/mixed-events-split-{}.txt",
+                event
+            );
+            let result =
+                BufReader::new(File::open(dir.join(format!("out.{}.folded", event))).unwrap());
+            let expected = BufReader::new(File::open(&expected_file).unwrap());
+            common::compare_results(result, expected, &expected_file, false);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[test]
+fn collapse_perf_split_events_cli() {
+    let input_file = "./tests/data/collapse-perf/mixed-events.txt";
+    let dir = std::env::temp_dir().join(format!("inferno-split-{}", rand::random::<u64>()));
+    std::fs::create_dir(&dir).unwrap();
+
+    let output = Command::cargo_bin("inferno-collapse-perf")
+        .unwrap()
+        .arg("--split-events")
+        .arg(dir.join("out.{event}.folded"))
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let expected_file = "./tests/data/collapse-perf/results/mixed-events-split-cycles.txt";
+    let result = BufReader::new(File::open(dir.join("out.cycles.folded")).unwrap());
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(result, expected, expected_file, false);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // A template without {event} would write every event to the same file.
+    let output = Command::cargo_bin("inferno-collapse-perf")
+        .unwrap()
+        .arg("--split-events")
+        .arg("out.folded")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    assert!(!output.status.success());
+}
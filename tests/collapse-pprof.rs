@@ -0,0 +1,118 @@
+mod common;
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::process::{Command, Stdio};
+
+use assert_cmd::prelude::CommandCargoExt;
+use inferno::collapse::pprof::{Folder, Options, SampleIndex};
+use inferno::collapse::Collapse;
+
+fn test_collapse_pprof(test_file: &str, expected_file: &str, options: Options) -> io::Result<()> {
+    common::test_collapse(Folder::from(options), test_file, expected_file, false)
+}
+
+#[test]
+fn collapse_pprof_cpu_default() {
+    let test_file = "./tests/data/collapse-pprof/cpu.pprof";
+    let result_file = "./tests/data/collapse-pprof/results/cpu-default.txt";
+    test_collapse_pprof(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_pprof_cpu_samples() {
+    let test_file = "./tests/data/collapse-pprof/cpu.pprof";
+    let result_file = "./tests/data/collapse-pprof/results/cpu-samples.txt";
+    let mut options = Options::default();
+    options.sample_index = SampleIndex::Name("samples".to_string());
+    test_collapse_pprof(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_pprof_cpu_addrs() {
+    let test_file = "./tests/data/collapse-pprof/cpu.pprof";
+    let result_file = "./tests/data/collapse-pprof/results/cpu-addrs.txt";
+    let mut options = Options::default();
+    options.include_addrs = true;
+    test_collapse_pprof(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_pprof_heap_default() {
+    let test_file = "./tests/data/collapse-pprof/heap.pb";
+    let result_file = "./tests/data/collapse-pprof/results/heap-default.txt";
+    test_collapse_pprof(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_pprof_heap_inuse_space() {
+    let test_file = "./tests/data/collapse-pprof/heap.pb";
+    let result_file = "./tests/data/collapse-pprof/results/heap-inuse-space.txt";
+    let mut options = Options::default();
+    options.sample_index = SampleIndex::Index(3);
+    test_collapse_pprof(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_pprof_should_reject_unknown_sample_type() {
+    let mut folder = Folder::from({
+        let mut options = Options::default();
+        options.sample_index = SampleIndex::Name("wall".to_string());
+        options
+    });
+    let input = BufReader::new(File::open("./tests/data/collapse-pprof/heap.pb").unwrap());
+    let error = folder.collapse(input, io::sink()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn collapse_pprof_should_reject_truncated_profile() {
+    let mut data = std::fs::read("./tests/data/collapse-pprof/heap.pb").unwrap();
+    data.truncate(data.len() / 2);
+    let error = Folder::default()
+        .collapse(&data[..], io::sink())
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn collapse_pprof_cli() {
+    let input_file = "./tests/data/collapse-pprof/cpu.pprof";
+    let expected_file = "./tests/data/collapse-pprof/results/cpu-default.txt";
+
+    // Test with file passed in
+    let output = Command::cargo_bin("inferno-collapse-pprof")
+        .unwrap()
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with STDIN
+    let mut child = Command::cargo_bin("inferno-collapse-pprof")
+        .unwrap()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    let mut input = BufReader::new(File::open(input_file).unwrap());
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    io::copy(&mut input, stdin).unwrap();
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test --sample-index by name
+    let input_file = "./tests/data/collapse-pprof/heap.pb";
+    let expected_file = "./tests/data/collapse-pprof/results/heap-inuse-space.txt";
+    let output = Command::cargo_bin("inferno-collapse-pprof")
+        .unwrap()
+        .arg("--sample-index")
+        .arg("inuse_space")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}
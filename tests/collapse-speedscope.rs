@@ -0,0 +1,73 @@
+mod common;
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::process::{Command, Stdio};
+
+use assert_cmd::prelude::CommandCargoExt;
+use inferno::collapse::speedscope::{Folder, Options};
+use inferno::collapse::Collapse;
+
+fn test_collapse_speedscope(
+    test_file: &str,
+    expected_file: &str,
+    options: Options,
+) -> io::Result<()> {
+    common::test_collapse(Folder::from(options), test_file, expected_file, false)
+}
+
+#[test]
+fn collapse_speedscope_default() {
+    let test_file = "./tests/data/collapse-speedscope/example.speedscope.json";
+    let result_file = "./tests/data/collapse-speedscope/results/example-default.txt";
+    test_collapse_speedscope(test_file, result_file, Options::default()).unwrap()
+}
+
+#[test]
+fn collapse_speedscope_profile_name() {
+    let test_file = "./tests/data/collapse-speedscope/example.speedscope.json";
+    let result_file = "./tests/data/collapse-speedscope/results/example-profile-name.txt";
+    let mut options = Options::default();
+    options.include_profile_name = true;
+    test_collapse_speedscope(test_file, result_file, options).unwrap()
+}
+
+#[test]
+fn collapse_speedscope_should_reject_mismatched_close() {
+    let input = BufReader::new(
+        File::open("./tests/data/collapse-speedscope/mismatched-close.speedscope.json").unwrap(),
+    );
+    let error = Folder::default().collapse(input, io::sink()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn collapse_speedscope_cli() {
+    let input_file = "./tests/data/collapse-speedscope/example.speedscope.json";
+    let expected_file = "./tests/data/collapse-speedscope/results/example-default.txt";
+
+    // Test with file passed in
+    let output = Command::cargo_bin("inferno-collapse-speedscope")
+        .unwrap()
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+
+    // Test with STDIN
+    let mut child = Command::cargo_bin("inferno-collapse-speedscope")
+        .unwrap()
+        .arg("--profile-name")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    let mut input = BufReader::new(File::open(input_file).unwrap());
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    io::copy(&mut input, stdin).unwrap();
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let expected_file = "./tests/data/collapse-speedscope/results/example-profile-name.txt";
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    common::compare_results(Cursor::new(output.stdout), expected, expected_file, false);
+}
@@ -0,0 +1,127 @@
+//! A collapser for a made-up format, implemented outside of the crate with the public
+//! `StackParser` API. Its stacks list one frame per line, root first, followed by `= <count>`.
+mod common;
+
+use std::io;
+
+use inferno::collapse::{
+    guess, Collapse, Occurrences, StackParser, DEFAULT_NSTACKS_PER_JOB, DEFAULT_NTHREADS,
+};
+
+#[derive(Clone)]
+struct Folder {
+    stack: Vec<String>,
+    nstacks_per_job: usize,
+    nthreads: usize,
+}
+
+impl Default for Folder {
+    fn default() -> Self {
+        Folder {
+            stack: Vec::new(),
+            nstacks_per_job: DEFAULT_NSTACKS_PER_JOB,
+            nthreads: *DEFAULT_NTHREADS,
+        }
+    }
+}
+
+impl StackParser for Folder {
+    fn pre_process<R>(&mut self, reader: &mut R, _: &mut Occurrences) -> io::Result<()>
+    where
+        R: io::BufRead,
+    {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        Ok(())
+    }
+
+    fn collapse_single_threaded<R>(
+        &mut self,
+        reader: R,
+        occurrences: &mut Occurrences,
+    ) -> io::Result<()>
+    where
+        R: io::BufRead,
+    {
+        for line in reader.lines() {
+            let line = line?;
+            if let Some(count) = line.strip_prefix("= ") {
+                let count = count
+                    .parse()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                occurrences.insert_or_add(self.stack.join(";"), count);
+                self.stack.clear();
+            } else if !line.is_empty() {
+                self.stack.push(line);
+            }
+        }
+        Ok(())
+    }
+
+    fn would_end_stack(&mut self, line: &[u8]) -> bool {
+        line.starts_with(b"= ")
+    }
+
+    fn clone_and_reset_stack_context(&self) -> Self {
+        Folder {
+            stack: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    fn is_applicable(&mut self, input: &str) -> Option<bool> {
+        Some(input.starts_with("# stackcount"))
+    }
+
+    fn nstacks_per_job(&self) -> usize {
+        self.nstacks_per_job
+    }
+
+    fn set_nstacks_per_job(&mut self, n: usize) {
+        self.nstacks_per_job = n;
+    }
+
+    fn nthreads(&self) -> usize {
+        self.nthreads
+    }
+
+    fn set_nthreads(&mut self, n: usize) {
+        self.nthreads = n;
+    }
+}
+
+#[test]
+fn collapse_stack_parser() {
+    let test_file = "./tests/data/collapse-stack-parser/stackcount.txt";
+    let result_file = "./tests/data/collapse-stack-parser/results/stackcount.txt";
+    for n in [1, 2, 8] {
+        let mut folder = Folder::default();
+        folder.set_nthreads(n);
+        folder.set_nstacks_per_job(7);
+        common::test_collapse(folder, test_file, result_file, false).unwrap();
+    }
+}
+
+#[test]
+fn collapse_stack_parser_guess() {
+    let test_file = "./tests/data/collapse-stack-parser/stackcount.txt";
+    let result_file = "./tests/data/collapse-stack-parser/results/stackcount.txt";
+    let mut folder = guess::Folder::default();
+    folder.register("stackcount", Folder::default());
+    common::test_collapse(folder.clone(), test_file, result_file, false).unwrap();
+
+    // Built-in formats are still detected.
+    let test_file = "./tests/data/collapse-dtrace/java.txt";
+    let result_file = "./tests/data/collapse-dtrace/results/java.txt";
+    common::test_collapse(folder, test_file, result_file, false).unwrap();
+}
+
+#[test]
+fn collapse_stack_parser_not_registered() {
+    let input = "# stackcount v1\nmain\n= 1\n";
+    let mut output = Vec::new();
+    guess::Folder::default()
+        .collapse(input.as_bytes(), &mut output)
+        .unwrap();
+    assert!(output.is_empty());
+}
@@ -0,0 +1,12 @@
+
+
+
+
+
+
+ 
+
+@  	(	(""""""""(""""ё""X""x"
+	 "
+"	*		 
+* * * * * * * *	 2 2alloc_objects2count2alloc_space2bytes2inuse_objects2inuse_space2/app/server2#/usr/lib/x86_64-linux-gnu/libc.so.62runtime.goexit2#/usr/lib/go/src/runtime/asm_amd64.s2runtime.main2/usr/lib/go/src/runtime/proc.go2	main.main2/app/main.go2	main.work2/app/work.go2main.helper2runtime.mallocgc2!/usr/lib/go/src/runtime/malloc.go2main.(*Server).handle2/app/server.go2strings.Index2"/usr/lib/go/src/strings/strings.go2Qstd::vector<int, std::allocator<int>>::push_back(int const&) [clone .cold]; weird2vector.hp
\ No newline at end of file
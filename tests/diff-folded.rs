@@ -68,10 +68,8 @@ where
     result_lines.sort_unstable();
     expected_lines.sort_unstable();
 
-    for (line_num, (result_line, expected_line)) in result_lines
-        .into_iter()
-        .zip(expected_lines.into_iter())
-        .enumerate()
+    for (line_num, (result_line, expected_line)) in
+        result_lines.into_iter().zip(expected_lines).enumerate()
     {
         assert_eq!(
             result_line, expected_line,
@@ -170,24 +168,16 @@ fn diff_folded_should_log_warning_on_bad_input_line() {
 }
 
 #[test]
-fn diff_folded_should_log_warning_about_fractional_samples() {
-    test_diff_folded_logs(
-        "./tests/data/diff-folded/before_fractionals.txt",
-        "./tests/data/diff-folded/after.txt",
-        |captured_logs| {
-            let nwarnings = captured_logs
-                .iter()
-                .filter(|log| {
-                    log.body == "The input data has fractional sample counts that will be truncated to integers" && log.level == Level::Warn
-                })
-                .count();
-            assert_eq!(
-                nwarnings, 1,
-                "fractional samples warning logged {} times, but should be logged exactly once",
-                nwarnings
-            );
-        },
-    );
+fn diff_folded_normalize_fractional_samples() {
+    let infile1 = "./tests/data/diff-folded/before_fractionals.txt";
+    let infile2 = "./tests/data/diff-folded/after.txt";
+    let expected_result_file = "./tests/data/diff-folded/results/fractionals_normalize.txt";
+
+    let opt = Options {
+        normalize: true,
+        ..Default::default()
+    };
+    test_diff_folded(infile1, infile2, expected_result_file, opt).unwrap();
 }
 
 #[test]
@@ -0,0 +1,105 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use assert_cmd::cargo::CommandCargoExt;
+use inferno::filter::{self, Options};
+use pretty_assertions::assert_eq;
+use regex::Regex;
+
+fn test_filter(input_file: &str, expected_file: &str, options: Options) -> io::Result<()> {
+    let input = vec![PathBuf::from(input_file)];
+    if fs::metadata(expected_file).is_err() || std::env::var("INFERNO_BLESS_TESTS").is_ok() {
+        // be nice to the dev and make the file
+        let f = File::create(expected_file).unwrap();
+        filter::from_files(&options, &input, f)?;
+    }
+
+    let mut result = Vec::new();
+    filter::from_files(&options, &input, &mut result)?;
+    let expected = fs::read_to_string(expected_file).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&result),
+        expected,
+        "\n{}",
+        expected_file
+    );
+    Ok(())
+}
+
+fn regex(re: &str) -> Regex {
+    Regex::new(re).unwrap()
+}
+
+#[test]
+fn filter_keep_and_drop_stacks() {
+    let input_file = "./tests/data/filter/stacks.txt";
+    let expected_file = "./tests/data/filter/results/keep-drop.txt";
+    let mut options = Options::default();
+    options.keep = vec![regex("^app;"), regex("^swapper;")];
+    options.drop = vec![regex(";gc;"), regex("cpu_idle$")];
+    test_filter(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn filter_drop_frames() {
+    let input_file = "./tests/data/filter/stacks.txt";
+    let expected_file = "./tests/data/filter/results/drop-frames.txt";
+    let mut options = Options::default();
+    options.drop_frames = vec![regex(r"^\[unknown\]$")];
+    test_filter(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn filter_root_at() {
+    let input_file = "./tests/data/filter/stacks.txt";
+    let expected_file = "./tests/data/filter/results/root-at.txt";
+    let mut options = Options::default();
+    options.root_at = Some(regex("::poll$"));
+    test_filter(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn filter_leaf_at_and_max_depth() {
+    let input_file = "./tests/data/filter/stacks.txt";
+    let expected_file = "./tests/data/filter/results/leaf-at-max-depth.txt";
+    let mut options = Options::default();
+    options.leaf_at = Some(regex("^handler$"));
+    options.max_depth = Some(4);
+    test_filter(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn filter_differential() {
+    let input_file = "./tests/data/filter/diff.txt";
+    let expected_file = "./tests/data/filter/results/diff.txt";
+    let mut options = Options::default();
+    options.drop = vec![regex("^swapper;")];
+    options.drop_frames = vec![regex(r"^\[unknown\]$")];
+    options.max_depth = Some(4);
+    test_filter(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn filter_cli() {
+    let input_file = "./tests/data/filter/stacks.txt";
+    let expected_file = "./tests/data/filter/results/cli.txt";
+
+    let output = Command::cargo_bin("inferno-filter")
+        .unwrap()
+        .arg("--drop")
+        .arg("^swapper;")
+        .arg("--drop-frames")
+        .arg(r"^\[unknown\]$")
+        .arg("--root-at")
+        .arg("^run$")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    if fs::metadata(expected_file).is_err() {
+        fs::write(expected_file, &output.stdout).unwrap();
+    }
+    let expected = fs::read_to_string(expected_file).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
@@ -8,7 +8,10 @@ use std::str::FromStr;
 
 use assert_cmd::cargo::CommandCargoExt;
 use inferno::flamegraph::color::{BackgroundColor, PaletteMap};
-use inferno::flamegraph::{self, Direction, Options, Palette, TextTruncateDirection};
+use inferno::flamegraph::{
+    self, CountFormat, Direction, Options, OutputFormat, Palette, Ratio, RerootMatch, TermColors,
+    TextTruncateDirection,
+};
 use log::Level;
 use pretty_assertions::assert_eq;
 use testing_logger::CapturedLog;
@@ -312,69 +315,137 @@ fn flamegraph_nameattr_should_warn_about_invalid_attribute() {
 }
 
 #[test]
-fn flamegraph_should_warn_about_fractional_samples() {
-    test_flamegraph_logs(
-        "./tests/data/flamegraph/fractional-samples/fractional.txt",
-        |captured_logs| {
-            let nwarnings = captured_logs
-                .iter()
-                .filter(|log| {
-                    log.body
-                        .starts_with("The input data has fractional sample counts")
-                        && log.level == Level::Warn
-                })
-                .count();
-            assert_eq!(
-                nwarnings, 1,
-                "fractional samples warning logged {} times, but should be logged exactly once",
-                nwarnings
-            );
-        },
-    );
+fn flamegraph_fractional_samples() {
+    let input_file = "./tests/data/flamegraph/fractional-samples/fractional.txt";
+    let expected_result_file = "./tests/data/flamegraph/fractional-samples/fractional.svg";
+
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
 }
 
 #[test]
-fn flamegraph_should_not_warn_about_zero_fractional_samples() {
-    test_flamegraph_logs(
-        "./tests/data/flamegraph/fractional-samples/zero-fractionals.txt",
-        |captured_logs| {
-            let nwarnings = captured_logs
-                .iter()
-                .filter(|log| {
-                    log.body
-                        .starts_with("The input data has fractional sample counts")
-                        && log.level == Level::Warn
-                })
-                .count();
-            assert_eq!(
-                nwarnings, 0,
-                "warning about fractional samples not expected"
-            );
-        },
+fn flamegraph_fractional_samples_as_integers() {
+    let input_file = "./tests/data/flamegraph/fractional-samples/fractional.txt";
+    let expected_result_file = "./tests/data/flamegraph/fractional-samples/fractional-integer.svg";
+
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    options.count_format = CountFormat::Integer;
+
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_fractional_samples_as_decimals() {
+    let input_file = "./tests/data/flamegraph/fractional-samples/no-fractionals.txt";
+    let expected_result_file =
+        "./tests/data/flamegraph/fractional-samples/no-fractionals-decimal.svg";
+
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    options.count_format = CountFormat::Decimal(2);
+
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_zero_fractional_samples() {
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    let mut zero_fractionals = Vec::new();
+    flamegraph::from_files(
+        &mut options,
+        &[PathBuf::from(
+            "./tests/data/flamegraph/fractional-samples/zero-fractionals.txt",
+        )],
+        &mut zero_fractionals,
+    )
+    .unwrap();
+
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    let mut no_fractionals = Vec::new();
+    flamegraph::from_files(
+        &mut options,
+        &[PathBuf::from(
+            "./tests/data/flamegraph/fractional-samples/no-fractionals.txt",
+        )],
+        &mut no_fractionals,
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(zero_fractionals).unwrap(),
+        String::from_utf8(no_fractionals).unwrap()
     );
 }
 
 #[test]
-fn flamegraph_should_not_warn_about_fractional_sample_with_tricky_stack() {
+fn flamegraph_should_not_warn_about_tricky_stack() {
     test_flamegraph_logs(
         "./tests/data/flamegraph/fractional-samples/tricky-stack.txt",
         |captured_logs| {
             let nwarnings = captured_logs
                 .iter()
-                .filter(|log| {
-                    log.body
-                        .starts_with("The input data has fractional sample counts")
-                        && log.level == Level::Warn
-                })
+                .filter(|log| log.level == Level::Warn)
                 .count();
-            assert_eq!(
-                nwarnings, 0,
-                "warning about fractional samples not expected"
-            );
+            assert_eq!(nwarnings, 0, "no warnings expected");
         },
     );
 }
 
+#[test]
+fn flamegraph_u64_samples() {
+    let input_file = "./tests/data/flamegraph/fractional-samples/u64.txt";
+    let expected_result_file = "./tests/data/flamegraph/fractional-samples/u64.svg";
+
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    options.count_name = "bytes".to_string();
+
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_multi_metric() {
+    let input_file = "./tests/data/flamegraph/multi-metric/perf-events.txt";
+    let expected_result_file = "./tests/data/flamegraph/multi-metric/cycles.svg";
+
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    options.metric = Some("cycles".to_string());
+    options.ratios = vec![Ratio::from_str("IPC=instructions/cycles").unwrap()];
+
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_multi_metric_reversed() {
+    let input_file = "./tests/data/flamegraph/multi-metric/perf-events.txt";
+    let expected_result_file = "./tests/data/flamegraph/multi-metric/instructions-reversed.svg";
+
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    options.reverse_stack_order = true;
+    options.metric = Some("instructions".to_string());
+    options.ratios = vec![Ratio::from_str("CPI=cycles/instructions").unwrap()];
+
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_multi_metric_should_return_error_on_unknown_metric() {
+    let input_file = "./tests/data/flamegraph/multi-metric/perf-events.txt";
+    let expected_result_file = "./tests/data/flamegraph/multi-metric/cycles.svg";
+
+    let mut options = flamegraph::Options::default();
+    options.metric = Some("branches".to_string());
+
+    assert!(test_flamegraph(input_file, expected_result_file, options).is_err());
+}
+
 fn load_palette_map_file(palette_file: &str) -> PaletteMap {
     let path = Path::new(palette_file);
     PaletteMap::load_from_file_or_empty(&path).unwrap()
@@ -429,7 +500,7 @@ fn flamegraph_should_warn_about_bad_input_lines() {
                 .count();
             assert_eq!(
                 nwarnings, 1,
-                "bad lines warning logged {} times, but should be logged exactly once",
+                "missing function warning logged {} times, but should be logged exactly once",
                 nwarnings
             );
         },
@@ -473,6 +544,22 @@ fn flamegraph_unsorted_multiple_input_files() {
     test_flamegraph_multiple_files(input_files, expected_result_file, options).unwrap();
 }
 
+#[cfg(feature = "compression")]
+#[test]
+fn flamegraph_compressed_input_files() {
+    let input_files = vec![
+        "./tests/data/flamegraph/compressed/perf-vertx-stacks-01-collapsed-all-unsorted-1.txt.gz"
+            .into(),
+        "./tests/data/flamegraph/compressed/perf-vertx-stacks-01-collapsed-all-unsorted-2.txt.zst"
+            .into(),
+    ];
+    let expected_result_file =
+        "./tests/data/flamegraph/perf-vertx-stacks/perf-vertx-stacks-01-collapsed-all.svg";
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    test_flamegraph_multiple_files(input_files, expected_result_file, options).unwrap();
+}
+
 #[test]
 fn flamegraph_should_prune_narrow_blocks() {
     let input_file = "./tests/data/flamegraph/narrow-blocks/narrow-blocks.txt";
@@ -847,7 +934,7 @@ fn flamegraph_should_warn_about_bad_input_lines_with_reversed_stack_ordering() {
                 .count();
             assert_eq!(
                 nwarnings, 1,
-                "bad lines warning logged {} times, but should be logged exactly once",
+                "missing function warning logged {} times, but should be logged exactly once",
                 nwarnings
             );
         },
@@ -965,3 +1052,332 @@ fn flamegraph_austin() {
     let opts = flamegraph::Options::default();
     test_flamegraph(input_file, expected_result_file, opts).unwrap();
 }
+
+#[test]
+fn flamegraph_json() {
+    let input_file = "./tests/data/flamegraph/grey-frames/grey-frames.txt";
+    let expected_result_file = "./tests/data/flamegraph/json/grey-frames.json";
+    let mut opts = flamegraph::Options::default();
+    opts.format = OutputFormat::Json;
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_json_differential() {
+    let input_file =
+        "./tests/data/flamegraph/differential/perf-cycles-instructions-01-collapsed-all-diff.txt";
+    let expected_result_file = "./tests/data/flamegraph/json/diff.json";
+    let mut opts = flamegraph::Options::default();
+    opts.format = OutputFormat::Json;
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_json_should_prune_narrow_blocks() {
+    let input_file = "./tests/data/flamegraph/narrow-blocks/narrow-blocks.txt";
+    let expected_result_file = "./tests/data/flamegraph/json/narrow-blocks.json";
+    let mut opts = flamegraph::Options::default();
+    opts.format = OutputFormat::Json;
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_json_cli() {
+    let input_file = "./tests/data/flamegraph/grey-frames/grey-frames.txt";
+    let expected_file = "./tests/data/flamegraph/json/grey-frames.json";
+    let output = Command::cargo_bin("inferno-flamegraph")
+        .unwrap()
+        .arg("--format")
+        .arg("json")
+        .arg("--pretty-xml")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    compare_results(Cursor::new(output.stdout), expected, expected_file);
+}
+
+#[test]
+fn flamegraph_html() {
+    let input_file = "./tests/data/flamegraph/grey-frames/grey-frames.txt";
+    let expected_result_file = "./tests/data/flamegraph/html/grey-frames.html";
+    let mut opts = flamegraph::Options::default();
+    opts.format = OutputFormat::Html;
+    opts.hash = true;
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_html_empty_input() {
+    let input_file = "./tests/data/flamegraph/empty/empty.txt";
+    let expected_result_file = "./tests/data/flamegraph/html/empty.html";
+    let mut opts = flamegraph::Options::default();
+    opts.format = OutputFormat::Html;
+    assert!(test_flamegraph(input_file, expected_result_file, opts).is_err());
+}
+
+#[test]
+fn flamegraph_term() {
+    let input_file = "./tests/data/flamegraph/grey-frames/grey-frames.txt";
+    let expected_result_file = "./tests/data/flamegraph/term/grey-frames.txt";
+    let mut opts = flamegraph::Options::default();
+    opts.format = OutputFormat::Term;
+    opts.hash = true;
+    opts.image_width = Some(100);
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_term_differential_inverted() {
+    let input_file =
+        "./tests/data/flamegraph/differential/perf-cycles-instructions-01-collapsed-all-diff.txt";
+    let expected_result_file = "./tests/data/flamegraph/term/diff-inverted-256.txt";
+    let mut opts = flamegraph::Options::default();
+    opts.format = OutputFormat::Term;
+    opts.term_colors = TermColors::Ansi256;
+    opts.direction = Direction::Inverted;
+    opts.image_width = Some(80);
+    test_flamegraph(input_file, expected_result_file, opts).unwrap();
+}
+
+#[test]
+fn flamegraph_term_cli() {
+    let input_file = "./tests/data/flamegraph/grey-frames/grey-frames.txt";
+    let expected_file = "./tests/data/flamegraph/term/grey-frames-256.txt";
+
+    let output = Command::cargo_bin("inferno-flamegraph")
+        .unwrap()
+        .arg("--format")
+        .arg("term")
+        .arg("--term-colors")
+        .arg("256")
+        .arg("--hash")
+        .arg("--width")
+        .arg("100")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    if fs::metadata(expected_file).is_err() {
+        fs::write(expected_file, &output.stdout).unwrap();
+    }
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    compare_results(Cursor::new(output.stdout), expected, expected_file);
+}
+
+#[test]
+fn flamegraph_reroot_first_match() {
+    let input_file = "./tests/data/flamegraph/reroot/tokio.txt";
+    let expected_result_file = "./tests/data/flamegraph/reroot/first.svg";
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    options.reroot = Some("^tokio::runtime::task::.*::poll$".to_string());
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_reroot_last_match() {
+    let input_file = "./tests/data/flamegraph/reroot/tokio.txt";
+    let expected_result_file = "./tests/data/flamegraph/reroot/last.svg";
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    options.reroot = Some("^tokio::runtime::task::.*::poll$".to_string());
+    options.reroot_match = RerootMatch::Last;
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_reroot_should_warn_about_no_sort() {
+    let mut options = flamegraph::Options::default();
+    options.no_sort = true;
+    options.reroot = Some("poll".to_string());
+    test_flamegraph_logs_with_options(
+        "./tests/data/flamegraph/reroot/tokio.txt",
+        |captured_logs| {
+            let nwarnings = captured_logs
+                .iter()
+                .filter(|log| {
+                    log.body
+                        .starts_with("Input lines are always sorted when `reroot`")
+                })
+                .count();
+            assert_eq!(
+                nwarnings, 1,
+                "no sort warning logged {} times, but should be logged exactly once",
+                nwarnings
+            );
+        },
+        options,
+    );
+}
+
+#[test]
+fn flamegraph_reroot_should_reject_invalid_regex() {
+    let input_file = "./tests/data/flamegraph/reroot/tokio.txt";
+    let mut options = flamegraph::Options::default();
+    options.reroot = Some("poll(".to_string());
+    let result = flamegraph::from_files(&mut options, &[PathBuf::from(input_file)], io::sink());
+    assert!(result.is_err());
+}
+
+#[test]
+fn flamegraph_reroot_cli() {
+    let input_file = "./tests/data/flamegraph/reroot/tokio.txt";
+    let expected_file = "./tests/data/flamegraph/reroot/last-cli.svg";
+
+    let output = Command::cargo_bin("inferno-flamegraph")
+        .unwrap()
+        .arg("--reroot")
+        .arg("^tokio::runtime::task::.*::poll$")
+        .arg("--reroot-match")
+        .arg("last")
+        .arg("--hash")
+        .arg("--pretty-xml")
+        .arg("--no-javascript")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    if fs::metadata(expected_file).is_err() {
+        fs::write(expected_file, &output.stdout).unwrap();
+    }
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    compare_results(Cursor::new(output.stdout), expected, expected_file);
+}
+
+#[test]
+fn flamegraph_butterfly() {
+    let input_file = "./tests/data/flamegraph/butterfly/memcpy.txt";
+    let expected_result_file = "./tests/data/flamegraph/butterfly/memcpy.svg";
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    options.butterfly = Some("memcpy".to_string());
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_butterfly_inverted() {
+    let input_file = "./tests/data/flamegraph/butterfly/memcpy.txt";
+    let expected_result_file = "./tests/data/flamegraph/butterfly/memcpy-inverted.svg";
+    let mut options = flamegraph::Options::default();
+    options.hash = true;
+    options.direction = Direction::Inverted;
+    options.butterfly = Some("memcpy".to_string());
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_butterfly_differential() {
+    let input_file = "./tests/data/flamegraph/butterfly/memcpy-diff.txt";
+    let expected_result_file = "./tests/data/flamegraph/butterfly/memcpy-diff.svg";
+    let mut options = flamegraph::Options::default();
+    options.butterfly = Some("memcpy".to_string());
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_butterfly_term() {
+    let input_file = "./tests/data/flamegraph/butterfly/memcpy.txt";
+    let expected_result_file = "./tests/data/flamegraph/butterfly/memcpy-term.txt";
+    let mut options = flamegraph::Options::default();
+    options.format = OutputFormat::Term;
+    options.hash = true;
+    options.image_width = Some(100);
+    options.butterfly = Some("memcpy".to_string());
+    test_flamegraph(input_file, expected_result_file, options).unwrap();
+}
+
+#[test]
+fn flamegraph_butterfly_should_warn_about_missing_function() {
+    let mut options = flamegraph::Options::default();
+    options.butterfly = Some("strlen".to_string());
+    test_flamegraph_logs_with_options(
+        "./tests/data/flamegraph/butterfly/memcpy.txt",
+        |captured_logs| {
+            let nwarnings = captured_logs
+                .iter()
+                .filter(|log| {
+                    log.body == "No stacks contain the function strlen" && log.level == Level::Warn
+                })
+                .count();
+            assert_eq!(
+                nwarnings, 1,
+                "missing function warning logged {} times, but should be logged exactly once",
+                nwarnings
+            );
+        },
+        options,
+    );
+}
+
+#[test]
+fn flamegraph_butterfly_should_not_write_json() {
+    let input_file = "./tests/data/flamegraph/butterfly/memcpy.txt";
+    let mut options = flamegraph::Options::default();
+    options.format = OutputFormat::Json;
+    options.butterfly = Some("memcpy".to_string());
+    let result = flamegraph::from_files(&mut options, &[PathBuf::from(input_file)], io::sink());
+    assert!(result.is_err());
+}
+
+#[test]
+fn flamegraph_butterfly_cli() {
+    let input_file = "./tests/data/flamegraph/butterfly/memcpy.txt";
+    let expected_file = "./tests/data/flamegraph/butterfly/memcpy-cli.svg";
+
+    let output = Command::cargo_bin("inferno-flamegraph")
+        .unwrap()
+        .arg("--butterfly")
+        .arg("memcpy")
+        .arg("--hash")
+        .arg("--pretty-xml")
+        .arg("--no-javascript")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    if fs::metadata(expected_file).is_err() {
+        fs::write(expected_file, &output.stdout).unwrap();
+    }
+    let expected = BufReader::new(File::open(expected_file).unwrap());
+    compare_results(Cursor::new(output.stdout), expected, expected_file);
+}
+
+#[cfg(feature = "png")]
+#[test]
+fn flamegraph_png_should_match_svg() {
+    let input_file = "./tests/data/flamegraph/grey-frames/grey-frames.txt";
+    let render = |format| {
+        let mut opts = flamegraph::Options::default();
+        opts.format = format;
+        opts.hash = true;
+        opts.image_width = Some(600);
+        let mut output = Vec::new();
+        flamegraph::from_files(&mut opts, &[PathBuf::from(input_file)], &mut output).unwrap();
+        output
+    };
+    let svg = String::from_utf8(render(OutputFormat::Svg)).unwrap();
+    let png = tiny_skia::Pixmap::decode_png(&render(OutputFormat::Png)).unwrap();
+
+    let size = regex::Regex::new(r#"<svg version="1.1" width="(\d+)" height="(\d+)""#)
+        .unwrap()
+        .captures(&svg)
+        .unwrap();
+    assert_eq!(png.width().to_string(), &size[1]);
+    assert_eq!(png.height().to_string(), &size[2]);
+
+    // The root frame spans the whole width, so its middle is in the middle of the image.
+    let root = regex::Regex::new(
+        r#"<title>all [^<]*</title><rect x="[^"]*" y="(\d+)" width="[^"]*" height="(\d+)" fill="rgb\((\d+),(\d+),(\d+)\)""#,
+    )
+    .unwrap()
+    .captures(&svg)
+    .unwrap();
+    let y = root[1].parse::<u32>().unwrap() + root[2].parse::<u32>().unwrap() / 2;
+    let pixel = png.pixel(png.width() / 2, y).unwrap();
+    assert_eq!(
+        (pixel.red(), pixel.green(), pixel.blue()),
+        (
+            root[3].parse().unwrap(),
+            root[4].parse().unwrap(),
+            root[5].parse().unwrap()
+        )
+    );
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::io::{self, Read};
+use std::process::Command;
+
+use assert_cmd::cargo::CommandCargoExt;
+use inferno::collapse::pprof::{Folder, Options as CollapseOptions, SampleIndex};
+use inferno::collapse::Collapse;
+use inferno::export::pprof::{self, Options};
+use inferno::folded::{Count, FoldedStacks};
+use pretty_assertions::assert_eq;
+
+/// Collapse an exported profile again, which should give back the (merged) input stacks.
+fn collapse(profile: &[u8], sample_index: SampleIndex) -> io::Result<String> {
+    let mut options = CollapseOptions::default();
+    options.sample_index = sample_index;
+    let mut collapsed = Vec::new();
+    Folder::from(options).collapse(profile, &mut collapsed)?;
+    Ok(String::from_utf8(collapsed).unwrap())
+}
+
+fn expected_stacks(input: &str, before: bool) -> String {
+    FoldedStacks::from_str_lossy(input)
+        .merge()
+        .into_iter()
+        .map(|mut s| {
+            if before {
+                s.count = s.before.unwrap_or_default();
+            }
+            s.before = None;
+            s
+        })
+        .filter(|s| s.count > Count::ZERO)
+        .collect::<FoldedStacks>()
+        .to_string()
+}
+
+#[test]
+fn to_pprof_should_round_trip() {
+    let input = fs::read_to_string("./tests/data/to-pprof/stacks.txt").unwrap();
+    let mut profile = Vec::new();
+    pprof::from_reader(&Options::default(), input.as_bytes(), &mut profile).unwrap();
+
+    assert!(profile.starts_with(&[0x1f, 0x8b]), "profile is not gzipped");
+    assert_eq!(
+        collapse(&profile, SampleIndex::Default).unwrap(),
+        expected_stacks(&input, false)
+    );
+}
+
+#[test]
+fn to_pprof_sample_type() {
+    let input = fs::read_to_string("./tests/data/to-pprof/stacks.txt").unwrap();
+    let mut options = Options::default();
+    options.sample_type = "cpu".to_string();
+    options.count_name = "nanoseconds".to_string();
+    let mut profile = Vec::new();
+    pprof::from_reader(&options, input.as_bytes(), &mut profile).unwrap();
+
+    let mut decompressed = Vec::new();
+    libflate::gzip::Decoder::new(&profile[..])
+        .unwrap()
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    // The first field holds the sample type and unit, as indices into the string table,
+    // which starts with the empty string.
+    assert_eq!(&decompressed[..6], &[0x0a, 0x04, 0x08, 0x01, 0x10, 0x02]);
+    let cpu = decompressed
+        .windows(5)
+        .position(|w| w == b"\x32\x03cpu")
+        .unwrap();
+    let ns = decompressed
+        .windows(13)
+        .position(|w| w == b"\x32\x0bnanoseconds")
+        .unwrap();
+    assert!(cpu < ns);
+    assert_eq!(
+        collapse(&profile, SampleIndex::Name("cpu".to_string())).unwrap(),
+        expected_stacks(&input, false)
+    );
+}
+
+#[test]
+fn to_pprof_differential() {
+    let input = fs::read_to_string("./tests/data/to-pprof/diff.txt").unwrap();
+    let mut profile = Vec::new();
+    pprof::from_reader(&Options::default(), input.as_bytes(), &mut profile).unwrap();
+
+    assert_eq!(
+        collapse(&profile, SampleIndex::Default).unwrap(),
+        expected_stacks(&input, false)
+    );
+    assert_eq!(
+        collapse(&profile, SampleIndex::Name("samples_before".to_string())).unwrap(),
+        expected_stacks(&input, true)
+    );
+}
+
+#[test]
+fn to_pprof_cli() {
+    let input_file = "./tests/data/to-pprof/stacks.txt";
+    let output = Command::cargo_bin("inferno-to-pprof")
+        .unwrap()
+        .arg("--sample-type")
+        .arg("cpu")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    assert!(output.status.success());
+
+    let input = fs::read_to_string(input_file).unwrap();
+    assert_eq!(
+        collapse(&output.stdout, SampleIndex::Name("cpu".to_string())).unwrap(),
+        expected_stacks(&input, false)
+    );
+}
@@ -0,0 +1,97 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+use std::process::Command;
+
+use assert_cmd::cargo::CommandCargoExt;
+use inferno::collapse::speedscope::{Folder, Options as CollapseOptions};
+use inferno::collapse::Collapse;
+use inferno::export::speedscope::{self, Options, Unit};
+use inferno::folded::{Count, FoldedStacks};
+use pretty_assertions::assert_eq;
+use serde_json::Value;
+
+fn test_to_speedscope(input_file: &str, expected_file: &str, options: Options) -> io::Result<()> {
+    let input = vec![PathBuf::from(input_file)];
+    if fs::metadata(expected_file).is_err() || std::env::var("INFERNO_BLESS_TESTS").is_ok() {
+        // be nice to the dev and make the file
+        let f = File::create(expected_file).unwrap();
+        speedscope::from_files(&options, &input, f)?;
+    }
+
+    let mut result = Vec::new();
+    speedscope::from_files(&options, &input, &mut result)?;
+    compare_results(&result, expected_file);
+    Ok(())
+}
+
+fn compare_results(result: &[u8], expected_file: &str) {
+    let result: Value = serde_json::from_slice(result).unwrap();
+    let expected: Value =
+        serde_json::from_reader(BufReader::new(File::open(expected_file).unwrap())).unwrap();
+    assert_eq!(result, expected, "\n{}", expected_file);
+}
+
+#[test]
+fn to_speedscope_default() {
+    let input_file = "./tests/data/to-speedscope/stacks.txt";
+    let expected_file = "./tests/data/to-speedscope/results/stacks.json";
+    test_to_speedscope(input_file, expected_file, Options::default()).unwrap();
+}
+
+#[test]
+fn to_speedscope_flame_chart() {
+    let input_file = "./tests/data/to-speedscope/stacks.txt";
+    let expected_file = "./tests/data/to-speedscope/results/stacks-flamechart.json";
+    let mut options = Options::default();
+    options.flame_chart = true;
+    test_to_speedscope(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn to_speedscope_differential() {
+    let input_file = "./tests/data/to-speedscope/diff.txt";
+    let expected_file = "./tests/data/to-speedscope/results/diff.json";
+    let mut options = Options::default();
+    options.name = Some("diff".to_string());
+    options.unit = Unit::Milliseconds;
+    test_to_speedscope(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn to_speedscope_should_round_trip() {
+    let input = fs::read_to_string("./tests/data/to-speedscope/stacks.txt").unwrap();
+
+    let mut exported = Vec::new();
+    speedscope::from_reader(&Options::default(), input.as_bytes(), &mut exported).unwrap();
+    let mut collapsed = Vec::new();
+    Folder::from(CollapseOptions::default())
+        .collapse(&exported[..], &mut collapsed)
+        .unwrap();
+
+    let expected = FoldedStacks::from_str_lossy(&input).merge();
+    let collapsed = FoldedStacks::from_str_lossy(std::str::from_utf8(&collapsed).unwrap()).merge();
+    assert_eq!(
+        collapsed.to_string(),
+        expected.filter(|s| s.count > Count::ZERO).to_string()
+    );
+}
+
+#[test]
+fn to_speedscope_cli() {
+    let input_file = "./tests/data/to-speedscope/stacks.txt";
+    let expected_file = "./tests/data/to-speedscope/results/stacks-flamechart-cli.json";
+
+    let output = Command::cargo_bin("inferno-to-speedscope")
+        .unwrap()
+        .arg("--flamechart")
+        .arg("--unit")
+        .arg("microseconds")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    if fs::metadata(expected_file).is_err() {
+        fs::write(expected_file, &output.stdout).unwrap();
+    }
+    compare_results(&output.stdout, expected_file);
+}
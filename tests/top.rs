@@ -0,0 +1,118 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use assert_cmd::cargo::CommandCargoExt;
+use inferno::folded::{Count, FoldedStacks};
+use inferno::report::{self, Options, ReportFormat, SortBy};
+use pretty_assertions::assert_eq;
+
+fn test_top(input_file: &str, expected_file: &str, options: Options) -> io::Result<()> {
+    let input = vec![PathBuf::from(input_file)];
+    if fs::metadata(expected_file).is_err() || std::env::var("INFERNO_BLESS_TESTS").is_ok() {
+        // be nice to the dev and make the file
+        let f = File::create(expected_file).unwrap();
+        report::from_files(&options, &input, f)?;
+    }
+
+    let mut result = Vec::new();
+    report::from_files(&options, &input, &mut result)?;
+    let expected = fs::read_to_string(expected_file).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&result),
+        expected,
+        "\n{}",
+        expected_file
+    );
+    Ok(())
+}
+
+#[test]
+fn top_table() {
+    let input_file = "./tests/data/top/stacks.txt";
+    let expected_file = "./tests/data/top/results/stacks.txt";
+    test_top(input_file, expected_file, Options::default()).unwrap();
+}
+
+#[test]
+fn top_table_by_total_with_limit() {
+    let input_file = "./tests/data/top/stacks.txt";
+    let expected_file = "./tests/data/top/results/stacks-total-3.txt";
+    let mut options = Options::default();
+    options.sort_by = SortBy::Inclusive;
+    options.limit = Some(3);
+    test_top(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn top_csv() {
+    let input_file = "./tests/data/top/stacks.txt";
+    let expected_file = "./tests/data/top/results/stacks.csv";
+    let mut options = Options::default();
+    options.format = ReportFormat::Csv;
+    test_top(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn top_json() {
+    let input_file = "./tests/data/top/stacks.txt";
+    let expected_file = "./tests/data/top/results/stacks.json";
+    let mut options = Options::default();
+    options.format = ReportFormat::Json;
+    test_top(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn top_differential() {
+    let input_file = "./tests/data/top/diff.txt";
+    let expected_file = "./tests/data/top/results/diff.txt";
+    let mut options = Options::default();
+    options.count_name = "ms".to_string();
+    test_top(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn top_differential_csv() {
+    let input_file = "./tests/data/top/diff.txt";
+    let expected_file = "./tests/data/top/results/diff.csv";
+    let mut options = Options::default();
+    options.format = ReportFormat::Csv;
+    options.sort_by = SortBy::Inclusive;
+    test_top(input_file, expected_file, options).unwrap();
+}
+
+#[test]
+fn top_should_count_recursion_once() {
+    let input = fs::read_to_string("./tests/data/top/stacks.txt").unwrap();
+    let stacks = FoldedStacks::from_str_lossy(&input);
+    let counts = report::function_counts(&stacks);
+    let parse = counts.iter().find(|c| c.function == "parse").unwrap();
+    assert_eq!(parse.self_count, Count::from(400u64));
+    assert_eq!(parse.total, Count::from(7600u64));
+    let main = counts.iter().find(|c| c.function == "main").unwrap();
+    assert_eq!(main.total, stacks.total() - Count::from(1000u64));
+}
+
+#[test]
+fn top_cli() {
+    let input_file = "./tests/data/top/stacks.txt";
+    let expected_file = "./tests/data/top/results/stacks-cli.csv";
+
+    let output = Command::cargo_bin("inferno-top")
+        .unwrap()
+        .arg("--sort")
+        .arg("total")
+        .arg("-n")
+        .arg("0")
+        .arg("--format")
+        .arg("csv")
+        .arg(input_file)
+        .output()
+        .expect("failed to execute process");
+    if fs::metadata(expected_file).is_err() {
+        fs::write(expected_file, &output.stdout).unwrap();
+    }
+    let expected = fs::read_to_string(expected_file).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}